@@ -1,10 +1,10 @@
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::{error, fmt, io};
 use std::fs::File;
 use std::io::{BufReader, BufRead, BufWriter, Write};
 use std::num::ParseIntError;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::collections::hash_map::Entry;
 
 use regex::Regex;
@@ -58,8 +58,9 @@ impl error::Error for AssemblerError {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
-enum OpCode {
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OpCode {
     MOV = 0x00,
     LD = 0x01,
     LDI = 0x11,
@@ -83,8 +84,37 @@ enum OpCode {
     JMP = 0x0C,
 }
 
-#[derive(Copy, Clone, Debug)]
-enum Register {
+impl OpCode {
+    pub fn from_code(code: u16) -> Option<OpCode> {
+        match code {
+            0x00 => Some(OpCode::MOV),
+            0x01 => Some(OpCode::LD),
+            0x11 => Some(OpCode::LDI),
+            0x02 => Some(OpCode::ST),
+            0x03 => Some(OpCode::AND),
+            0x13 => Some(OpCode::ANDI),
+            0x04 => Some(OpCode::OR),
+            0x14 => Some(OpCode::ORI),
+            0x05 => Some(OpCode::XOR),
+            0x15 => Some(OpCode::XORI),
+            0x06 => Some(OpCode::NOT),
+            0x07 => Some(OpCode::ADD),
+            0x17 => Some(OpCode::ADDI),
+            0x08 => Some(OpCode::SUB),
+            0x09 => Some(OpCode::SL),
+            0x19 => Some(OpCode::SLI),
+            0x0A => Some(OpCode::SR),
+            0x1A => Some(OpCode::SRI),
+            0x0B => Some(OpCode::CMP),
+            0x1B => Some(OpCode::CMPI),
+            0x0C => Some(OpCode::JMP),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Register {
     A = 0x0,
     B = 0x1,
     Addr = 0x2,
@@ -94,7 +124,20 @@ enum Register {
 }
 
 impl Register {
-    fn from_str(reg_str: &str) -> Option<Register> {
+    pub fn from_code(code: u16) -> Option<Register> {
+        match code {
+            0x0 => Some(Register::A),
+            0x1 => Some(Register::B),
+            0x2 => Some(Register::Addr),
+            0x3 => Some(Register::SP),
+            0x4 => Some(Register::SR),
+            0x5 => Some(Register::PC),
+            _ => None,
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(reg_str: &str) -> Option<Register> {
         let reg_str = reg_str.to_lowercase();
         match reg_str.as_str() {
             "a" => Some(Register::A),
@@ -108,8 +151,8 @@ impl Register {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
-enum Condition {
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Condition {
     None = 0x0,
     Zero = 0x1,
     Equal = 0x2,
@@ -120,6 +163,22 @@ enum Condition {
     GreaterThanOrEqual = 0x7,
 }
 
+impl Condition {
+    pub fn from_code(code: u16) -> Option<Condition> {
+        match code {
+            0x0 => Some(Condition::None),
+            0x1 => Some(Condition::Zero),
+            0x2 => Some(Condition::Equal),
+            0x3 => Some(Condition::NotEqual),
+            0x4 => Some(Condition::LessThan),
+            0x5 => Some(Condition::LessThanOrEqual),
+            0x6 => Some(Condition::GreaterThan),
+            0x7 => Some(Condition::GreaterThanOrEqual),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 enum InstructionData {
     None,
@@ -144,14 +203,22 @@ impl InstructionData {
 
 #[derive(Clone, Debug)]
 struct Instruction {
-    opcode: OpCode,
+    // `None` for words emitted by `.word`
+    opcode: Option<OpCode>,
     data: InstructionData,
 }
 
 impl Instruction {
     fn new(opcode: OpCode, data: InstructionData) -> Instruction {
         Instruction {
-            opcode,
+            opcode: Some(opcode),
+            data,
+        }
+    }
+
+    fn data(data: InstructionData) -> Instruction {
+        Instruction {
+            opcode: None,
             data,
         }
     }
@@ -161,10 +228,14 @@ impl Instruction {
     }
 
     fn encode(&self) -> u16 {
-        (self.opcode as u16) << 11 | (self.data.encode() & 0x7ff)
+        match self.opcode {
+            Some(opcode) => (opcode as u16) << 11 | (self.data.encode() & 0x7ff),
+            None => self.data.encode(),
+        }
     }
 }
 
+#[allow(clippy::manual_strip, clippy::from_str_radix_10)]
 fn parse_u16(text: &str) -> Result<u16, ParseIntError> {
     if text.starts_with("0x") {
         u16::from_str_radix(&text["0x".len()..], 16)
@@ -177,13 +248,60 @@ fn parse_u16(text: &str) -> Result<u16, ParseIntError> {
     }
 }
 
-pub fn run(source_path: PathBuf, output_path: PathBuf) -> Result<(), AssemblerError> {
+/// An assembled program together with the addresses of its labels
+#[derive(Clone, Debug)]
+pub struct Program {
+    pub words: Vec<u16>,
+    pub labels: HashMap<String, u16>,
+    /// Source line (starting at 1) that each word was assembled from
+    pub lines: Vec<usize>,
+    /// Addresses of the words emitted by `.word`
+    pub data: HashSet<u16>,
+}
+
+impl Program {
+    pub fn line_of(&self, address: u16) -> Option<usize> {
+        self.lines.get(address as usize).copied()
+    }
+
+    /// Returns the address of the first word assembled from `line` or, if the line doesn't contain an
+    /// instruction, from the next line that does
+    pub fn address_of_line(&self, line: usize) -> Option<u16> {
+        self.lines.iter()
+            .enumerate()
+            .filter(|(_, &word_line)| word_line >= line)
+            .min_by_key(|(_, &word_line)| word_line)
+            .map(|(address, _)| address as u16)
+    }
+
+    pub fn is_data(&self, address: u16) -> bool {
+        self.data.contains(&address)
+    }
+
+    pub fn label_at(&self, address: u16) -> Option<&str> {
+        self.labels.iter()
+            .filter(|(_, &label_address)| label_address == address)
+            .map(|(label, _)| label.as_str())
+            .min()
+    }
+}
+
+pub fn assemble(source: &str) -> Result<Program, AssemblerError> {
+    assemble_reader(source.as_bytes(), Path::new("<source>"))
+}
+
+pub fn assemble_file(source_path: PathBuf) -> Result<Program, AssemblerError> {
     let source_file = File::open(&source_path)
         .map_err(|err| AssemblerError::FileOpen(source_path.clone(), err))?;
 
-    let source_reader = BufReader::new(source_file);
+    assemble_reader(BufReader::new(source_file), &source_path)
+}
 
+#[allow(clippy::manual_strip, clippy::redundant_closure, clippy::unnecessary_lazy_evaluations, clippy::single_match)]
+fn assemble_reader<R: BufRead>(source_reader: R, source_path: &Path) -> Result<Program, AssemblerError> {
     let mut instructions = Vec::new();
+    let mut lines = Vec::new();
+    let mut data = HashSet::new();
 
     let mut labels = HashMap::new();
 
@@ -212,7 +330,7 @@ pub fn run(source_path: PathBuf, output_path: PathBuf) -> Result<(), AssemblerEr
 
     for (line_number, line) in source_reader.lines().enumerate() {
         let line = line
-            .map_err(|err| AssemblerError::FileRead(source_path.clone(), err))?;
+            .map_err(|err| AssemblerError::FileRead(source_path.to_path_buf(), err))?;
 
         let m = re.captures(&line);
 
@@ -237,6 +355,28 @@ pub fn run(source_path: PathBuf, output_path: PathBuf) -> Result<(), AssemblerEr
             if let Some(instruction) = instruction {
                 let instruction = instruction.to_lowercase();
                 let instr = match instruction.as_str() {
+                    ".word" => {
+                        let value_str = operand1
+                            .ok_or_else(|| AssemblerError::MissingOperand(line_number, "value".to_string()))?;
+                        if operand2.is_some() {
+                            return Err(AssemblerError::TooManyOperands(line_number));
+                        }
+
+                        let first_char = value_str.chars().next().unwrap();
+
+                        let value = if first_char.is_alphabetic() {
+                            // Label
+                            InstructionData::Immediate1Reference(value_str.to_string())
+                        } else {
+                            // Constant
+                            let value = parse_u16(value_str)
+                                .map_err(|err| AssemblerError::InvalidIntegerLiteral(line_number, value_str.to_string(), err))?;
+                            InstructionData::Immediate1(value)
+                        };
+
+                        data.insert(current_address);
+                        Instruction::data(value)
+                    },
                     "mov" => {
                         let target_str = operand1
                             .ok_or_else(|| AssemblerError::MissingOperand(line_number, "target register".to_string()))?;
@@ -478,6 +618,7 @@ pub fn run(source_path: PathBuf, output_path: PathBuf) -> Result<(), AssemblerEr
                     .ok_or_else(|| AssemblerError::AddressSpaceExhausted())?;
 
                 instructions.push(instr);
+                lines.push(line_number + 1);
             }
         } else {
             return Err(AssemblerError::Syntax(line_number, line));
@@ -496,14 +637,24 @@ pub fn run(source_path: PathBuf, output_path: PathBuf) -> Result<(), AssemblerEr
         }
     }
 
+    Ok(Program {
+        words: instructions.iter().map(Instruction::encode).collect(),
+        labels,
+        lines,
+        data,
+    })
+}
+
+pub fn run(source_path: PathBuf, output_path: PathBuf) -> Result<(), AssemblerError> {
+    let program = assemble_file(source_path)?;
+
     let output_file = File::create(&output_path)
         .map_err(|err| AssemblerError::FileOpen(output_path.clone(), err))?;
 
     let mut output_writer = BufWriter::new(output_file);
 
-    for instruction in &instructions {
-        let encoded = instruction.encode();
-        let encoded_bytes = encoded.to_le_bytes();
+    for word in &program.words {
+        let encoded_bytes = word.to_le_bytes();
         output_writer.write_all(&encoded_bytes)
             .map_err(|err| AssemblerError::FileWrite(output_path.clone(), err))?;
     }
@@ -0,0 +1,68 @@
+
+use std::{env, process};
+use std::path::Path;
+use std::error::Error;
+
+use circuit::CircuitFile;
+
+fn print_usage(exec_path: &str) {
+    eprint!("Usage: ");
+    let exec_path = Path::new(exec_path);
+    if let Some(exec_name) = exec_path.file_name() {
+        eprint!("{}", exec_name.to_string_lossy());
+    } else {
+        eprint!("{}", exec_path.display());
+    }
+    eprintln!(" FILE");
+    eprintln!();
+    eprintln!("Validates a LogicSimulator circuit file and lists its circuits and components.");
+}
+
+fn run(path: &Path) -> Result<(), Box<dyn Error>> {
+    let file = CircuitFile::load(path)?;
+
+    println!("Version {}, {} circuits", file.version, file.circuits.len());
+    for circuit in &file.circuits {
+        println!();
+        println!("{} ({} components, {} connections)", circuit.name, circuit.components.len(), circuit.connections.len());
+
+        let mut counts: Vec<(&str, usize)> = Vec::new();
+        for component in &circuit.components {
+            let type_name = component.kind.type_name();
+            match counts.iter_mut().find(|(name, _)| *name == type_name) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((type_name, 1)),
+            }
+        }
+        for (type_name, count) in counts {
+            println!("  {:<14} {}", type_name, count);
+        }
+
+        let subcircuits = circuit.subcircuits();
+        if !subcircuits.is_empty() {
+            println!("  uses {}", subcircuits.join(", "));
+        }
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let args = env::args().collect::<Vec<_>>();
+
+    if args.len() != 2 {
+        print_usage(&args[0]);
+        process::exit(1);
+    }
+
+    if let Err(err) = run(Path::new(&args[1])) {
+        eprintln!("error: {}", err);
+
+        let mut err: &dyn Error = &*err;
+        while let Some(source) = err.source() {
+            eprintln!("reason: {}", source);
+            err = source;
+        }
+        process::exit(1);
+    }
+}
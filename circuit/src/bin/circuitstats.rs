@@ -0,0 +1,155 @@
+
+use std::{env, process};
+use std::path::{Path, PathBuf};
+use std::error::Error;
+
+use circuit::{CircuitFile, Resources, instance_counts};
+
+struct Options {
+    path: PathBuf,
+    circuit_name: Option<String>,
+    csv: bool,
+}
+
+fn print_usage(exec_path: &str) {
+    eprint!("Usage: ");
+    let exec_path = Path::new(exec_path);
+    if let Some(exec_name) = exec_path.file_name() {
+        eprint!("{}", exec_name.to_string_lossy());
+    } else {
+        eprint!("{}", exec_path.display());
+    }
+    eprintln!(" [--csv] FILE [CIRCUIT]");
+    eprintln!();
+    eprintln!("Counts the gates, register bits, memories and connections of CIRCUIT (the first circuit by default) and of");
+    eprintln!("every block it contains. --csv prints one line per block and resource instead, for comparing versions.");
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut paths = Vec::new();
+    let mut csv = false;
+
+    for arg in args {
+        match arg.as_str() {
+            "--csv" => csv = true,
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ if paths.len() < 2 => paths.push(arg.clone()),
+            _ => return Err(format!("unexpected argument \"{}\"", arg)),
+        }
+    }
+
+    let mut paths = paths.into_iter();
+    Ok(Options {
+        path: PathBuf::from(paths.next().ok_or("missing circuit file")?),
+        circuit_name: paths.next(),
+        csv,
+    })
+}
+
+fn print_error(err: &dyn Error) {
+    eprintln!("error: {}", err);
+
+    let mut err = err;
+    while let Some(source) = err.source() {
+        eprintln!("reason: {}", source);
+        err = source;
+    }
+}
+
+fn gate_name(kind: &str, inputs: u32) -> String {
+    if kind == "not" {
+        kind.to_string()
+    } else {
+        format!("{}{}", kind, inputs)
+    }
+}
+
+// Name and value of every resource, for the CSV output
+fn resource_values(resources: &Resources) -> Vec<(String, u64)> {
+    let mut values = vec![("gates".to_string(), resources.total_gates())];
+    for gate in &resources.gates {
+        values.push((gate_name(gate.kind, gate.inputs), gate.count));
+    }
+    values.push(("registers".to_string(), resources.registers));
+    values.push(("register_bits".to_string(), resources.register_bits));
+    values.push(("rom_bits".to_string(), resources.memory_bits("rom")));
+    values.push(("sram_bits".to_string(), resources.memory_bits("sram")));
+    values.push(("components".to_string(), resources.components));
+    values.push(("connections".to_string(), resources.connections));
+    values
+}
+
+fn run(options: Options) -> Result<(), Box<dyn Error>> {
+    let file = CircuitFile::load(&options.path)?;
+    let circuit = match &options.circuit_name {
+        Some(name) => file.circuit(name).ok_or_else(|| format!("no circuit named \"{}\"", name))?,
+        None => file.main_circuit().ok_or("the circuit file is empty")?,
+    };
+
+    let blocks = instance_counts(&file, circuit).into_iter()
+        .map(|(circuit, instances)| (circuit, instances, Resources::of(circuit), Resources::total(&file, circuit)))
+        .collect::<Vec<_>>();
+
+    if options.csv {
+        println!("block,instances,resource,own,total");
+        for (circuit, instances, own, total) in &blocks {
+            let own = resource_values(own);
+            for (name, total) in resource_values(total) {
+                let own = own.iter().find(|(own_name, _)| *own_name == name).map_or(0, |&(_, own)| own);
+                println!("{},{},{},{},{}", circuit.name, instances, name, own, total);
+            }
+        }
+        return Ok(());
+    }
+
+    println!("Per instance, including the blocks inside:");
+    println!();
+    println!("  {:<24} {:>9} {:>7} {:>6} {:>6} {:>6} {:>9} {:>9} {:>9} {:>11}",
+             "Block", "Instances", "Gates", "and", "or", "not", "Reg bits", "ROM bits", "SRAM bits", "Connections");
+    for (circuit, instances, _, total) in &blocks {
+        println!("  {:<24} {:>9} {:>7} {:>6} {:>6} {:>6} {:>9} {:>9} {:>9} {:>11}",
+                 circuit.name, instances, total.total_gates(), total.gate_count("and"), total.gate_count("or"),
+                 total.gate_count("not"), total.register_bits, total.memory_bits("rom"), total.memory_bits("sram"),
+                 total.connections);
+    }
+
+    for (circuit, instances, own, total) in &blocks {
+        println!();
+        let plural = if *instances == 1 { "" } else { "s" };
+        println!("{} ({} instance{})", circuit.name, instances, plural);
+
+        let subcircuits = circuit.subcircuits();
+        if !subcircuits.is_empty() {
+            println!("  uses {}", subcircuits.join(", "));
+        }
+
+        println!("  {:<16} {:>8} {:>8}", "", "Own", "Total");
+        for (name, total) in resource_values(total) {
+            let own = resource_values(own).into_iter().find(|(own_name, _)| *own_name == name).map_or(0, |(_, own)| own);
+            println!("  {:<16} {:>8} {:>8}", name, own, total);
+        }
+        for memory in &total.memories {
+            println!("  {:<16} {} x {}x{}", memory.kind, memory.count, 1u64 << memory.address_width, memory.word_size);
+        }
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let args = env::args().collect::<Vec<_>>();
+
+    let options = match parse_args(&args[1..]) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            print_usage(&args[0]);
+            process::exit(1);
+        },
+    };
+
+    if let Err(err) = run(options) {
+        print_error(&*err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,82 @@
+
+use std::{env, process};
+use std::path::Path;
+use std::error::Error;
+
+use circuit::{CircuitFile, Netlist, NetlistIssue};
+
+fn print_usage(exec_path: &str) {
+    eprint!("Usage: ");
+    let exec_path = Path::new(exec_path);
+    if let Some(exec_name) = exec_path.file_name() {
+        eprint!("{}", exec_name.to_string_lossy());
+    } else {
+        eprint!("{}", exec_path.display());
+    }
+    eprintln!(" FILE [CIRCUIT]");
+    eprintln!();
+    eprintln!("Extracts the nets of a circuit from its wires and reports dangling wires, shorts and undriven nets.");
+    eprintln!("CIRCUIT defaults to the main circuit.");
+}
+
+fn run(path: &Path, circuit_name: Option<&str>) -> Result<bool, Box<dyn Error>> {
+    let file = CircuitFile::load(path)?;
+    let circuit = match circuit_name {
+        Some(name) => file.circuit(name).ok_or_else(|| format!("no circuit named {}", name))?,
+        None => file.main_circuit().ok_or("the file contains no circuits")?,
+    };
+
+    let netlist = Netlist::extract(&file, circuit)?;
+
+    let pin_name = |pin_ref: &circuit::PinRef| {
+        let component = &circuit.components[pin_ref.component];
+        format!("{}{}.{}", component.kind.type_name(), pin_ref.component, netlist.pin(*pin_ref).name)
+    };
+
+    println!("{}: {} nets", circuit.name, netlist.nets.len());
+    for net in &netlist.nets {
+        let pins = net.pins.iter().map(pin_name).collect::<Vec<_>>();
+        println!("  {}: {}", net.name, pins.join(" "));
+    }
+
+    if !netlist.issues.is_empty() {
+        println!();
+        println!("Issues:");
+        for issue in &netlist.issues {
+            match issue {
+                NetlistIssue::Short { pins, .. } => {
+                    let pins = pins.iter().map(pin_name).collect::<Vec<_>>();
+                    println!("  {}: {}", issue, pins.join(" "));
+                },
+                NetlistIssue::Undriven(net) => println!("  net {} has no driver", netlist.nets[*net].name),
+                _ => println!("  {}", issue),
+            }
+        }
+    }
+
+    Ok(netlist.issues.is_empty())
+}
+
+fn main() {
+    let args = env::args().collect::<Vec<_>>();
+
+    if args.len() != 2 && args.len() != 3 {
+        print_usage(&args[0]);
+        process::exit(1);
+    }
+
+    match run(Path::new(&args[1]), args.get(2).map(String::as_str)) {
+        Ok(true) => {},
+        Ok(false) => process::exit(2),
+        Err(err) => {
+            eprintln!("error: {}", err);
+
+            let mut err: &dyn Error = &*err;
+            while let Some(source) = err.source() {
+                eprintln!("reason: {}", source);
+                err = source;
+            }
+            process::exit(1);
+        },
+    }
+}
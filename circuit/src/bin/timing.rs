@@ -0,0 +1,182 @@
+
+use std::{env, process};
+use std::path::{Path, PathBuf};
+use std::error::Error;
+
+use circuit::{CircuitFile, ComponentRef, Edge, Simulation, TimingAnalysis, TimingPath};
+
+const DEFAULT_PATHS: usize = 10;
+const DEFAULT_DEPTH: usize = 2;
+
+struct Options {
+    path: PathBuf,
+    circuit_name: Option<String>,
+    paths: usize,
+    depth: usize,
+}
+
+fn print_usage(exec_path: &str) {
+    eprint!("Usage: ");
+    let exec_path = Path::new(exec_path);
+    if let Some(exec_name) = exec_path.file_name() {
+        eprint!("{}", exec_name.to_string_lossy());
+    } else {
+        eprint!("{}", exec_path.display());
+    }
+    eprintln!(" [OPTIONS] FILE [CIRCUIT]");
+    eprintln!();
+    eprintln!("Finds the longest combinational paths between the registers of CIRCUIT (the first circuit by default) in");
+    eprintln!("gate delays, the shortest clock period they allow and the blocks they pass through. Exits with status 2 if");
+    eprintln!("the clock of the circuit is faster than that.");
+    eprintln!();
+    eprintln!("Options:");
+    eprintln!("    --paths N             list the N longest paths (default {})", DEFAULT_PATHS);
+    eprintln!("    --depth N             name blocks down to N levels below CIRCUIT (default {})", DEFAULT_DEPTH);
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut paths = Vec::new();
+    let mut options = Options {
+        path: PathBuf::new(),
+        circuit_name: None,
+        paths: DEFAULT_PATHS,
+        depth: DEFAULT_DEPTH,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next()
+            .ok_or_else(|| format!("missing value for {}", name))
+            .and_then(|text| text.parse::<usize>().map_err(|_| format!("invalid value \"{}\" for {}", text, name)));
+
+        match arg.as_str() {
+            "--paths" => options.paths = value(arg)?,
+            "--depth" => options.depth = value(arg)?,
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ if paths.len() < 2 => paths.push(arg.clone()),
+            _ => return Err(format!("unexpected argument \"{}\"", arg)),
+        }
+    }
+
+    let mut paths = paths.into_iter();
+    options.path = PathBuf::from(paths.next().ok_or("missing circuit file")?);
+    options.circuit_name = paths.next();
+
+    Ok(options)
+}
+
+fn print_error(err: &dyn Error) {
+    eprintln!("error: {}", err);
+
+    let mut err = err;
+    while let Some(source) = err.source() {
+        eprintln!("reason: {}", source);
+        err = source;
+    }
+}
+
+fn edge_name(edge: Edge) -> &'static str {
+    match edge {
+        Edge::Rising => "rising",
+        Edge::Falling => "falling",
+    }
+}
+
+// Path of a component like `main.CPU0.ALU9.and12`
+fn component_name(simulation: &Simulation, file: &CircuitFile, component: ComponentRef) -> String {
+    let instance = &simulation.instances()[component.instance];
+    let type_name = file.circuit(&instance.circuit)
+        .and_then(|circuit| circuit.components.get(component.component))
+        .map_or("component", |component| component.kind.type_name());
+    format!("{}.{}{}", instance.path, type_name, component.component)
+}
+
+// The block a component belongs to: the path of its instance, cut off `depth` levels below the top
+fn block_name(simulation: &Simulation, component: ComponentRef, depth: usize) -> String {
+    let path = &simulation.instances()[component.instance].path;
+    path.split('.').take(depth + 1).collect::<Vec<_>>().join(".")
+}
+
+// Consecutive components of a path grouped by block, with the number of delays in each
+fn path_blocks(simulation: &Simulation, path: &TimingPath, depth: usize) -> Vec<(String, u64)> {
+    let mut blocks: Vec<(String, u64)> = Vec::new();
+    for &component in &path.components {
+        let block = block_name(simulation, component, depth);
+        match blocks.last_mut() {
+            Some((last, delays)) if *last == block => *delays += 1,
+            _ => blocks.push((block, 1)),
+        }
+    }
+    blocks
+}
+
+fn run(options: Options) -> Result<bool, Box<dyn Error>> {
+    let file = CircuitFile::load(&options.path)?;
+    let circuit = match &options.circuit_name {
+        Some(name) => file.circuit(name).ok_or_else(|| format!("no circuit named \"{}\"", name))?,
+        None => file.main_circuit().ok_or("the circuit file is empty")?,
+    };
+    let simulation = Simulation::new(&file, circuit)?;
+    let analysis = TimingAnalysis::new(&simulation)?;
+
+    let worst = match analysis.paths.first() {
+        Some(path) => path,
+        None => {
+            println!("No clocked paths");
+            return Ok(true);
+        },
+    };
+
+    let min_period = analysis.min_period();
+    match simulation.clock_period() {
+        Some(period) => println!("Clock period {}, shortest possible period {}", period, min_period),
+        None => println!("Shortest possible clock period {}", min_period),
+    }
+    let blocks = path_blocks(&simulation, worst, options.depth);
+    if let Some((block, delays)) = blocks.iter().max_by_key(|(_, delays)| *delays) {
+        println!("Limited by {}, {} of the {} delays of the longest path", block, delays, worst.components.len());
+    }
+
+    println!();
+    println!("Longest paths:");
+    for path in analysis.paths.iter().take(options.paths) {
+        let capture = match path.capture_edge {
+            Some(edge) => format!("{} ({})", component_name(&simulation, &file, path.capture), edge_name(edge)),
+            None => format!("{} (write)", component_name(&simulation, &file, path.capture)),
+        };
+        println!();
+        println!("  period {:>4}: {} ({}) -> {}", path.min_period, component_name(&simulation, &file, path.launch), edge_name(path.launch_edge), capture);
+        let blocks = path_blocks(&simulation, path, options.depth).into_iter()
+            .map(|(block, delays)| format!("{} ({})", block, delays))
+            .collect::<Vec<_>>();
+        println!("    {}", blocks.join(" -> "));
+        println!("    settles {} steps after the edge, the clock reaches the end after {}", path.arrival, path.capture_clock_delay);
+    }
+
+    Ok(simulation.clock_period().is_none_or(|period| period >= min_period))
+}
+
+fn main() {
+    let args = env::args().collect::<Vec<_>>();
+
+    let options = match parse_args(&args[1..]) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            print_usage(&args[0]);
+            process::exit(1);
+        },
+    };
+
+    match run(options) {
+        Ok(true) => {},
+        Ok(false) => {
+            eprintln!("error: the clock is faster than the longest path allows");
+            process::exit(2);
+        },
+        Err(err) => {
+            print_error(&*err);
+            process::exit(1);
+        },
+    }
+}
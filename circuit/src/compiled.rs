@@ -0,0 +1,512 @@
+
+use std::ops::Range;
+
+use crate::format::CircuitError;
+use crate::sim::{ComponentRef, ElementKind, Simulation};
+
+// Passes over the combinational logic per clock edge before a circuit counts as oscillating
+const MAX_PASSES: usize = 64;
+
+// Combinational operations, in topological order. Ranges index `CompiledSimulation::operands`, which holds nets.
+#[derive(Clone, Debug)]
+enum Operation {
+    And {
+        inputs: Range<usize>,
+        output: usize,
+    },
+    Or {
+        inputs: Range<usize>,
+        output: usize,
+    },
+    Not {
+        input: usize,
+        output: usize,
+    },
+    // Reads a ROM or SRAM
+    Read {
+        memory: usize,
+        enable: usize,
+        address: Range<usize>,
+        outputs: Range<usize>,
+    },
+}
+
+#[derive(Clone, Debug)]
+struct Memory {
+    // One block of `size` words per lane, only one block that all lanes share for a ROM
+    words: Vec<u64>,
+    size: usize,
+    shared: bool,
+}
+
+impl Memory {
+    fn word(&self, lane: usize, address: usize) -> u64 {
+        if address >= self.size {
+            return 0;
+        }
+        let block = if self.shared { 0 } else { lane };
+        self.words[block * self.size + address]
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Write {
+    memory: usize,
+    write_enable: usize,
+    address: Range<usize>,
+    data: Range<usize>,
+}
+
+#[derive(Clone, Debug)]
+struct Register {
+    clear: usize,
+    enable: usize,
+    clock: usize,
+    data: Range<usize>,
+    outputs: Range<usize>,
+    // Every bit of the register in all lanes
+    bits: Vec<u64>,
+    // Value of the clock input at the last pass, to find rising edges
+    last_clock: u64,
+}
+
+#[derive(Clone, Debug)]
+struct Clock {
+    output: usize,
+    half_period: u64,
+    value: u64,
+}
+
+// What an element of the event-driven simulation became
+#[derive(Copy, Clone, Debug)]
+enum Role {
+    None,
+    Register(usize),
+    Memory(usize),
+    Source(usize),
+}
+
+/// Levelized simulation of a LogicSimulator circuit, compiled from a `Simulation`.
+///
+/// Instead of reacting to every change after one gate delay, the combinational logic is sorted topologically and
+/// evaluated in one pass at every clock edge. Registers load the values D and EN had just before the edge. This
+/// matches the event-driven simulation as long as the circuit settles within every phase of the clock, and it
+/// doesn't report glitches or timing violations.
+///
+/// The circuit is simulated in up to 64 lanes at once, each net holds one bit per lane. Lanes are independent copies
+/// of the circuit that share ROM contents, they only differ in the state of their sources. Nets and components are
+/// numbered like in the `Simulation` the circuit was compiled from, the methods without a lane refer to lane 0. There
+/// is no unknown state, X in the `Simulation` becomes 0.
+#[derive(Clone, Debug)]
+pub struct CompiledSimulation {
+    lanes: usize,
+    lane_mask: u64,
+    operations: Vec<Operation>,
+    operands: Vec<usize>,
+    memories: Vec<Memory>,
+    writes: Vec<Write>,
+    registers: Vec<Register>,
+    clocks: Vec<Clock>,
+    // Output net and value of every source, constant 1 and top-level input
+    sources: Vec<(usize, u64)>,
+    roles: Vec<Role>,
+    component_elements: Vec<Vec<Option<usize>>>,
+    nets: Vec<u64>,
+    previous: Vec<u64>,
+    dirty: bool,
+    time: u64,
+}
+
+fn lane_mask(lanes: usize) -> u64 {
+    if lanes >= 64 { !0 } else { (1 << lanes) - 1 }
+}
+
+impl CompiledSimulation {
+    /// Compiles the circuit of `simulation` in its current state for `lanes` lanes (1 to 64), all of which start in
+    /// that state. Fails if the circuit contains a combinational loop.
+    pub fn new(simulation: &Simulation, lanes: usize) -> Result<CompiledSimulation, CircuitError> {
+        let lanes = lanes.clamp(1, 64);
+        let mask = lane_mask(lanes);
+        let all_lanes = |value: bool| if value { mask } else { 0 };
+
+        let elements = simulation.elements();
+        let slots = simulation.slots();
+        let slot_nets = |outputs: &Range<usize>| outputs.clone().map(|slot| slots[slot].net).collect::<Vec<_>>();
+
+        let mut compiled = CompiledSimulation {
+            lanes,
+            lane_mask: mask,
+            operations: Vec::new(),
+            operands: Vec::new(),
+            memories: Vec::new(),
+            writes: Vec::new(),
+            registers: Vec::new(),
+            clocks: Vec::new(),
+            sources: Vec::new(),
+            roles: vec![Role::None; elements.len()],
+            component_elements: simulation.instances().iter()
+                .enumerate()
+                .map(|(instance, _)| {
+                    let components = simulation.netlist(instance).pins.len();
+                    (0..components).map(|component| simulation.element(ComponentRef { instance, component })).collect()
+                })
+                .collect(),
+            nets: (0..simulation.net_count()).map(|net| all_lanes(simulation.net_value(net))).collect(),
+            previous: Vec::new(),
+            dirty: true,
+            time: simulation.time(),
+        };
+
+        for (index, element) in elements.iter().enumerate() {
+            let outputs = slot_nets(&element.outputs);
+            match &element.kind {
+                // Sorted below, the SRAM only sets up its write port here
+                ElementKind::And | ElementKind::Or | ElementKind::Not | ElementKind::Rom { .. } => {},
+                ElementKind::Sram { memory, .. } => {
+                    let size = memory.len();
+                    let memory_index = compiled.memories.len();
+                    compiled.memories.push(Memory {
+                        words: memory.iter().cycle().take(size * lanes).copied().collect(),
+                        size,
+                        shared: false,
+                    });
+                    compiled.roles[index] = Role::Memory(memory_index);
+
+                    let address_width = element.inputs.len() - 2 - outputs.len();
+                    let address = compiled.add_operands(&element.inputs[2..2 + address_width]);
+                    let data = compiled.add_operands(&element.inputs[2 + address_width..]);
+                    compiled.writes.push(Write {
+                        memory: memory_index,
+                        write_enable: element.inputs[1],
+                        address,
+                        data,
+                    });
+                },
+                ElementKind::Register { value, clock, .. } => {
+                    let data = compiled.add_operands(&element.inputs[3..]);
+                    let register_outputs = compiled.add_operands(&outputs);
+                    compiled.roles[index] = Role::Register(compiled.registers.len());
+                    compiled.registers.push(Register {
+                        clear: element.inputs[0],
+                        enable: element.inputs[1],
+                        clock: element.inputs[2],
+                        data,
+                        outputs: register_outputs,
+                        bits: (0..outputs.len()).map(|bit| all_lanes(bit < 64 && value & (1 << bit) != 0)).collect(),
+                        last_clock: all_lanes(*clock),
+                    });
+                },
+                ElementKind::Clock { half_period } => compiled.clocks.push(Clock {
+                    output: outputs[0],
+                    half_period: *half_period,
+                    value: all_lanes(slots[element.outputs.start].value),
+                }),
+                ElementKind::Const(value) => compiled.sources.push((outputs[0], all_lanes(*value))),
+                ElementKind::Source => {
+                    compiled.roles[index] = Role::Source(compiled.sources.len());
+                    compiled.sources.push((outputs[0], all_lanes(slots[element.outputs.start].value)));
+                },
+            }
+        }
+
+        for index in simulation.combinational_order()? {
+            let element = &elements[index];
+            let outputs = slot_nets(&element.outputs);
+            let operation = match &element.kind {
+                ElementKind::And => Operation::And {
+                    inputs: compiled.add_operands(&element.inputs),
+                    output: outputs[0],
+                },
+                ElementKind::Or => Operation::Or {
+                    inputs: compiled.add_operands(&element.inputs),
+                    output: outputs[0],
+                },
+                ElementKind::Not => Operation::Not {
+                    input: element.inputs[0],
+                    output: outputs[0],
+                },
+                ElementKind::Rom { words } => {
+                    let memory = compiled.memories.len();
+                    compiled.memories.push(Memory {
+                        words: words.iter().map(|&word| word as u64).collect(),
+                        size: words.len(),
+                        shared: true,
+                    });
+                    compiled.roles[index] = Role::Memory(memory);
+                    Operation::Read {
+                        memory,
+                        enable: element.inputs[0],
+                        address: compiled.add_operands(&element.inputs[1..]),
+                        outputs: compiled.add_operands(&outputs),
+                    }
+                },
+                ElementKind::Sram { .. } => {
+                    let memory = match compiled.roles[index] {
+                        Role::Memory(memory) => memory,
+                        _ => unreachable!(),
+                    };
+                    let address_width = element.inputs.len() - 2 - outputs.len();
+                    Operation::Read {
+                        memory,
+                        enable: element.inputs[0],
+                        address: compiled.add_operands(&element.inputs[2..2 + address_width]),
+                        outputs: compiled.add_operands(&outputs),
+                    }
+                },
+                _ => unreachable!(),
+            };
+            compiled.operations.push(operation);
+        }
+
+        compiled.previous = compiled.nets.clone();
+        Ok(compiled)
+    }
+
+    fn add_operands(&mut self, nets: &[usize]) -> Range<usize> {
+        let start = self.operands.len();
+        self.operands.extend_from_slice(nets);
+        start..self.operands.len()
+    }
+
+    pub fn lanes(&self) -> usize {
+        self.lanes
+    }
+
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    /// Period of the first clock of the top circuit
+    pub fn clock_period(&self) -> Option<u64> {
+        self.clocks.first().map(|clock| 2 * clock.half_period)
+    }
+
+    fn element(&self, component: ComponentRef) -> Option<usize> {
+        self.component_elements.get(component.instance)?.get(component.component).copied().flatten()
+    }
+
+    fn role(&self, component: ComponentRef) -> Role {
+        self.element(component).map_or(Role::None, |element| self.roles[element])
+    }
+
+    /// Value of a net in lane 0
+    pub fn net_value(&self, net: usize) -> bool {
+        self.nets[net] & 1 != 0
+    }
+
+    /// Value of a net in every lane, lane 0 is the lowest bit
+    pub fn net_lanes(&self, net: usize) -> u64 {
+        self.nets[net]
+    }
+
+    /// Sets a toggle button or an `input` of the top circuit in all lanes, returns `false` for any other component
+    pub fn set_source(&mut self, component: ComponentRef, value: bool) -> bool {
+        self.set_source_lanes(component, if value { !0 } else { 0 })
+    }
+
+    /// Sets a toggle button or an `input` of the top circuit, every bit of `lanes` is the value in one lane
+    pub fn set_source_lanes(&mut self, component: ComponentRef, lanes: u64) -> bool {
+        match self.role(component) {
+            Role::Source(source) => {
+                self.sources[source].1 = lanes & self.lane_mask;
+                self.dirty = true;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Replaces the contents of a ROM, fails if the component isn't a ROM or `words` don't fit into it
+    pub fn load_rom(&mut self, component: ComponentRef, words: &[u16]) -> Result<(), CircuitError> {
+        match self.role(component) {
+            Role::Memory(memory) if self.memories[memory].shared => {
+                let address_width = self.operations.iter()
+                    .find_map(|operation| match operation {
+                        Operation::Read { memory: read, address, .. } if *read == memory => Some(address.len()),
+                        _ => None,
+                    })
+                    .unwrap();
+                let capacity = 1 << address_width;
+                if words.len() > capacity {
+                    return Err(CircuitError::RomOverflow(words.len(), capacity));
+                }
+                self.memories[memory] = Memory {
+                    words: words.iter().map(|&word| word as u64).collect(),
+                    size: words.len(),
+                    shared: true,
+                };
+                self.dirty = true;
+                Ok(())
+            },
+            _ => Err(CircuitError::NotRom),
+        }
+    }
+
+    /// Current value of a register in lane 0
+    pub fn register(&self, component: ComponentRef) -> Option<u64> {
+        self.register_lane(component, 0)
+    }
+
+    pub fn register_lane(&self, component: ComponentRef, lane: usize) -> Option<u64> {
+        match self.role(component) {
+            Role::Register(register) => Some(self.registers[register].bits.iter()
+                .take(64)
+                .enumerate()
+                .fold(0, |value, (bit, &lanes)| value | (lanes >> lane & 1) << bit)),
+            _ => None,
+        }
+    }
+
+    /// Contents of an SRAM in lane 0
+    pub fn memory(&self, component: ComponentRef) -> Option<&[u64]> {
+        self.memory_lane(component, 0)
+    }
+
+    pub fn memory_lane(&self, component: ComponentRef, lane: usize) -> Option<&[u64]> {
+        match self.role(component) {
+            Role::Memory(memory) if !self.memories[memory].shared && lane < self.lanes => {
+                let Memory { words, size, .. } = &self.memories[memory];
+                Some(&words[lane * size..(lane + 1) * size])
+            },
+            _ => None,
+        }
+    }
+
+    // Address on a range of operands in one lane
+    fn gather(&self, operands: &Range<usize>, lane: usize) -> usize {
+        self.operands[operands.clone()].iter()
+            .enumerate()
+            .fold(0, |address, (bit, &net)| address | ((self.nets[net] >> lane & 1) as usize) << bit)
+    }
+
+    // Recomputes every net from the sources, clocks, registers and the combinational logic
+    fn evaluate(&mut self) {
+        for net in self.nets.iter_mut() {
+            *net = 0;
+        }
+        for &(net, value) in &self.sources {
+            self.nets[net] |= value;
+        }
+        for clock in &self.clocks {
+            self.nets[clock.output] |= clock.value;
+        }
+        for register in &self.registers {
+            for (&net, &bits) in self.operands[register.outputs.clone()].iter().zip(&register.bits) {
+                self.nets[net] |= bits;
+            }
+        }
+
+        for index in 0..self.operations.len() {
+            match &self.operations[index] {
+                Operation::And { inputs, output } => {
+                    let value = self.operands[inputs.clone()].iter().fold(self.lane_mask, |value, &net| value & self.nets[net]);
+                    self.nets[*output] |= value;
+                },
+                Operation::Or { inputs, output } => {
+                    let value = self.operands[inputs.clone()].iter().fold(0, |value, &net| value | self.nets[net]);
+                    self.nets[*output] |= value;
+                },
+                Operation::Not { input, output } => {
+                    self.nets[*output] |= !self.nets[*input] & self.lane_mask;
+                },
+                Operation::Read { memory, enable, address, outputs } => {
+                    let enable = self.nets[*enable];
+                    if enable == 0 {
+                        continue;
+                    }
+                    for lane in (0..self.lanes).filter(|&lane| enable >> lane & 1 != 0) {
+                        let word = self.memories[*memory].word(lane, self.gather(address, lane));
+                        for (bit, &net) in self.operands[outputs.clone()].iter().enumerate() {
+                            self.nets[net] |= (word >> bit & 1) << lane;
+                        }
+                    }
+                },
+            }
+        }
+    }
+
+    // Lets registers and SRAMs react to the nets, returns whether that changed any state
+    fn update(&mut self) -> bool {
+        let mut changed = false;
+
+        for register in &mut self.registers {
+            let clock = self.nets[register.clock];
+            let rising = clock & !register.last_clock;
+            register.last_clock = clock;
+
+            let clear = self.nets[register.clear];
+            let load = rising & self.previous[register.enable] & !clear;
+            if load == 0 && clear == 0 {
+                continue;
+            }
+            for (bits, &net) in register.bits.iter_mut().zip(&self.operands[register.data.clone()]) {
+                let value = (*bits & !load | self.previous[net] & load) & !clear;
+                changed |= value != *bits;
+                *bits = value;
+            }
+        }
+
+        for index in 0..self.writes.len() {
+            let write_enable = self.nets[self.writes[index].write_enable];
+            for lane in (0..self.lanes).filter(|&lane| write_enable >> lane & 1 != 0) {
+                let Write { memory, address, data, .. } = &self.writes[index];
+                let address = self.gather(address, lane);
+                let data = self.gather(data, lane) as u64;
+                let memory = &mut self.memories[*memory];
+                let word = &mut memory.words[lane * memory.size + address];
+                changed |= *word != data;
+                *word = data;
+            }
+        }
+
+        changed
+    }
+
+    // Evaluates until no register or memory changes anymore
+    fn settle(&mut self) {
+        for _ in 0..MAX_PASSES {
+            self.previous.copy_from_slice(&self.nets);
+            self.evaluate();
+            if !self.update() {
+                break;
+            }
+        }
+        self.dirty = false;
+    }
+
+    /// Advances the simulation by `steps` time steps, like `Simulation::run`. The logic is only evaluated at clock
+    /// edges and after sources or ROMs were changed.
+    pub fn run(&mut self, steps: u64) {
+        let end = self.time + steps;
+        while self.time < end {
+            let time = self.time;
+            let mut toggled = false;
+            for clock in &mut self.clocks {
+                if time.is_multiple_of(clock.half_period) {
+                    let value = if (time / clock.half_period).is_multiple_of(2) { self.lane_mask } else { 0 };
+                    toggled |= value != clock.value;
+                    clock.value = value;
+                }
+            }
+            if toggled || self.dirty {
+                self.settle();
+            }
+
+            let next_edge = self.clocks.iter()
+                .map(|clock| (time / clock.half_period + 1) * clock.half_period)
+                .min();
+            self.time = next_edge.map_or(end, |next_edge| next_edge.min(end));
+        }
+    }
+
+    /// Runs for a number of periods of the first clock, returns `false` if there is no clock
+    pub fn run_cycles(&mut self, cycles: u64) -> bool {
+        match self.clock_period() {
+            Some(period) => {
+                self.run(cycles * period);
+                true
+            },
+            None => false,
+        }
+    }
+}
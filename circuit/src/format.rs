@@ -0,0 +1,579 @@
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::{error, fmt};
+
+use serde_json::{Map, Value};
+
+// Memories are allocated in full when simulating, wider addresses don't fit
+const MAX_ADDRESS_WIDTH: u32 = 24;
+
+#[derive(Debug)]
+pub enum CircuitError {
+    FileRead(PathBuf, io::Error),
+    FileWrite(PathBuf, io::Error),
+    Json(serde_json::Error),
+    /// A missing or malformed field, given as a path like `circuits[1].components[4].inputs`
+    Invalid(String),
+    UnknownComponentType(String, String),
+    DuplicateCircuit(String),
+    UnknownCircuit(String, String),
+    MissingCircuit(String),
+    RecursiveCircuit(String),
+    InvalidRomContents(String),
+    /// Gates that feed back into themselves without a register, given as the path of one of them
+    CombinationalLoop(String),
+    NotRom,
+    /// More words than the address inputs of a ROM reach, and the number of words it can hold
+    RomOverflow(usize, usize),
+}
+
+impl fmt::Display for CircuitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            CircuitError::FileRead(path, _) => write!(f, "failed to read circuit {}", path.display()),
+            CircuitError::FileWrite(path, _) => write!(f, "failed to write circuit {}", path.display()),
+            CircuitError::Json(_) => write!(f, "circuit is not valid JSON"),
+            CircuitError::Invalid(path) => write!(f, "invalid or missing \"{}\"", path),
+            CircuitError::UnknownComponentType(path, name) => write!(f, "unknown component type \"{}\" at {}", name, path),
+            CircuitError::DuplicateCircuit(name) => write!(f, "circuit \"{}\" is defined more than once", name),
+            CircuitError::UnknownCircuit(circuit, name) => write!(f, "circuit \"{}\" uses undefined circuit \"{}\"", circuit, name),
+            CircuitError::MissingCircuit(name) => write!(f, "no circuit named \"{}\"", name),
+            CircuitError::RecursiveCircuit(name) => write!(f, "circuit \"{}\" contains itself", name),
+            CircuitError::InvalidRomContents(path) => write!(f, "invalid ROM contents at {}", path),
+            CircuitError::CombinationalLoop(path) => write!(f, "combinational loop through {}", path),
+            CircuitError::NotRom => write!(f, "component is not a ROM"),
+            CircuitError::RomOverflow(words, capacity) => write!(f, "{} words don't fit into a ROM of {} words", words, capacity),
+        }
+    }
+}
+
+impl error::Error for CircuitError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            CircuitError::FileRead(_, err) => Some(err),
+            CircuitError::FileWrite(_, err) => Some(err),
+            CircuitError::Json(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// A file in the format of LogicSimulator, as written by hdlc.
+///
+/// Fields that aren't modelled are kept in `extra` and written back unchanged, so a file that is read and written again
+/// stays the same.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CircuitFile {
+    pub version: u64,
+    pub circuits: Vec<Circuit>,
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Circuit {
+    pub name: String,
+    pub label: String,
+    pub components: Vec<Component>,
+    pub connections: Vec<Connection>,
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Component {
+    pub x: i64,
+    pub y: i64,
+    pub kind: ComponentKind,
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ComponentKind {
+    Rom {
+        address_width: u32,
+        word_size: u32,
+        contents: String,
+    },
+    Sram {
+        address_width: u32,
+        data_width: u32,
+    },
+    And {
+        inputs: u32,
+    },
+    Or {
+        inputs: u32,
+    },
+    Not,
+    Clock {
+        period: u64,
+    },
+    ToggleButton,
+    Bargraph {
+        size: u32,
+        off_color: Option<String>,
+        on_color: Option<String>,
+    },
+    Register {
+        width: u32,
+    },
+    Custom {
+        circuit_name: String,
+    },
+    Const {
+        value: bool,
+    },
+    Input {
+        label: String,
+    },
+    Output {
+        label: String,
+    },
+}
+
+/// A straight wire from (x1, y1) to (x2, y2)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Connection {
+    pub x1: i64,
+    pub y1: i64,
+    pub x2: i64,
+    pub y2: i64,
+    pub extra: Map<String, Value>,
+}
+
+// Wraps an object and records which fields were read, everything else ends up in `extra`
+struct Fields<'a> {
+    object: &'a Map<String, Value>,
+    path: String,
+    used: Vec<&'static str>,
+}
+
+impl<'a> Fields<'a> {
+    fn new(value: &'a Value, path: String) -> Result<Fields<'a>, CircuitError> {
+        let object = value.as_object().ok_or_else(|| CircuitError::Invalid(path.clone()))?;
+        Ok(Fields {
+            object,
+            path,
+            used: Vec::new(),
+        })
+    }
+
+    fn path(&self, name: &str) -> String {
+        if self.path.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}.{}", self.path, name)
+        }
+    }
+
+    fn optional(&mut self, name: &'static str) -> Option<&'a Value> {
+        self.used.push(name);
+        self.object.get(name)
+    }
+
+    fn get(&mut self, name: &'static str) -> Result<&'a Value, CircuitError> {
+        self.optional(name).ok_or_else(|| CircuitError::Invalid(self.path(name)))
+    }
+
+    fn integer(&mut self, name: &'static str) -> Result<i64, CircuitError> {
+        self.get(name)?.as_i64().ok_or_else(|| CircuitError::Invalid(self.path(name)))
+    }
+
+    fn number(&mut self, name: &'static str) -> Result<u64, CircuitError> {
+        self.get(name)?.as_u64().ok_or_else(|| CircuitError::Invalid(self.path(name)))
+    }
+
+    // A width or count, which has to be at least 1
+    fn size(&mut self, name: &'static str) -> Result<u32, CircuitError> {
+        let value = self.number(name)?;
+        if value == 0 || value > u32::MAX as u64 {
+            return Err(CircuitError::Invalid(self.path(name)));
+        }
+        Ok(value as u32)
+    }
+
+    fn address_width(&mut self, name: &'static str) -> Result<u32, CircuitError> {
+        let value = self.size(name)?;
+        if value > MAX_ADDRESS_WIDTH {
+            return Err(CircuitError::Invalid(self.path(name)));
+        }
+        Ok(value)
+    }
+
+    fn string(&mut self, name: &'static str) -> Result<String, CircuitError> {
+        self.get(name)?.as_str().map(str::to_string).ok_or_else(|| CircuitError::Invalid(self.path(name)))
+    }
+
+    fn optional_string(&mut self, name: &'static str) -> Result<Option<String>, CircuitError> {
+        match self.optional(name) {
+            Some(value) => value.as_str().map(|value| Some(value.to_string())).ok_or_else(|| CircuitError::Invalid(self.path(name))),
+            None => Ok(None),
+        }
+    }
+
+    fn array(&mut self, name: &'static str) -> Result<&'a Vec<Value>, CircuitError> {
+        self.get(name)?.as_array().ok_or_else(|| CircuitError::Invalid(self.path(name)))
+    }
+
+    fn extra(&self) -> Map<String, Value> {
+        self.object.iter()
+            .filter(|(name, _)| !self.used.contains(&name.as_str()))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect()
+    }
+}
+
+impl ComponentKind {
+    /// The `type` of the component in the file
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            ComponentKind::Rom { .. } => "rom",
+            ComponentKind::Sram { .. } => "sram",
+            ComponentKind::And { .. } => "and",
+            ComponentKind::Or { .. } => "or",
+            ComponentKind::Not => "not",
+            ComponentKind::Clock { .. } => "clock",
+            ComponentKind::ToggleButton => "togglebutton",
+            ComponentKind::Bargraph { .. } => "bargraph",
+            ComponentKind::Register { .. } => "register",
+            ComponentKind::Custom { .. } => "custom",
+            ComponentKind::Const { .. } => "const",
+            ComponentKind::Input { .. } => "input",
+            ComponentKind::Output { .. } => "output",
+        }
+    }
+}
+
+impl Component {
+    pub fn new(x: i64, y: i64, kind: ComponentKind) -> Component {
+        Component {
+            x,
+            y,
+            kind,
+            extra: Map::new(),
+        }
+    }
+
+    fn from_json(value: &Value, path: String) -> Result<Component, CircuitError> {
+        let mut fields = Fields::new(value, path)?;
+
+        let type_name = fields.string("type")?;
+        let x = fields.integer("x")?;
+        let y = fields.integer("y")?;
+
+        let kind = match type_name.as_str() {
+            "rom" => ComponentKind::Rom {
+                address_width: fields.address_width("addresswidth")?,
+                word_size: fields.size("wordsize")?,
+                contents: fields.string("contents")?,
+            },
+            "sram" => ComponentKind::Sram {
+                address_width: fields.address_width("addresswidth")?,
+                data_width: fields.size("datawidth")?,
+            },
+            "and" => ComponentKind::And {
+                inputs: fields.size("inputs")?,
+            },
+            "or" => ComponentKind::Or {
+                inputs: fields.size("inputs")?,
+            },
+            "not" => ComponentKind::Not,
+            "clock" => ComponentKind::Clock {
+                period: fields.number("period")?,
+            },
+            "togglebutton" => ComponentKind::ToggleButton,
+            "bargraph" => ComponentKind::Bargraph {
+                size: fields.size("size")?,
+                off_color: fields.optional_string("offColor")?,
+                on_color: fields.optional_string("onColor")?,
+            },
+            "register" => ComponentKind::Register {
+                width: fields.size("width")?,
+            },
+            "custom" => ComponentKind::Custom {
+                circuit_name: fields.string("circuitName")?,
+            },
+            "const" => ComponentKind::Const {
+                value: fields.get("value")?.as_bool().ok_or_else(|| CircuitError::Invalid(fields.path("value")))?,
+            },
+            "input" => ComponentKind::Input {
+                label: fields.string("label")?,
+            },
+            "output" => ComponentKind::Output {
+                label: fields.string("label")?,
+            },
+            _ => return Err(CircuitError::UnknownComponentType(fields.path, type_name)),
+        };
+
+        Ok(Component {
+            x,
+            y,
+            kind,
+            extra: fields.extra(),
+        })
+    }
+
+    fn to_json(&self) -> Value {
+        let mut object = Map::new();
+        object.insert("type".to_string(), Value::from(self.kind.type_name()));
+        object.insert("x".to_string(), Value::from(self.x));
+        object.insert("y".to_string(), Value::from(self.y));
+
+        let mut insert = |name: &str, value: Value| {
+            object.insert(name.to_string(), value);
+        };
+        match &self.kind {
+            ComponentKind::Rom { address_width, word_size, contents } => {
+                insert("addresswidth", Value::from(*address_width));
+                insert("wordsize", Value::from(*word_size));
+                insert("contents", Value::from(contents.as_str()));
+            },
+            ComponentKind::Sram { address_width, data_width } => {
+                insert("addresswidth", Value::from(*address_width));
+                insert("datawidth", Value::from(*data_width));
+            },
+            ComponentKind::And { inputs } | ComponentKind::Or { inputs } => insert("inputs", Value::from(*inputs)),
+            ComponentKind::Not | ComponentKind::ToggleButton => {},
+            ComponentKind::Clock { period } => insert("period", Value::from(*period)),
+            ComponentKind::Bargraph { size, off_color, on_color } => {
+                insert("size", Value::from(*size));
+                if let Some(color) = off_color {
+                    insert("offColor", Value::from(color.as_str()));
+                }
+                if let Some(color) = on_color {
+                    insert("onColor", Value::from(color.as_str()));
+                }
+            },
+            ComponentKind::Register { width } => insert("width", Value::from(*width)),
+            ComponentKind::Custom { circuit_name } => insert("circuitName", Value::from(circuit_name.as_str())),
+            ComponentKind::Const { value } => insert("value", Value::from(*value)),
+            ComponentKind::Input { label } | ComponentKind::Output { label } => insert("label", Value::from(label.as_str())),
+        }
+
+        object.extend(self.extra.clone());
+        Value::Object(object)
+    }
+}
+
+impl Connection {
+    pub fn new(x1: i64, y1: i64, x2: i64, y2: i64) -> Connection {
+        Connection {
+            x1,
+            y1,
+            x2,
+            y2,
+            extra: Map::new(),
+        }
+    }
+
+    fn from_json(value: &Value, path: String) -> Result<Connection, CircuitError> {
+        let mut fields = Fields::new(value, path)?;
+        Ok(Connection {
+            x1: fields.integer("x1")?,
+            y1: fields.integer("y1")?,
+            x2: fields.integer("x2")?,
+            y2: fields.integer("y2")?,
+            extra: fields.extra(),
+        })
+    }
+
+    fn to_json(&self) -> Value {
+        let mut object = Map::new();
+        object.insert("x1".to_string(), Value::from(self.x1));
+        object.insert("y1".to_string(), Value::from(self.y1));
+        object.insert("x2".to_string(), Value::from(self.x2));
+        object.insert("y2".to_string(), Value::from(self.y2));
+        object.extend(self.extra.clone());
+        Value::Object(object)
+    }
+}
+
+impl Circuit {
+    pub fn new(name: &str) -> Circuit {
+        Circuit {
+            name: name.to_string(),
+            label: name.to_string(),
+            components: Vec::new(),
+            connections: Vec::new(),
+            extra: Map::new(),
+        }
+    }
+
+    fn from_json(value: &Value, path: String) -> Result<Circuit, CircuitError> {
+        let mut fields = Fields::new(value, path)?;
+
+        let name = fields.string("name")?;
+        let label = fields.string("label")?;
+        let components = fields.array("components")?.iter()
+            .enumerate()
+            .map(|(index, component)| Component::from_json(component, format!("{}[{}]", fields.path("components"), index)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let connections = fields.array("connections")?.iter()
+            .enumerate()
+            .map(|(index, connection)| Connection::from_json(connection, format!("{}[{}]", fields.path("connections"), index)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Circuit {
+            name,
+            label,
+            components,
+            connections,
+            extra: fields.extra(),
+        })
+    }
+
+    fn to_json(&self) -> Value {
+        let mut object = Map::new();
+        object.insert("name".to_string(), Value::from(self.name.as_str()));
+        object.insert("label".to_string(), Value::from(self.label.as_str()));
+        object.insert("components".to_string(), Value::Array(self.components.iter().map(Component::to_json).collect()));
+        object.insert("connections".to_string(), Value::Array(self.connections.iter().map(Connection::to_json).collect()));
+        object.extend(self.extra.clone());
+        Value::Object(object)
+    }
+
+    /// Names of the circuits that are used as `custom` components, in the order they first appear
+    pub fn subcircuits(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        for component in &self.components {
+            if let ComponentKind::Custom { circuit_name } = &component.kind {
+                if !names.contains(&circuit_name.as_str()) {
+                    names.push(circuit_name.as_str());
+                }
+            }
+        }
+        names
+    }
+}
+
+impl CircuitFile {
+    pub fn from_json(value: &Value) -> Result<CircuitFile, CircuitError> {
+        let mut fields = Fields::new(value, String::new())?;
+
+        let version = fields.number("version")?;
+        let circuits = fields.array("circuits")?.iter()
+            .enumerate()
+            .map(|(index, circuit)| Circuit::from_json(circuit, format!("circuits[{}]", index)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(CircuitFile {
+            version,
+            circuits,
+            extra: fields.extra(),
+        })
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut object = Map::new();
+        object.insert("version".to_string(), Value::from(self.version));
+        object.insert("circuits".to_string(), Value::Array(self.circuits.iter().map(Circuit::to_json).collect()));
+        object.extend(self.extra.clone());
+        Value::Object(object)
+    }
+
+    /// Parses and validates a file
+    pub fn parse(text: &str) -> Result<CircuitFile, CircuitError> {
+        let value = serde_json::from_str(text)
+            .map_err(CircuitError::Json)?;
+        let file = CircuitFile::from_json(&value)?;
+        file.validate()?;
+        Ok(file)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<CircuitFile, CircuitError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|err| CircuitError::FileRead(path.to_path_buf(), err))?;
+        CircuitFile::parse(&text)
+    }
+
+    /// Writes the file in the compact form LogicSimulator uses
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), CircuitError> {
+        let path = path.as_ref();
+        fs::write(path, self.to_json().to_string())
+            .map_err(|err| CircuitError::FileWrite(path.to_path_buf(), err))
+    }
+
+    pub fn circuit(&self, name: &str) -> Option<&Circuit> {
+        self.circuits.iter().find(|circuit| circuit.name == name)
+    }
+
+    pub fn circuit_mut(&mut self, name: &str) -> Option<&mut Circuit> {
+        self.circuits.iter_mut().find(|circuit| circuit.name == name)
+    }
+
+    /// The circuit LogicSimulator opens, which is the first one
+    pub fn main_circuit(&self) -> Option<&Circuit> {
+        self.circuits.first()
+    }
+
+    /// Checks that circuit names are unique, that every `custom` component refers to an existing circuit without
+    /// containing itself and that the ROM contents can be decoded
+    pub fn validate(&self) -> Result<(), CircuitError> {
+        let mut names = HashSet::new();
+        for circuit in &self.circuits {
+            if !names.insert(circuit.name.as_str()) {
+                return Err(CircuitError::DuplicateCircuit(circuit.name.clone()));
+            }
+        }
+
+        for (circuit_index, circuit) in self.circuits.iter().enumerate() {
+            for name in circuit.subcircuits() {
+                if !names.contains(name) {
+                    return Err(CircuitError::UnknownCircuit(circuit.name.clone(), name.to_string()));
+                }
+            }
+            for (index, component) in circuit.components.iter().enumerate() {
+                if let ComponentKind::Rom { contents, .. } = &component.kind {
+                    if parse_rom_contents(contents).is_none() {
+                        return Err(CircuitError::InvalidRomContents(format!("circuits[{}].components[{}]", circuit_index, index)));
+                    }
+                }
+            }
+        }
+
+        // Depth-first search for cycles, 1 marks circuits on the current path and 2 the finished ones
+        let mut state = HashMap::new();
+        for circuit in &self.circuits {
+            self.check_recursion(circuit, &mut state)?;
+        }
+
+        Ok(())
+    }
+
+    fn check_recursion<'a>(&'a self, circuit: &'a Circuit, state: &mut HashMap<&'a str, u8>) -> Result<(), CircuitError> {
+        match state.get(circuit.name.as_str()) {
+            Some(1) => return Err(CircuitError::RecursiveCircuit(circuit.name.clone())),
+            Some(_) => return Ok(()),
+            None => {},
+        }
+
+        state.insert(&circuit.name, 1);
+        for name in circuit.subcircuits() {
+            // Unknown circuits were reported before
+            if let Some(subcircuit) = self.circuit(name) {
+                self.check_recursion(subcircuit, state)?;
+            }
+        }
+        state.insert(&circuit.name, 2);
+
+        Ok(())
+    }
+}
+
+/// Decodes the `contents` of a ROM: hexadecimal words separated by whitespace or commas
+pub fn parse_rom_contents(contents: &str) -> Option<Vec<u16>> {
+    contents.split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|word| !word.is_empty())
+        .map(|word| u16::from_str_radix(word.trim_start_matches("0x"), 16).ok())
+        .collect()
+}
+
+pub fn format_rom_contents(words: &[u16]) -> String {
+    words.iter()
+        .map(|word| format!("{:04x}", word))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,148 @@
+
+use crate::format::{Circuit, CircuitError, CircuitFile, ComponentKind};
+use crate::sim::{ComponentRef, Simulation};
+
+/// Splits the label of a port into the name of its bus and a bit index: `alu_fn[2]`, `alu_fn_2` and `alu_fn2` are all
+/// bit 2 of `alu_fn`, a label without an index is bit 0 of a bus of its own
+pub fn parse_port_label(label: &str) -> (String, u32) {
+    if let Some(name) = label.strip_suffix(']') {
+        if let Some((name, index)) = name.rsplit_once('[') {
+            if let Ok(index) = index.parse() {
+                return (name.to_string(), index);
+            }
+        }
+    }
+
+    let name = label.trim_end_matches(|c: char| c.is_ascii_digit());
+    if name.is_empty() || name.len() == label.len() {
+        return (label.to_string(), 0);
+    }
+    let index = label[name.len()..].parse().unwrap_or(0);
+    (name.trim_end_matches('_').to_string(), index)
+}
+
+/// A bus made of `input` or `output` components with the same name
+#[derive(Clone, Debug)]
+pub struct Port {
+    pub name: String,
+    /// The component of every bit, bits without a component are always 0
+    pub bits: Vec<Option<usize>>,
+}
+
+impl Port {
+    pub fn width(&self) -> u32 {
+        self.bits.len() as u32
+    }
+}
+
+fn collect_ports(circuit: &Circuit, input: bool) -> Vec<Port> {
+    let mut ports: Vec<Port> = Vec::new();
+    for (index, component) in circuit.components.iter().enumerate() {
+        let label = match &component.kind {
+            ComponentKind::Input { label } if input => label,
+            ComponentKind::Output { label } if !input => label,
+            _ => continue,
+        };
+        let (name, bit) = parse_port_label(label);
+        let bit = bit as usize;
+
+        let port = match ports.iter().position(|port| port.name == name) {
+            Some(position) => &mut ports[position],
+            None => {
+                ports.push(Port { name, bits: Vec::new() });
+                ports.last_mut().unwrap()
+            },
+        };
+        if port.bits.len() <= bit {
+            port.bits.resize(bit + 1, None);
+        }
+        port.bits[bit] = Some(index);
+    }
+    ports
+}
+
+/// Drives a circuit through its `input` components and reads its `output` components, bit by bit or as buses.
+///
+/// This is meant for combinational blocks compiled on their own, like `build.sh InstructionDecoder`.
+#[derive(Clone, Debug)]
+pub struct Harness {
+    file: CircuitFile,
+    simulation: Simulation,
+    inputs: Vec<Port>,
+    outputs: Vec<Port>,
+}
+
+impl Harness {
+    pub fn new(file: CircuitFile, circuit_name: &str) -> Result<Harness, CircuitError> {
+        let circuit = file.circuit(circuit_name)
+            .ok_or_else(|| CircuitError::MissingCircuit(circuit_name.to_string()))?;
+        let simulation = Simulation::new(&file, circuit)?;
+        let inputs = collect_ports(circuit, true);
+        let outputs = collect_ports(circuit, false);
+
+        Ok(Harness {
+            file,
+            simulation,
+            inputs,
+            outputs,
+        })
+    }
+
+    pub fn file(&self) -> &CircuitFile {
+        &self.file
+    }
+
+    pub fn simulation(&self) -> &Simulation {
+        &self.simulation
+    }
+
+    pub fn simulation_mut(&mut self) -> &mut Simulation {
+        &mut self.simulation
+    }
+
+    pub fn inputs(&self) -> &[Port] {
+        &self.inputs
+    }
+
+    pub fn outputs(&self) -> &[Port] {
+        &self.outputs
+    }
+
+    /// Drives an input bus with `value`, returns `false` if there is no such input. The change takes effect with the
+    /// next step of the simulation.
+    pub fn set(&mut self, name: &str, value: u64) -> bool {
+        let port = match self.inputs.iter().find(|port| port.name == name) {
+            Some(port) => port,
+            None => return false,
+        };
+        for (bit, component) in port.bits.iter().enumerate() {
+            if let Some(component) = *component {
+                self.simulation.set_source(ComponentRef { instance: 0, component }, bit < 64 && value & (1 << bit) != 0);
+            }
+        }
+        true
+    }
+
+    /// Value of an input or output bus
+    pub fn get(&self, name: &str) -> Option<u64> {
+        // Inputs are read at the pin of the `input` component, outputs at the pin of the `output` component
+        let (port, pin) = match self.outputs.iter().find(|port| port.name == name) {
+            Some(port) => (port, "in"),
+            None => (self.inputs.iter().find(|port| port.name == name)?, "out"),
+        };
+        let value = port.bits.iter()
+            .take(64)
+            .enumerate()
+            .filter_map(|(bit, component)| Some((bit, (*component)?)))
+            .fold(0, |value, (bit, component)| {
+                let high = self.simulation.pin_value(ComponentRef { instance: 0, component }, pin).unwrap_or(false);
+                value | (high as u64) << bit
+            });
+        Some(value)
+    }
+
+    /// Steps until the outputs are stable, see `Simulation::settle`
+    pub fn settle(&mut self, max_steps: u64) -> Option<u64> {
+        self.simulation.settle(max_steps)
+    }
+}
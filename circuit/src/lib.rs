@@ -0,0 +1,19 @@
+
+mod format;
+mod pins;
+mod netlist;
+mod sim;
+mod harness;
+mod compiled;
+mod stats;
+mod timing;
+pub mod testing;
+
+pub use crate::format::{CircuitFile, Circuit, Component, ComponentKind, Connection, CircuitError, parse_rom_contents, format_rom_contents};
+pub use crate::pins::{Pin, Direction, component_pins, ports};
+pub use crate::netlist::{Netlist, Net, PinRef, NetlistIssue};
+pub use crate::sim::{Simulation, Instance, ComponentRef};
+pub use crate::harness::{Harness, Port, parse_port_label};
+pub use crate::compiled::CompiledSimulation;
+pub use crate::stats::{Resources, GateCount, MemoryCount, instance_counts};
+pub use crate::timing::{TimingAnalysis, TimingPath, Edge};
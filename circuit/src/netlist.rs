@@ -0,0 +1,351 @@
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::format::{Circuit, CircuitError, CircuitFile, ComponentKind, Connection};
+use crate::pins::{Direction, Pin, component_pins};
+
+/// A pin of a component in a circuit
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PinRef {
+    pub component: usize,
+    pub pin: usize,
+}
+
+/// A set of pins connected by wires
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Net {
+    pub name: String,
+    pub pins: Vec<PinRef>,
+    /// Indices of the wire segments that make up the net
+    pub connections: Vec<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NetlistIssue {
+    /// A wire end that touches neither another wire nor a pin
+    DanglingWire {
+        connection: usize,
+        x: i64,
+        y: i64,
+    },
+    /// Two collinear wires overlap and thereby connect pins that would otherwise be on separate nets
+    Short {
+        connections: (usize, usize),
+        pins: Vec<PinRef>,
+    },
+    /// A net with wires and inputs that nothing drives
+    Undriven(usize),
+}
+
+/// The nets of a circuit, extracted from the geometry of its wires and pins.
+///
+/// Wires are connected where their ends meet and where the end of one wire lies on another one (a junction), two wires
+/// that merely cross aren't connected. Pins connect to the wire ends and to other pins at their position.
+#[derive(Clone, Debug)]
+pub struct Netlist {
+    /// The pins of every component
+    pub pins: Vec<Vec<Pin>>,
+    /// The net of every pin of every component, unconnected pins have a net of their own
+    pub pin_nets: Vec<Vec<usize>>,
+    pub nets: Vec<Net>,
+    pub issues: Vec<NetlistIssue>,
+}
+
+// Disjoint-set forest over wire segments and pins
+struct Sets {
+    parents: Vec<usize>,
+}
+
+impl Sets {
+    fn new(count: usize) -> Sets {
+        Sets {
+            parents: (0..count).collect(),
+        }
+    }
+
+    fn find(&mut self, element: usize) -> usize {
+        let mut root = element;
+        while self.parents[root] != root {
+            root = self.parents[root];
+        }
+        // Path compression
+        let mut element = element;
+        while self.parents[element] != root {
+            let parent = self.parents[element];
+            self.parents[element] = root;
+            element = parent;
+        }
+        root
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let a = self.find(a);
+        let b = self.find(b);
+        if a != b {
+            self.parents[b] = a;
+        }
+    }
+}
+
+// Whether (x, y) lies on the segment but isn't one of its ends
+fn in_interior(connection: &Connection, x: i64, y: i64) -> bool {
+    let &Connection { x1, y1, x2, y2, .. } = connection;
+    let cross = (x2 - x1) * (y - y1) - (y2 - y1) * (x - x1);
+    cross == 0
+        && x >= x1.min(x2) && x <= x1.max(x2)
+        && y >= y1.min(y2) && y <= y1.max(y2)
+        && (x, y) != (x1, y1) && (x, y) != (x2, y2)
+}
+
+// Whether two segments on the same line share more than a single point, only for horizontal and vertical segments
+fn overlap(a: (i64, i64), b: (i64, i64)) -> bool {
+    a.0.max(b.0) < a.1.min(b.1)
+}
+
+// Whether two wires lie on the same horizontal or vertical line and overlap
+fn collinear_overlap(a: &Connection, b: &Connection) -> bool {
+    let x_range = |c: &Connection| (c.x1.min(c.x2), c.x1.max(c.x2));
+    let y_range = |c: &Connection| (c.y1.min(c.y2), c.y1.max(c.y2));
+    if a.y1 == a.y2 && b.y1 == b.y2 && a.y1 == b.y1 {
+        overlap(x_range(a), x_range(b))
+    } else if a.x1 == a.x2 && b.x1 == b.x2 && a.x1 == b.x1 {
+        overlap(y_range(a), y_range(b))
+    } else {
+        false
+    }
+}
+
+impl Netlist {
+    pub fn extract(file: &CircuitFile, circuit: &Circuit) -> Result<Netlist, CircuitError> {
+        let pins = circuit.components.iter()
+            .map(|component| component_pins(component, file).ok_or_else(|| match &component.kind {
+                ComponentKind::Custom { circuit_name } => CircuitError::UnknownCircuit(circuit.name.clone(), circuit_name.clone()),
+                _ => unreachable!(),
+            }))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Elements of the sets: first the wire segments, then all pins
+        let connections = &circuit.connections;
+        let mut pin_refs = Vec::new();
+        for (component, component_pins) in pins.iter().enumerate() {
+            for pin in 0..component_pins.len() {
+                pin_refs.push(PinRef { component, pin });
+            }
+        }
+        let pin_element = |index: usize| connections.len() + index;
+        let mut sets = Sets::new(connections.len() + pin_refs.len());
+
+        // Wire ends and pins at the same position
+        let mut points: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+        for (index, connection) in connections.iter().enumerate() {
+            points.entry((connection.x1, connection.y1)).or_default().push(index);
+            if (connection.x2, connection.y2) != (connection.x1, connection.y1) {
+                points.entry((connection.x2, connection.y2)).or_default().push(index);
+            }
+        }
+        for (index, pin_ref) in pin_refs.iter().enumerate() {
+            let pin = &pins[pin_ref.component][pin_ref.pin];
+            points.entry((pin.x, pin.y)).or_default().push(pin_element(index));
+        }
+        for elements in points.values() {
+            for &element in &elements[1..] {
+                sets.union(elements[0], element);
+            }
+        }
+
+        // Junctions, looked up by the line the segments lie on. A wire that overlaps another one on the same line ends on
+        // it as well, those two are connected further below where the overlap is reported.
+        let mut horizontal: HashMap<i64, Vec<usize>> = HashMap::new();
+        let mut vertical: HashMap<i64, Vec<usize>> = HashMap::new();
+        let mut diagonal = Vec::new();
+        for (index, connection) in connections.iter().enumerate() {
+            if connection.y1 == connection.y2 {
+                horizontal.entry(connection.y1).or_default().push(index);
+            } else if connection.x1 == connection.x2 {
+                vertical.entry(connection.x1).or_default().push(index);
+            } else {
+                diagonal.push(index);
+            }
+        }
+        let mut junctions = HashSet::new();
+        for (&(x, y), elements) in &points {
+            if !elements.iter().any(|&element| element < connections.len()) {
+                continue;
+            }
+            let candidates = horizontal.get(&y).into_iter().flatten()
+                .chain(vertical.get(&x).into_iter().flatten())
+                .chain(diagonal.iter());
+            for &index in candidates {
+                if !in_interior(&connections[index], x, y) {
+                    continue;
+                }
+                junctions.insert((x, y));
+                let joined = elements.iter()
+                    .find(|&&element| element >= connections.len() || !collinear_overlap(&connections[element], &connections[index]));
+                if let Some(&element) = joined {
+                    sets.union(element, index);
+                }
+            }
+        }
+
+        let mut issues = Vec::new();
+        for (index, connection) in connections.iter().enumerate() {
+            for &(x, y) in &[(connection.x1, connection.y1), (connection.x2, connection.y2)] {
+                if points[&(x, y)].len() == 1 && !junctions.contains(&(x, y)) {
+                    issues.push(NetlistIssue::DanglingWire { connection: index, x, y });
+                }
+            }
+        }
+
+        // Overlapping wires are connected as well, but they usually aren't intended to be
+        let mut group_pins: HashMap<usize, Vec<PinRef>> = HashMap::new();
+        for (index, &pin_ref) in pin_refs.iter().enumerate() {
+            group_pins.entry(sets.find(pin_element(index))).or_default().push(pin_ref);
+        }
+        let groups = (0..connections.len()).map(|index| sets.find(index)).collect::<Vec<_>>();
+        let mut overlaps = Vec::new();
+        for segments in horizontal.values() {
+            let range = |index: usize| (connections[index].x1.min(connections[index].x2), connections[index].x1.max(connections[index].x2));
+            Netlist::find_overlaps(segments, range, &mut overlaps);
+        }
+        for segments in vertical.values() {
+            let range = |index: usize| (connections[index].y1.min(connections[index].y2), connections[index].y1.max(connections[index].y2));
+            Netlist::find_overlaps(segments, range, &mut overlaps);
+        }
+        overlaps.sort_unstable();
+        for (a, b) in overlaps {
+            if sets.find(a) == sets.find(b) {
+                continue;
+            }
+            sets.union(a, b);
+
+            let pins_a = group_pins.get(&groups[a]).cloned().unwrap_or_default();
+            let pins_b = group_pins.get(&groups[b]).cloned().unwrap_or_default();
+            if !pins_a.is_empty() && !pins_b.is_empty() {
+                issues.push(NetlistIssue::Short {
+                    connections: (a, b),
+                    pins: pins_a.into_iter().chain(pins_b).collect(),
+                });
+            }
+        }
+
+        // Number the nets in the order of the components
+        let mut net_indices = HashMap::new();
+        let mut nets: Vec<Net> = Vec::new();
+        let mut pin_nets = pins.iter().map(|component_pins| vec![0; component_pins.len()]).collect::<Vec<_>>();
+        for (index, &pin_ref) in pin_refs.iter().enumerate() {
+            let root = sets.find(pin_element(index));
+            let net = *net_indices.entry(root).or_insert_with(|| {
+                nets.push(Net { name: String::new(), pins: Vec::new(), connections: Vec::new() });
+                nets.len() - 1
+            });
+            nets[net].pins.push(pin_ref);
+            pin_nets[pin_ref.component][pin_ref.pin] = net;
+        }
+        for index in 0..connections.len() {
+            let root = sets.find(index);
+            let net = *net_indices.entry(root).or_insert_with(|| {
+                nets.push(Net { name: String::new(), pins: Vec::new(), connections: Vec::new() });
+                nets.len() - 1
+            });
+            nets[net].connections.push(index);
+        }
+
+        let mut netlist = Netlist {
+            pins,
+            pin_nets,
+            nets,
+            issues,
+        };
+        netlist.name_nets(circuit);
+
+        for (index, net) in netlist.nets.iter().enumerate() {
+            let direction = |pin_ref: &&PinRef| netlist.pin(**pin_ref).direction;
+            let has_inputs = net.pins.iter().any(|pin_ref| direction(&pin_ref) == Direction::Input);
+            let has_drivers = net.pins.iter().any(|pin_ref| direction(&pin_ref) == Direction::Output);
+            if !net.connections.is_empty() && has_inputs && !has_drivers {
+                netlist.issues.push(NetlistIssue::Undriven(index));
+            }
+        }
+
+        Ok(netlist)
+    }
+
+    // Collects the pairs of segments on the same line whose ranges overlap
+    fn find_overlaps<F: Fn(usize) -> (i64, i64)>(segments: &[usize], range: F, overlaps: &mut Vec<(usize, usize)>) {
+        let mut sorted = segments.to_vec();
+        sorted.sort_by_key(|&index| range(index));
+        for (position, &a) in sorted.iter().enumerate() {
+            for &b in &sorted[position + 1..] {
+                if range(b).0 >= range(a).1 {
+                    break;
+                }
+                if overlap(range(a), range(b)) {
+                    overlaps.push((a.min(b), a.max(b)));
+                }
+            }
+        }
+    }
+
+    // Nets are named after the `input` or `output` component on them, otherwise after the pin driving them
+    fn name_nets(&mut self, circuit: &Circuit) {
+        let mut used = HashMap::new();
+        for index in 0..self.nets.len() {
+            let net = &self.nets[index];
+
+            let label = net.pins.iter()
+                .filter_map(|pin_ref| match &circuit.components[pin_ref.component].kind {
+                    ComponentKind::Input { label } => Some((0, label.clone())),
+                    ComponentKind::Output { label } => Some((1, label.clone())),
+                    _ => None,
+                })
+                .min();
+            let pin_name = |pin_ref: &PinRef| {
+                let component = &circuit.components[pin_ref.component];
+                format!("{}{}.{}", component.kind.type_name(), pin_ref.component, self.pin(*pin_ref).name)
+            };
+            let driver = net.pins.iter()
+                .find(|pin_ref| self.pin(**pin_ref).direction == Direction::Output)
+                .or_else(|| net.pins.first());
+
+            let name = match (label, driver) {
+                (Some((_, label)), _) => label,
+                (None, Some(pin_ref)) => pin_name(pin_ref),
+                (None, None) => format!("wire{}", net.connections[0]),
+            };
+
+            let count = used.entry(name.clone()).or_insert(0);
+            *count += 1;
+            self.nets[index].name = if *count == 1 { name } else { format!("{}#{}", name, count) };
+        }
+    }
+
+    pub fn pin(&self, pin_ref: PinRef) -> &Pin {
+        &self.pins[pin_ref.component][pin_ref.pin]
+    }
+
+    /// The net of the pin of `component` called `name`
+    pub fn net_of(&self, component: usize, name: &str) -> Option<usize> {
+        self.pins[component].iter()
+            .position(|pin| pin.name == name)
+            .map(|pin| self.pin_nets[component][pin])
+    }
+
+    /// The output pins that drive a net, LogicSimulator ORs their values
+    pub fn drivers(&self, net: usize) -> impl Iterator<Item = PinRef> + '_ {
+        self.nets[net].pins.iter()
+            .copied()
+            .filter(move |&pin_ref| self.pin(pin_ref).direction == Direction::Output)
+    }
+}
+
+impl fmt::Display for NetlistIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            NetlistIssue::DanglingWire { connection, x, y } => write!(f, "wire {} ends unconnected at ({}, {})", connection, x, y),
+            NetlistIssue::Short { connections: (a, b), pins } => write!(f, "wires {} and {} overlap and connect {} pins", a, b, pins.len()),
+            NetlistIssue::Undriven(net) => write!(f, "net {} has no driver", net),
+        }
+    }
+}
@@ -0,0 +1,181 @@
+
+use crate::format::{Circuit, CircuitFile, Component, ComponentKind};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Input,
+    Output,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pin {
+    pub name: String,
+    pub direction: Direction,
+    pub x: i64,
+    pub y: i64,
+}
+
+// Width of gates and of the larger components, pins sit one unit outside of the body
+const GATE_WIDTH: i64 = 6;
+const BLOCK_WIDTH: i64 = 12;
+
+// Pin geometry as LogicSimulator lays it out: pins are 2 units apart, inputs on the left and outputs on the right. Each
+// column is centered on a body that is as high as the longer column. `None` is an empty slot that separates groups of
+// pins.
+struct Layout {
+    x: i64,
+    y: i64,
+    width: i64,
+    min_height: i64,
+    inputs: Vec<Option<String>>,
+    outputs: Vec<Option<String>>,
+}
+
+impl Layout {
+    fn new(component: &Component, width: i64) -> Layout {
+        Layout {
+            x: component.x,
+            y: component.y,
+            width,
+            min_height: 0,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+        }
+    }
+
+    fn pins(self) -> Vec<Pin> {
+        let height = (2 * self.inputs.len().max(self.outputs.len()) as i64).max(self.min_height);
+
+        let y = self.y;
+        let column = |slots: Vec<Option<String>>, x: i64, direction: Direction| {
+            let offset = (height - 2 * slots.len() as i64) / 2;
+            slots.into_iter()
+                .enumerate()
+                .filter_map(move |(index, name)| name.map(|name| Pin {
+                    name,
+                    direction,
+                    x,
+                    y: y + 1 + offset + 2 * index as i64,
+                }))
+        };
+
+        let (input_x, output_x) = (self.x - 1, self.x + self.width);
+        let mut pins = column(self.inputs, input_x, Direction::Input).collect::<Vec<_>>();
+        pins.extend(column(self.outputs, output_x, Direction::Output));
+        pins
+    }
+}
+
+fn named(names: &[&str]) -> Vec<Option<String>> {
+    names.iter().map(|name| Some(name.to_string())).collect()
+}
+
+fn numbered(prefix: &str, count: u32) -> Vec<Option<String>> {
+    (0..count).map(|index| Some(format!("{}{}", prefix, index))).collect()
+}
+
+/// The `input` and `output` components of a circuit, which become the pins of `custom` components using it.
+///
+/// Both are ordered from top to bottom, like the pins.
+pub fn ports(circuit: &Circuit) -> (Vec<usize>, Vec<usize>) {
+    let mut inputs = Vec::new();
+    let mut outputs = Vec::new();
+    for (index, component) in circuit.components.iter().enumerate() {
+        match component.kind {
+            ComponentKind::Input { .. } => inputs.push(index),
+            ComponentKind::Output { .. } => outputs.push(index),
+            _ => {},
+        }
+    }
+
+    let position = |&index: &usize| (circuit.components[index].y, circuit.components[index].x);
+    inputs.sort_by_key(position);
+    outputs.sort_by_key(position);
+    (inputs, outputs)
+}
+
+fn label(component: &Component) -> Option<String> {
+    match &component.kind {
+        ComponentKind::Input { label } | ComponentKind::Output { label } => Some(label.clone()),
+        _ => None,
+    }
+}
+
+/// Positions of the pins of a component, `file` is needed to look up the ports of `custom` components.
+///
+/// Returns `None` for a `custom` component whose circuit doesn't exist.
+pub fn component_pins(component: &Component, file: &CircuitFile) -> Option<Vec<Pin>> {
+    let pins = match &component.kind {
+        ComponentKind::And { inputs } | ComponentKind::Or { inputs } => {
+            let mut layout = Layout::new(component, GATE_WIDTH);
+            layout.inputs = numbered("in", *inputs);
+            layout.outputs = named(&["out"]);
+            layout.pins()
+        },
+        ComponentKind::Not => {
+            let mut layout = Layout::new(component, GATE_WIDTH);
+            layout.min_height = 4;
+            layout.inputs = named(&["in"]);
+            layout.outputs = named(&["out"]);
+            layout.pins()
+        },
+        ComponentKind::Clock { .. } | ComponentKind::ToggleButton | ComponentKind::Const { .. } => {
+            let mut layout = Layout::new(component, GATE_WIDTH);
+            layout.min_height = 4;
+            layout.outputs = named(&["out"]);
+            layout.pins()
+        },
+        ComponentKind::Input { .. } => {
+            let mut layout = Layout::new(component, GATE_WIDTH);
+            layout.outputs = named(&["out"]);
+            layout.pins()
+        },
+        ComponentKind::Output { .. } => {
+            let mut layout = Layout::new(component, GATE_WIDTH);
+            layout.inputs = named(&["in"]);
+            layout.pins()
+        },
+        ComponentKind::Bargraph { size, .. } => {
+            let mut layout = Layout::new(component, GATE_WIDTH);
+            layout.inputs = numbered("in", *size);
+            layout.pins()
+        },
+        ComponentKind::Rom { address_width, word_size, .. } => {
+            let mut layout = Layout::new(component, BLOCK_WIDTH);
+            layout.inputs = named(&["OE"]);
+            layout.inputs.push(None);
+            layout.inputs.extend(numbered("A", *address_width));
+            layout.outputs = numbered("D", *word_size);
+            layout.pins()
+        },
+        ComponentKind::Sram { address_width, data_width } => {
+            let mut layout = Layout::new(component, BLOCK_WIDTH);
+            layout.inputs = named(&["OE", "WE"]);
+            layout.inputs.push(None);
+            layout.inputs.extend(numbered("A", *address_width));
+            layout.inputs.push(None);
+            layout.inputs.extend(numbered("D", *data_width));
+            layout.outputs = numbered("Q", *data_width);
+            layout.pins()
+        },
+        ComponentKind::Register { width } => {
+            let mut layout = Layout::new(component, BLOCK_WIDTH);
+            layout.inputs = named(&["CLR", "EN", "C"]);
+            layout.inputs.push(None);
+            layout.inputs.extend(numbered("D", *width));
+            layout.outputs = numbered("Q", *width);
+            layout.pins()
+        },
+        ComponentKind::Custom { circuit_name } => {
+            let circuit = file.circuit(circuit_name)?;
+            let (inputs, outputs) = ports(circuit);
+
+            let mut layout = Layout::new(component, BLOCK_WIDTH);
+            layout.inputs = inputs.iter().map(|&index| label(&circuit.components[index])).collect();
+            layout.outputs = outputs.iter().map(|&index| label(&circuit.components[index])).collect();
+            layout.pins()
+        },
+    };
+
+    Some(pins)
+}
@@ -0,0 +1,845 @@
+
+use std::collections::{HashMap, VecDeque};
+use std::mem;
+use std::ops::Range;
+
+use crate::format::{Circuit, CircuitError, CircuitFile, ComponentKind, parse_rom_contents};
+use crate::netlist::Netlist;
+use crate::pins::{Direction, ports};
+
+/// A component in a circuit instance
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ComponentRef {
+    pub instance: usize,
+    pub component: usize,
+}
+
+/// A circuit placed in the simulation, either the top circuit or the circuit of a `custom` component
+#[derive(Clone, Debug)]
+pub struct Instance {
+    /// Dot-separated path of the instance, e.g. `main.GPIO49` for the `custom` component 49 of `main`
+    pub path: String,
+    pub circuit: String,
+    pub parent: Option<ComponentRef>,
+    // Simulation net of every net of the circuit's netlist
+    nets: Vec<usize>,
+    // Element of every component, `None` for components that only connect nets or only display them
+    elements: Vec<Option<usize>>,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum ElementKind {
+    And,
+    Or,
+    Not,
+    Const(bool),
+    Clock {
+        half_period: u64,
+    },
+    // Toggle buttons and the `input` components of the top circuit, both are set from outside
+    Source,
+    Rom {
+        words: Vec<u16>,
+    },
+    Sram {
+        memory: Vec<u64>,
+        // Bits of every word that are X
+        unknown: Vec<u64>,
+    },
+    Register {
+        value: u64,
+        unknown: u64,
+        clock: bool,
+        clock_unknown: bool,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Element {
+    pub(crate) kind: ElementKind,
+    pub(crate) inputs: Vec<usize>,
+    pub(crate) outputs: Range<usize>,
+}
+
+// An output of an element. Changes become visible on the net one time step after the element was evaluated. An
+// unknown output always has `value` 0.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Slot {
+    pub(crate) net: usize,
+    pub(crate) value: bool,
+    unknown: bool,
+    next: bool,
+    next_unknown: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Net {
+    // Number of drivers that output 1, a net carries the OR of its drivers like in LogicSimulator
+    high: u32,
+    // Number of drivers that output X, they only make the net X if no driver outputs 1
+    unknown: u32,
+    fanout: Vec<usize>,
+}
+
+impl Net {
+    // Value and whether the value is X, which reads as 0
+    fn state(&self) -> (bool, bool) {
+        (self.high > 0, self.high == 0 && self.unknown > 0)
+    }
+}
+
+// A word whose `unknown` bits are X, their bits in `value` are 0
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Word {
+    value: u64,
+    unknown: u64,
+}
+
+impl Word {
+    fn known(value: u64) -> Word {
+        Word { value, unknown: 0 }
+    }
+
+    // Either of two words: bits that differ become X
+    fn merge(self, other: Word) -> Word {
+        let unknown = self.unknown | other.unknown | (self.value ^ other.value);
+        Word { value: self.value & !unknown, unknown }
+    }
+}
+
+// Calls `f` with every address that an address with X bits may stand for
+fn for_each_address(address: Word, mut f: impl FnMut(usize)) {
+    let mut bits = address.unknown;
+    loop {
+        f((address.value | bits) as usize);
+        if bits == 0 {
+            break;
+        }
+        bits = (bits - 1) & address.unknown;
+    }
+}
+
+// Reads a memory at an address that may contain X bits: bits that differ between the candidate words become X
+fn read_memory(address: Word, read: impl Fn(usize) -> Word) -> Word {
+    let mut result = None;
+    for_each_address(address, |address| {
+        let word = read(address);
+        result = Some(result.map_or(word, |result: Word| result.merge(word)));
+    });
+    result.unwrap()
+}
+
+// Mask of the lowest `bits` bits
+fn mask(bits: usize) -> u64 {
+    if bits >= 64 { !0 } else { (1 << bits) - 1 }
+}
+
+// Output of a ROM or SRAM whose OE is 1 or X: with OE X, every bit that would be 1 is X
+fn output_enable(data: Word, (_, enable_unknown): (bool, bool)) -> Word {
+    if enable_unknown {
+        Word { value: 0, unknown: data.value | data.unknown }
+    } else {
+        data
+    }
+}
+
+/// Event-driven simulation of a LogicSimulator circuit.
+///
+/// Every gate, ROM, SRAM and register takes one time step to react to a change at its inputs. Time is measured in the
+/// units of LogicSimulator: a `clock` with a period of 60 is high for 30 steps and low for 30 steps, starting with a
+/// rising edge at time 0. `custom` components are flattened, the nets of their `input` and `output` components are
+/// merged with the nets outside.
+///
+/// Registers are positive-edge triggered and load D while EN is high, CLR clears them asynchronously. The SRAM is
+/// written whenever WE is high. ROM and SRAM output 0 while OE is low. All nets, registers and memories start at 0.
+///
+/// `make_unknown` lets registers and memories start in the unknown state X instead, like real flip-flops after
+/// power-up. X propagates pessimistically: an AND gate outputs 0 if any input is 0 and X if any other input is X, a
+/// register loads X if its clock or EN may have caused a load, a memory outputs X for every bit that differs between
+/// the addresses an address with X bits may stand for. A net is X if one of its drivers outputs X and none outputs 1.
+/// There is no high-impedance state, a net without active drivers is 0 like in LogicSimulator.
+#[derive(Clone, Debug)]
+pub struct Simulation {
+    instances: Vec<Instance>,
+    netlists: HashMap<String, Netlist>,
+    elements: Vec<Element>,
+    slots: Vec<Slot>,
+    nets: Vec<Net>,
+    clocks: Vec<usize>,
+    pending: Vec<usize>,
+    dirty: Vec<usize>,
+    is_dirty: Vec<bool>,
+    time: u64,
+}
+
+// Collects elements and nets while the hierarchy is flattened. Nets of different instances that are connected by ports
+// are merged afterwards.
+#[derive(Default)]
+struct Builder {
+    instances: Vec<Instance>,
+    netlists: HashMap<String, Netlist>,
+    net_parents: Vec<usize>,
+    elements: Vec<Element>,
+    slot_nets: Vec<usize>,
+}
+
+impl Builder {
+    fn find(&mut self, net: usize) -> usize {
+        let mut root = net;
+        while self.net_parents[root] != root {
+            root = self.net_parents[root];
+        }
+        self.net_parents[net] = root;
+        root
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let a = self.find(a);
+        let b = self.find(b);
+        if a != b {
+            self.net_parents[b] = a;
+        }
+    }
+
+    fn add_element(&mut self, kind: ElementKind, inputs: Vec<usize>, outputs: Vec<usize>) -> usize {
+        let start = self.slot_nets.len();
+        self.slot_nets.extend(outputs);
+        self.elements.push(Element {
+            kind,
+            inputs,
+            outputs: start..self.slot_nets.len(),
+        });
+        self.elements.len() - 1
+    }
+
+    fn instantiate(&mut self, file: &CircuitFile, circuit: &Circuit, path: String, parent: Option<ComponentRef>) -> Result<usize, CircuitError> {
+        if !self.netlists.contains_key(&circuit.name) {
+            let netlist = Netlist::extract(file, circuit)?;
+            self.netlists.insert(circuit.name.clone(), netlist);
+        }
+        let netlist = &self.netlists[&circuit.name];
+
+        let first_net = self.net_parents.len();
+        self.net_parents.extend(first_net..first_net + netlist.nets.len());
+        let nets = (first_net..first_net + netlist.nets.len()).collect::<Vec<_>>();
+        let pin_nets = netlist.pin_nets.iter()
+            .map(|component_nets| component_nets.iter().map(|&net| first_net + net).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let directions = netlist.pins.iter()
+            .map(|pins| pins.iter().map(|pin| pin.direction).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        let index = self.instances.len();
+        self.instances.push(Instance {
+            path: path.clone(),
+            circuit: circuit.name.clone(),
+            parent,
+            nets,
+            elements: vec![None; circuit.components.len()],
+        });
+
+        for (component_index, component) in circuit.components.iter().enumerate() {
+            let pin_nets = &pin_nets[component_index];
+            let of_direction = |direction: Direction| pin_nets.iter()
+                .zip(&directions[component_index])
+                .filter(|&(_, &pin_direction)| pin_direction == direction)
+                .map(|(&net, _)| net)
+                .collect::<Vec<_>>();
+            let inputs = of_direction(Direction::Input);
+            let outputs = of_direction(Direction::Output);
+
+            let kind = match &component.kind {
+                ComponentKind::And { .. } => ElementKind::And,
+                ComponentKind::Or { .. } => ElementKind::Or,
+                ComponentKind::Not => ElementKind::Not,
+                ComponentKind::Const { value } => ElementKind::Const(*value),
+                ComponentKind::Clock { period } => ElementKind::Clock {
+                    half_period: (period / 2).max(1),
+                },
+                ComponentKind::ToggleButton => ElementKind::Source,
+                ComponentKind::Input { .. } if parent.is_none() => ElementKind::Source,
+                ComponentKind::Rom { contents, .. } => ElementKind::Rom {
+                    words: parse_rom_contents(contents)
+                        .ok_or_else(|| CircuitError::InvalidRomContents(format!("{}.components[{}]", path, component_index)))?,
+                },
+                ComponentKind::Sram { address_width, .. } => ElementKind::Sram {
+                    memory: vec![0; 1 << address_width],
+                    unknown: vec![0; 1 << address_width],
+                },
+                ComponentKind::Register { .. } => ElementKind::Register {
+                    value: 0,
+                    unknown: 0,
+                    clock: false,
+                    clock_unknown: false,
+                },
+                ComponentKind::Custom { circuit_name } => {
+                    // Checked by extracting the netlist
+                    let subcircuit = file.circuit(circuit_name).unwrap();
+                    let child_path = format!("{}.{}{}", path, circuit_name, component_index);
+                    let child = self.instantiate(file, subcircuit, child_path, Some(ComponentRef { instance: index, component: component_index }))?;
+
+                    // The pins of a custom component are the inputs and then the outputs of its circuit
+                    let (port_inputs, port_outputs) = ports(subcircuit);
+                    let child_netlist = &self.netlists[&subcircuit.name];
+                    let port_nets = port_inputs.iter().chain(&port_outputs)
+                        .map(|&port| self.instances[child].nets[child_netlist.pin_nets[port][0]])
+                        .collect::<Vec<_>>();
+                    for (&outer, inner) in pin_nets.iter().zip(port_nets) {
+                        self.union(outer, inner);
+                    }
+                    continue;
+                },
+                ComponentKind::Input { .. } | ComponentKind::Output { .. } | ComponentKind::Bargraph { .. } => continue,
+            };
+
+            let element = self.add_element(kind, inputs, outputs);
+            self.instances[index].elements[component_index] = Some(element);
+        }
+
+        Ok(index)
+    }
+}
+
+impl Simulation {
+    /// Creates a simulation of `circuit` and everything it contains
+    pub fn new(file: &CircuitFile, circuit: &Circuit) -> Result<Simulation, CircuitError> {
+        let mut builder = Builder::default();
+        builder.instantiate(file, circuit, circuit.name.clone(), None)?;
+
+        // Number the merged nets
+        let mut net_indices = HashMap::new();
+        let mut compact = Vec::with_capacity(builder.net_parents.len());
+        for net in 0..builder.net_parents.len() {
+            let root = builder.find(net);
+            let count = net_indices.len();
+            compact.push(*net_indices.entry(root).or_insert(count));
+        }
+        let mut nets = vec![Net::default(); net_indices.len()];
+
+        let mut instances = builder.instances;
+        for instance in &mut instances {
+            for net in &mut instance.nets {
+                *net = compact[*net];
+            }
+        }
+        let mut elements = builder.elements;
+        let mut clocks = Vec::new();
+        for (index, element) in elements.iter_mut().enumerate() {
+            for net in &mut element.inputs {
+                *net = compact[*net];
+                nets[*net].fanout.push(index);
+            }
+            if let ElementKind::Clock { .. } = element.kind {
+                clocks.push(index);
+            }
+        }
+        for net in &mut nets {
+            net.fanout.dedup();
+        }
+        let slots = builder.slot_nets.iter()
+            .map(|&net| Slot { net: compact[net], value: false, unknown: false, next: false, next_unknown: false })
+            .collect();
+
+        // Everything is evaluated once, so that e.g. inverters start driving 1
+        let element_count = elements.len();
+        Ok(Simulation {
+            instances,
+            netlists: builder.netlists,
+            elements,
+            slots,
+            nets,
+            clocks,
+            pending: Vec::new(),
+            dirty: (0..element_count).collect(),
+            is_dirty: vec![true; element_count],
+            time: 0,
+        })
+    }
+
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    pub fn instances(&self) -> &[Instance] {
+        &self.instances
+    }
+
+    /// Looks up an instance by its path, the top circuit has the path of its name
+    pub fn instance(&self, path: &str) -> Option<usize> {
+        self.instances.iter().position(|instance| instance.path == path)
+    }
+
+    /// The netlist of the circuit of an instance, the simulation nets are the ones returned by `net`
+    pub fn netlist(&self, instance: usize) -> &Netlist {
+        &self.netlists[&self.instances[instance].circuit]
+    }
+
+    /// Simulation net of a net of an instance's netlist
+    pub fn net(&self, instance: usize, net: usize) -> usize {
+        self.instances[instance].nets[net]
+    }
+
+    /// Value of a net, X reads as 0
+    pub fn net_value(&self, net: usize) -> bool {
+        self.nets[net].high > 0
+    }
+
+    /// Whether a net is X
+    pub fn net_is_unknown(&self, net: usize) -> bool {
+        self.nets[net].state().1
+    }
+
+    /// Value of the pin of a component called `name`
+    pub fn pin_value(&self, component: ComponentRef, name: &str) -> Option<bool> {
+        let net = self.netlist(component.instance).net_of(component.component, name)?;
+        Some(self.net_value(self.net(component.instance, net)))
+    }
+
+    /// Components of a type, searched through all instances
+    pub fn find_components(&self, type_name: &str, file: &CircuitFile) -> Vec<ComponentRef> {
+        let mut found = Vec::new();
+        for (instance_index, instance) in self.instances.iter().enumerate() {
+            if let Some(circuit) = file.circuit(&instance.circuit) {
+                for (component, _) in circuit.components.iter().enumerate().filter(|(_, component)| component.kind.type_name() == type_name) {
+                    found.push(ComponentRef { instance: instance_index, component });
+                }
+            }
+        }
+        found
+    }
+
+    // Value of the `input` or `output` component labelled `label`
+    fn port_net(&self, instance: usize, label: &str, file: &CircuitFile) -> Option<usize> {
+        let circuit = file.circuit(&self.instances[instance].circuit)?;
+        let component = circuit.components.iter().position(|component| match &component.kind {
+            ComponentKind::Input { label: port } | ComponentKind::Output { label: port } => port == label,
+            _ => false,
+        })?;
+        let net = self.netlist(instance).pin_nets[component][0];
+        Some(self.net(instance, net))
+    }
+
+    /// Value of the `input` or `output` component labelled `label` in an instance
+    pub fn port_value(&self, instance: usize, label: &str, file: &CircuitFile) -> Option<bool> {
+        self.port_net(instance, label, file).map(|net| self.net_value(net))
+    }
+
+    pub(crate) fn element(&self, component: ComponentRef) -> Option<usize> {
+        self.instances.get(component.instance)?.elements.get(component.component).copied().flatten()
+    }
+
+    // Component an element was created for
+    pub(crate) fn element_component(&self, element: usize) -> Option<ComponentRef> {
+        self.instances.iter()
+            .enumerate()
+            .find_map(|(index, instance)| {
+                let component = instance.elements.iter().position(|&candidate| candidate == Some(element))?;
+                Some(ComponentRef { instance: index, component })
+            })
+    }
+
+    // Path of the component of an element, for error messages
+    pub(crate) fn element_path(&self, element: usize) -> String {
+        match self.element_component(element) {
+            Some(component) => format!("{}.components[{}]", self.instances[component.instance].path, component.component),
+            None => format!("element {}", element),
+        }
+    }
+
+    /// Whether the outputs of an element follow its inputs without a clock: gates, ROMs and the read side of SRAMs
+    pub(crate) fn is_combinational(&self, element: usize) -> bool {
+        matches!(self.elements[element].kind, ElementKind::And | ElementKind::Or | ElementKind::Not | ElementKind::Rom { .. } | ElementKind::Sram { .. })
+    }
+
+    /// The inputs whose nets the outputs of a combinational element follow. The read side of an SRAM only depends on OE
+    /// and the address. WE and D belong to the write, which only changes the contents.
+    pub(crate) fn combinational_inputs(&self, element: usize) -> Vec<usize> {
+        let element = &self.elements[element];
+        match element.kind {
+            ElementKind::Sram { .. } => {
+                let address_width = element.inputs.len() - 2 - element.outputs.len();
+                Some(element.inputs[0]).into_iter().chain(element.inputs[2..2 + address_width].iter().copied()).collect()
+            },
+            _ => element.inputs.clone(),
+        }
+    }
+
+    // Combinational elements sorted so that every element comes after all elements that drive its combinational inputs.
+    // Uses Kahn's algorithm: an element is ready once all drivers of its inputs are.
+    pub(crate) fn combinational_order(&self) -> Result<Vec<usize>, CircuitError> {
+        let combinational = (0..self.elements.len()).filter(|&element| self.is_combinational(element)).collect::<Vec<_>>();
+        let mut drivers = vec![Vec::new(); self.nets.len()];
+        for (position, &element) in combinational.iter().enumerate() {
+            for slot in self.elements[element].outputs.clone() {
+                drivers[self.slots[slot].net].push(position);
+            }
+        }
+
+        let mut waiting = vec![0; combinational.len()];
+        let mut readers = vec![Vec::new(); combinational.len()];
+        for (position, &element) in combinational.iter().enumerate() {
+            for net in self.combinational_inputs(element) {
+                for &driver in &drivers[net] {
+                    waiting[position] += 1;
+                    readers[driver].push(position);
+                }
+            }
+        }
+
+        let mut ready = (0..combinational.len()).filter(|&position| waiting[position] == 0).collect::<VecDeque<_>>();
+        let mut order = Vec::with_capacity(combinational.len());
+        while let Some(position) = ready.pop_front() {
+            order.push(combinational[position]);
+            for &reader in &readers[position] {
+                waiting[reader] -= 1;
+                if waiting[reader] == 0 {
+                    ready.push_back(reader);
+                }
+            }
+        }
+        match (0..combinational.len()).find(|&position| waiting[position] > 0) {
+            Some(position) => Err(CircuitError::CombinationalLoop(self.element_path(combinational[position]))),
+            None => Ok(order),
+        }
+    }
+
+    pub(crate) fn elements(&self) -> &[Element] {
+        &self.elements
+    }
+
+    pub(crate) fn slots(&self) -> &[Slot] {
+        &self.slots
+    }
+
+    pub(crate) fn net_count(&self) -> usize {
+        self.nets.len()
+    }
+
+    // Drives the output of a toggle button or top-level input, the change is processed in the next step
+    fn drive(&mut self, element: usize, value: bool) {
+        let slot = self.elements[element].outputs.start;
+        self.slots[slot].next = value;
+        self.slots[slot].next_unknown = false;
+        self.set_slot(slot, value, false);
+    }
+
+    /// Sets the state of a toggle button or an `input` of the top circuit, returns `false` for any other component
+    pub fn set_source(&mut self, component: ComponentRef, value: bool) -> bool {
+        match self.element(component) {
+            Some(element) if matches!(self.elements[element].kind, ElementKind::Source) => {
+                self.drive(element, value);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Sets the `input` component labelled `label` of the top circuit
+    pub fn set_input(&mut self, label: &str, value: bool, file: &CircuitFile) -> bool {
+        let circuit = match file.circuit(&self.instances[0].circuit) {
+            Some(circuit) => circuit,
+            None => return false,
+        };
+        let component = circuit.components.iter().position(|component| matches!(&component.kind, ComponentKind::Input { label: port } if port == label));
+        match component {
+            Some(component) => self.set_source(ComponentRef { instance: 0, component }, value),
+            None => false,
+        }
+    }
+
+    /// Current value of a register
+    pub fn register(&self, component: ComponentRef) -> Option<u64> {
+        match self.elements[self.element(component)?].kind {
+            ElementKind::Register { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Bits of a register that are X
+    pub fn register_unknown(&self, component: ComponentRef) -> Option<u64> {
+        match self.elements[self.element(component)?].kind {
+            ElementKind::Register { unknown, .. } => Some(unknown),
+            _ => None,
+        }
+    }
+
+    /// Contents of an SRAM, X bits read as 0
+    pub fn memory(&self, component: ComponentRef) -> Option<&[u64]> {
+        match &self.elements[self.element(component)?].kind {
+            ElementKind::Sram { memory, .. } => Some(memory),
+            _ => None,
+        }
+    }
+
+    /// Bits of every SRAM word that are X
+    pub fn memory_unknown(&self, component: ComponentRef) -> Option<&[u64]> {
+        match &self.elements[self.element(component)?].kind {
+            ElementKind::Sram { unknown, .. } => Some(unknown),
+            _ => None,
+        }
+    }
+
+    /// Puts every register and every SRAM word into the unknown state X, e.g. right after creating the simulation to
+    /// find out what a circuit does without a reset
+    pub fn make_unknown(&mut self) {
+        for index in 0..self.elements.len() {
+            let width = self.elements[index].outputs.len();
+            match &mut self.elements[index].kind {
+                ElementKind::Register { value, unknown, .. } => {
+                    *value = 0;
+                    *unknown = mask(width);
+                },
+                ElementKind::Sram { memory, unknown } => {
+                    for word in memory.iter_mut() {
+                        *word = 0;
+                    }
+                    for word in unknown.iter_mut() {
+                        *word = mask(width);
+                    }
+                },
+                _ => continue,
+            }
+            self.mark_dirty(index);
+        }
+    }
+
+    /// Replaces the contents of a ROM, fails if the component isn't a ROM or `words` don't fit into it
+    pub fn load_rom(&mut self, component: ComponentRef, words: &[u16]) -> Result<(), CircuitError> {
+        let element = self.element(component).ok_or(CircuitError::NotRom)?;
+        // OE and the address
+        let capacity = 1 << (self.elements[element].inputs.len() - 1);
+        match &mut self.elements[element].kind {
+            ElementKind::Rom { .. } if words.len() > capacity => Err(CircuitError::RomOverflow(words.len(), capacity)),
+            ElementKind::Rom { words: contents } => {
+                *contents = words.to_vec();
+                self.mark_dirty(element);
+                Ok(())
+            },
+            _ => Err(CircuitError::NotRom),
+        }
+    }
+
+    /// Period of the first clock of the top circuit
+    pub fn clock_period(&self) -> Option<u64> {
+        self.clocks.first().map(|&element| match self.elements[element].kind {
+            ElementKind::Clock { half_period } => 2 * half_period,
+            _ => unreachable!(),
+        })
+    }
+
+    fn mark_dirty(&mut self, element: usize) {
+        if !self.is_dirty[element] {
+            self.is_dirty[element] = true;
+            self.dirty.push(element);
+        }
+    }
+
+    fn set_slot(&mut self, slot: usize, value: bool, unknown: bool) {
+        let Slot { net, value: old, unknown: old_unknown, .. } = self.slots[slot];
+        if old == value && old_unknown == unknown {
+            return;
+        }
+        self.slots[slot].value = value;
+        self.slots[slot].unknown = unknown;
+
+        let previous = self.nets[net].state();
+        let net_state = &mut self.nets[net];
+        if value != old {
+            if value {
+                net_state.high += 1;
+            } else {
+                net_state.high -= 1;
+            }
+        }
+        if unknown != old_unknown {
+            if unknown {
+                net_state.unknown += 1;
+            } else {
+                net_state.unknown -= 1;
+            }
+        }
+        if self.nets[net].state() != previous {
+            for index in 0..self.nets[net].fanout.len() {
+                let element = self.nets[net].fanout[index];
+                self.mark_dirty(element);
+            }
+        }
+    }
+
+    // Computes the outputs of an element from its inputs and schedules the changes for the next step
+    fn evaluate(&mut self, index: usize) {
+        let nets = &self.nets;
+        let state = |net: usize| nets[net].state();
+        let word = |bits: &[usize]| bits.iter()
+            .enumerate()
+            .fold(Word::known(0), |word, (bit, &net)| {
+                let (value, unknown) = state(net);
+                Word {
+                    value: word.value | (value as u64) << bit,
+                    unknown: word.unknown | (unknown as u64) << bit,
+                }
+            });
+        let bit = |(value, unknown): (bool, bool)| Word { value: value as u64, unknown: unknown as u64 };
+
+        let element = &mut self.elements[index];
+        let inputs = &element.inputs;
+        let width = element.outputs.len();
+        let word = match &mut element.kind {
+            ElementKind::And => {
+                if inputs.iter().any(|&net| state(net) == (false, false)) {
+                    Word::known(0)
+                } else {
+                    inputs.iter().fold(Word::known(1), |word, &net| if state(net).1 { bit((false, true)) } else { word })
+                }
+            },
+            ElementKind::Or => {
+                if inputs.iter().any(|&net| state(net).0) {
+                    Word::known(1)
+                } else {
+                    inputs.iter().fold(Word::known(0), |word, &net| if state(net).1 { bit((false, true)) } else { word })
+                }
+            },
+            ElementKind::Not => match state(inputs[0]) {
+                (_, true) => bit((false, true)),
+                (value, false) => Word::known(!value as u64),
+            },
+            ElementKind::Const(constant) => Word::known(*constant as u64),
+            // Driven from outside
+            ElementKind::Clock { .. } | ElementKind::Source => return,
+            ElementKind::Rom { words } => {
+                let enable = state(inputs[0]);
+                if enable == (false, false) {
+                    Word::known(0)
+                } else {
+                    let data = read_memory(word(&inputs[1..]), |address| Word::known(words.get(address).copied().unwrap_or(0) as u64));
+                    output_enable(data, enable)
+                }
+            },
+            ElementKind::Sram { memory, unknown } => {
+                let address_width = inputs.len() - 2 - width;
+                let address = word(&inputs[2..2 + address_width]);
+                let write_enable = state(inputs[1]);
+                if write_enable != (false, false) {
+                    let data = word(&inputs[2 + address_width..]);
+                    if write_enable == (true, false) && address.unknown == 0 {
+                        memory[address.value as usize] = data.value;
+                        unknown[address.value as usize] = data.unknown;
+                    } else {
+                        // The write may have happened at any of the addresses, or not at all
+                        for_each_address(address, |address| {
+                            let merged = Word { value: memory[address], unknown: unknown[address] }.merge(data);
+                            memory[address] = merged.value;
+                            unknown[address] = merged.unknown;
+                        });
+                    }
+                }
+
+                let enable = state(inputs[0]);
+                if enable == (false, false) {
+                    Word::known(0)
+                } else {
+                    let data = read_memory(address, |address| Word { value: memory[address], unknown: unknown[address] });
+                    output_enable(data, enable)
+                }
+            },
+            ElementKind::Register { value: register, unknown, clock, clock_unknown } => {
+                let (clear, enable, clock_now) = (state(inputs[0]), state(inputs[1]), state(inputs[2]));
+                let rising = !*clock_unknown && !*clock && clock_now == (true, false);
+                // An edge from or to X may have been a rising edge
+                let maybe_rising = !rising && (*clock, *clock_unknown) != clock_now
+                    && (*clock_unknown || !*clock) && (clock_now.0 || clock_now.1);
+                *clock = clock_now.0;
+                *clock_unknown = clock_now.1;
+
+                let old = Word { value: *register, unknown: *unknown };
+                let mut new = if clear == (true, false) {
+                    Word::known(0)
+                } else if rising && enable == (true, false) {
+                    word(&inputs[3..])
+                } else if (rising || maybe_rising) && enable != (false, false) {
+                    old.merge(word(&inputs[3..]))
+                } else {
+                    old
+                };
+                if clear.1 {
+                    new = new.merge(Word::known(0));
+                }
+                *register = new.value;
+                *unknown = new.unknown;
+                new
+            },
+        };
+
+        for (bit, slot) in self.elements[index].outputs.clone().enumerate() {
+            let unknown = word.unknown & (1 << bit) != 0;
+            let output = word.value & (1 << bit) != 0;
+            let Slot { next, next_unknown, .. } = self.slots[slot];
+            if next != output || next_unknown != unknown {
+                self.slots[slot].next = output;
+                self.slots[slot].next_unknown = unknown;
+                self.pending.push(slot);
+            }
+        }
+    }
+
+    /// Advances the simulation by one time step
+    pub fn step(&mut self) {
+        for index in 0..self.clocks.len() {
+            let element = self.clocks[index];
+            if let ElementKind::Clock { half_period } = self.elements[element].kind {
+                if self.time.is_multiple_of(half_period) {
+                    self.drive(element, (self.time / half_period).is_multiple_of(2));
+                }
+            }
+        }
+
+        for slot in mem::take(&mut self.pending) {
+            let Slot { next, next_unknown, .. } = self.slots[slot];
+            self.set_slot(slot, next, next_unknown);
+        }
+
+        let dirty = mem::take(&mut self.dirty);
+        for &element in &dirty {
+            self.is_dirty[element] = false;
+        }
+        for element in dirty {
+            self.evaluate(element);
+        }
+
+        self.time += 1;
+    }
+
+    pub fn run(&mut self, steps: u64) {
+        for _ in 0..steps {
+            self.step();
+        }
+    }
+
+    /// Runs for a number of periods of the first clock, returns `false` if there is no clock
+    pub fn run_cycles(&mut self, cycles: u64) -> bool {
+        match self.clock_period() {
+            Some(period) => {
+                self.run(cycles * period);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Whether no changes are pending, only the clocks can change the state of a stable circuit
+    pub fn is_stable(&self) -> bool {
+        self.pending.is_empty() && self.dirty.is_empty()
+    }
+
+    /// Steps until the circuit is stable, returns the number of steps or `None` if it oscillates for longer than
+    /// `max_steps`
+    pub fn settle(&mut self, max_steps: u64) -> Option<u64> {
+        for steps in 0..=max_steps {
+            if self.is_stable() {
+                return Some(steps);
+            }
+            self.step();
+        }
+        None
+    }
+}
@@ -0,0 +1,144 @@
+
+use crate::format::{Circuit, CircuitFile, ComponentKind};
+
+/// Number of gates of one type (`and`, `or` or `not`) and fan-in
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GateCount {
+    pub kind: &'static str,
+    pub inputs: u32,
+    pub count: u64,
+}
+
+/// Number of ROMs or SRAMs (`rom` or `sram`) of one size
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemoryCount {
+    pub kind: &'static str,
+    pub address_width: u32,
+    pub word_size: u32,
+    pub count: u64,
+}
+
+impl MemoryCount {
+    pub fn bits(&self) -> u64 {
+        self.count * (1 << self.address_width) * self.word_size as u64
+    }
+}
+
+/// The hardware a circuit is made of, either of the circuit alone or including everything in its `custom` components
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Resources {
+    /// Sorted by type and fan-in
+    pub gates: Vec<GateCount>,
+    pub registers: u64,
+    pub register_bits: u64,
+    /// Sorted by type and size
+    pub memories: Vec<MemoryCount>,
+    pub components: u64,
+    /// Wire segments
+    pub connections: u64,
+}
+
+impl Resources {
+    /// Resources of the components of a circuit, `custom` components only count as components
+    pub fn of(circuit: &Circuit) -> Resources {
+        let mut resources = Resources {
+            components: circuit.components.len() as u64,
+            connections: circuit.connections.len() as u64,
+            ..Resources::default()
+        };
+        for component in &circuit.components {
+            match component.kind {
+                ComponentKind::And { inputs } => resources.add_gates("and", inputs, 1),
+                ComponentKind::Or { inputs } => resources.add_gates("or", inputs, 1),
+                ComponentKind::Not => resources.add_gates("not", 1, 1),
+                ComponentKind::Register { width } => {
+                    resources.registers += 1;
+                    resources.register_bits += width as u64;
+                },
+                ComponentKind::Rom { address_width, word_size, .. } => resources.add_memories("rom", address_width, word_size, 1),
+                ComponentKind::Sram { address_width, data_width } => resources.add_memories("sram", address_width, data_width, 1),
+                _ => {},
+            }
+        }
+        resources
+    }
+
+    /// Resources of a circuit and everything it contains
+    pub fn total(file: &CircuitFile, circuit: &Circuit) -> Resources {
+        let mut resources = Resources::of(circuit);
+        for component in &circuit.components {
+            if let ComponentKind::Custom { circuit_name } = &component.kind {
+                if let Some(subcircuit) = file.circuit(circuit_name) {
+                    resources.add(&Resources::total(file, subcircuit), 1);
+                }
+            }
+        }
+        resources
+    }
+
+    fn add_gates(&mut self, kind: &'static str, inputs: u32, count: u64) {
+        match self.gates.binary_search_by_key(&(kind, inputs), |gate| (gate.kind, gate.inputs)) {
+            Ok(index) => self.gates[index].count += count,
+            Err(index) => self.gates.insert(index, GateCount { kind, inputs, count }),
+        }
+    }
+
+    fn add_memories(&mut self, kind: &'static str, address_width: u32, word_size: u32, count: u64) {
+        let key = (kind, address_width, word_size);
+        match self.memories.binary_search_by_key(&key, |memory| (memory.kind, memory.address_width, memory.word_size)) {
+            Ok(index) => self.memories[index].count += count,
+            Err(index) => self.memories.insert(index, MemoryCount { kind, address_width, word_size, count }),
+        }
+    }
+
+    /// Adds the resources of `times` copies of `other`
+    pub fn add(&mut self, other: &Resources, times: u64) {
+        for gate in &other.gates {
+            self.add_gates(gate.kind, gate.inputs, gate.count * times);
+        }
+        for memory in &other.memories {
+            self.add_memories(memory.kind, memory.address_width, memory.word_size, memory.count * times);
+        }
+        self.registers += other.registers * times;
+        self.register_bits += other.register_bits * times;
+        self.components += other.components * times;
+        self.connections += other.connections * times;
+    }
+
+    /// Number of gates of a type, of any fan-in
+    pub fn gate_count(&self, kind: &str) -> u64 {
+        self.gates.iter().filter(|gate| gate.kind == kind).map(|gate| gate.count).sum()
+    }
+
+    pub fn total_gates(&self) -> u64 {
+        self.gates.iter().map(|gate| gate.count).sum()
+    }
+
+    /// Bits of all ROMs or all SRAMs
+    pub fn memory_bits(&self, kind: &str) -> u64 {
+        self.memories.iter().filter(|memory| memory.kind == kind).map(MemoryCount::bits).sum()
+    }
+}
+
+/// Every circuit used by `circuit`, including itself, with the number of times it is instantiated. Circuits are in
+/// the order they are first found in, `circuit` comes first.
+pub fn instance_counts<'a>(file: &'a CircuitFile, circuit: &'a Circuit) -> Vec<(&'a Circuit, u64)> {
+    let mut counts: Vec<(&Circuit, u64)> = Vec::new();
+    let mut pending = vec![circuit];
+    while let Some(circuit) = pending.pop() {
+        match counts.iter_mut().find(|(counted, _)| counted.name == circuit.name) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((circuit, 1)),
+        }
+        let subcircuits = circuit.components.iter()
+            .filter_map(|component| match &component.kind {
+                ComponentKind::Custom { circuit_name } => file.circuit(circuit_name),
+                _ => None,
+            });
+        let start = pending.len();
+        pending.extend(subcircuits);
+        // Visit the components in their order
+        pending[start..].reverse();
+    }
+    counts
+}
@@ -0,0 +1,108 @@
+
+use crate::format::{Circuit, CircuitFile, Component, ComponentKind, Connection};
+use crate::pins::component_pins;
+use crate::sim::ComponentRef;
+
+/// Builds small circuits for tests. Every component gets a column and a row of its own and wires run straight from pin
+/// to pin, so they only meet at the pins they connect.
+pub struct Builder {
+    pub file: CircuitFile,
+    // The circuit components and wires are added to
+    editing: usize,
+}
+
+impl Builder {
+    /// Starts with an empty circuit `main`
+    pub fn new() -> Builder {
+        Builder {
+            file: CircuitFile {
+                version: 1,
+                circuits: vec![Circuit::new("main")],
+                extra: Default::default(),
+            },
+            editing: 0,
+        }
+    }
+
+    pub fn add_circuit(&mut self, name: &str) {
+        self.file.circuits.push(Circuit::new(name));
+    }
+
+    /// Adds everything from now on to the circuit `name`
+    pub fn edit(&mut self, name: &str) {
+        self.editing = self.file.circuits.iter().position(|circuit| circuit.name == name)
+            .unwrap_or_else(|| panic!("no circuit {}", name));
+    }
+
+    pub fn add(&mut self, kind: ComponentKind) -> usize {
+        let components = &mut self.file.circuits[self.editing].components;
+        let index = components.len() as i64;
+        components.push(Component::new(index * 40, index * 70 + (index * index) % 13, kind));
+        components.len() - 1
+    }
+
+    fn position(&self, component: usize, name: &str) -> (i64, i64) {
+        let component = &self.file.circuits[self.editing].components[component];
+        let pin = component_pins(component, &self.file).unwrap()
+            .into_iter()
+            .find(|pin| pin.name == name)
+            .unwrap_or_else(|| panic!("no pin {} on {:?}", name, component.kind));
+        (pin.x, pin.y)
+    }
+
+    pub fn wire(&mut self, from: &(usize, String), to: &(usize, String)) {
+        let (x1, y1) = self.position(from.0, &from.1);
+        let (x2, y2) = self.position(to.0, &to.1);
+        self.file.circuits[self.editing].connections.push(Connection::new(x1, y1, x2, y2));
+    }
+
+    /// Drives the inputs `{prefix}0` and up of a component with the bits of `value`, each 1 from a const of its own
+    pub fn constant(&mut self, component: usize, prefix: &str, bits: u32, value: u64) {
+        for bit in (0..bits).filter(|&bit| value & (1 << bit) != 0) {
+            let one = self.add(ComponentKind::Const { value: true });
+            self.wire(&pin(one, "out"), &pin(component, &format!("{}{}", prefix, bit)));
+        }
+    }
+
+    /// Adds a gate whose inputs are wired to `inputs`, returns its output
+    pub fn gate(&mut self, kind: ComponentKind, inputs: &[(usize, String)]) -> (usize, String) {
+        let gate = self.add(kind.clone());
+        for (index, input) in inputs.iter().enumerate() {
+            let name = if kind == ComponentKind::Not { "in".to_string() } else { format!("in{}", index) };
+            self.wire(input, &(gate, name));
+        }
+        (gate, "out".to_string())
+    }
+
+    pub fn not(&mut self, input: (usize, String)) -> (usize, String) {
+        self.gate(ComponentKind::Not, &[input])
+    }
+
+    pub fn and(&mut self, inputs: &[(usize, String)]) -> (usize, String) {
+        self.gate(ComponentKind::And { inputs: inputs.len() as u32 }, inputs)
+    }
+
+    pub fn or(&mut self, inputs: &[(usize, String)]) -> (usize, String) {
+        self.gate(ComponentKind::Or { inputs: inputs.len() as u32 }, inputs)
+    }
+
+    /// The top-level circuit `main`
+    pub fn circuit(&self) -> &Circuit {
+        &self.file.circuits[0]
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Builder {
+        Builder::new()
+    }
+}
+
+pub fn pin(component: usize, name: &str) -> (usize, String) {
+    (component, name.to_string())
+}
+
+/// A component of the top-level circuit
+pub fn component(component: usize) -> ComponentRef {
+    ComponentRef { instance: 0, component }
+}
@@ -0,0 +1,208 @@
+
+use crate::format::CircuitError;
+use crate::sim::{ComponentRef, ElementKind, Simulation};
+
+/// Edge of the clock
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Edge {
+    Rising,
+    Falling,
+}
+
+impl Edge {
+    fn flag(self) -> u8 {
+        match self {
+            Edge::Rising => RISING,
+            Edge::Falling => FALLING,
+        }
+    }
+}
+
+// Which edges of the clock make a net rise
+const RISING: u8 = 1;
+const FALLING: u8 = 2;
+
+/// A combinational path from a register (or the clock itself) to a register or SRAM input
+#[derive(Clone, Debug)]
+pub struct TimingPath {
+    /// The register that changes first, or the clock
+    pub launch: ComponentRef,
+    pub launch_edge: Edge,
+    /// The register or SRAM at the end
+    pub capture: ComponentRef,
+    /// `None` for an SRAM, which writes while WE is high instead of at an edge
+    pub capture_edge: Option<Edge>,
+    /// Time from the clock edge until the end of the path settles, including the delay of the clock to the launching
+    /// register and of the register itself
+    pub arrival: u64,
+    /// Delay of the clock to the capturing register, the path may take this much longer
+    pub capture_clock_delay: u64,
+    /// Every component along the path, starting with `launch` and ending with the component that drives the input of
+    /// `capture`
+    pub components: Vec<ComponentRef>,
+    /// Shortest clock period at which the path settles in time
+    pub min_period: u64,
+}
+
+/// Longest combinational paths of a circuit, measured in the gate delays of `Simulation`.
+///
+/// Every gate, ROM, SRAM and register delays a change by one step, like in the simulation. A path starts when a
+/// clock edge reaches a register and ends at the D or EN input of a register or at an input of an SRAM. It has to
+/// settle before the next edge that clocks its end: a full period later for registers clocked by the same edge, half a
+/// period later for registers clocked by the other edge, like those behind an inverted clock. SRAMs write while WE is
+/// high, so their inputs have to settle within half a period. The output of an SRAM only follows OE and the address, a
+/// path into D or WE ends at the SRAM. All clocks count as one clock.
+#[derive(Clone, Debug)]
+pub struct TimingAnalysis {
+    /// The worst path between every pair of components, the longest first
+    pub paths: Vec<TimingPath>,
+}
+
+impl TimingAnalysis {
+    pub fn new(simulation: &Simulation) -> Result<TimingAnalysis, CircuitError> {
+        let order = simulation.combinational_order()?;
+        let elements = simulation.elements();
+        let slots = simulation.slots();
+        let output_nets = |element: usize| elements[element].outputs.clone().map(|slot| slots[slot].net).collect::<Vec<_>>();
+        let nets = simulation.net_count();
+
+        // Delay of the clock to every net it reaches without passing a register, and the edges that make the net rise
+        let mut clock_delay = vec![0; nets];
+        let mut clock_edges = vec![0u8; nets];
+        let clocks = (0..elements.len())
+            .filter(|&element| matches!(elements[element].kind, ElementKind::Clock { .. }))
+            .collect::<Vec<_>>();
+        for &clock in &clocks {
+            for net in output_nets(clock) {
+                clock_edges[net] = RISING;
+            }
+        }
+        for &element in &order {
+            let inputs = simulation.combinational_inputs(element);
+            let edges = inputs.iter().fold(0, |edges, &net| edges | clock_edges[net]);
+            if edges == 0 {
+                continue;
+            }
+            let edges = match elements[element].kind {
+                ElementKind::Not => (edges & RISING) << 1 | (edges & FALLING) >> 1,
+                _ => edges,
+            };
+            let delay = inputs.iter().filter(|&&net| clock_edges[net] != 0).map(|&net| clock_delay[net]).max().unwrap_or(0) + 1;
+            for net in output_nets(element) {
+                clock_edges[net] |= edges;
+                clock_delay[net] = clock_delay[net].max(delay);
+            }
+        }
+
+        // Register inputs are D and EN, the clock is input 2
+        let registers = (0..elements.len())
+            .filter(|&element| matches!(elements[element].kind, ElementKind::Register { .. }))
+            .filter(|&element| clock_edges[elements[element].inputs[2]] != 0)
+            .collect::<Vec<_>>();
+        let srams = (0..elements.len())
+            .filter(|&element| matches!(elements[element].kind, ElementKind::Sram { .. }))
+            .collect::<Vec<_>>();
+
+        let component = |element: usize| simulation.element_component(element).unwrap_or(ComponentRef { instance: 0, component: 0 });
+        let mut paths: Vec<TimingPath> = Vec::new();
+
+        for &edge in &[Edge::Rising, Edge::Falling] {
+            // Latest time after the edge at which every net may still change, and where that change came from
+            let mut arrival: Vec<Option<u64>> = vec![None; nets];
+            let mut from: Vec<Option<(usize, Option<usize>)>> = vec![None; nets];
+            for &clock in &clocks {
+                for net in output_nets(clock) {
+                    arrival[net] = Some(0);
+                    from[net] = Some((clock, None));
+                }
+            }
+            for &register in &registers {
+                let clock = elements[register].inputs[2];
+                if clock_edges[clock] & edge.flag() != 0 {
+                    for net in output_nets(register) {
+                        arrival[net] = Some(clock_delay[clock] + 1);
+                        from[net] = Some((register, None));
+                    }
+                }
+            }
+
+            for &element in &order {
+                let latest = simulation.combinational_inputs(element).into_iter()
+                    .filter_map(|net| Some((arrival[net]?, net)))
+                    .max();
+                if let Some((time, input)) = latest {
+                    for net in output_nets(element) {
+                        if arrival[net].is_none_or(|current| time + 1 > current) {
+                            arrival[net] = Some(time + 1);
+                            from[net] = Some((element, Some(input)));
+                        }
+                    }
+                }
+            }
+
+            // Walks back from an input to the register or clock where its latest change started
+            let trace = |mut net: usize| {
+                let mut components = Vec::new();
+                while let Some((element, input)) = from[net] {
+                    components.push(component(element));
+                    match input {
+                        Some(input) => net = input,
+                        None => break,
+                    }
+                }
+                components.reverse();
+                components
+            };
+
+            let mut add_path = |capture: usize, capture_edge: Option<Edge>, net: usize, capture_clock_delay: u64, min_period: u64| {
+                let time = match arrival[net] {
+                    Some(time) if min_period > 0 => time,
+                    _ => return,
+                };
+                let components = trace(net);
+                let launch = components[0];
+                let capture = component(capture);
+                match paths.iter_mut().find(|path| path.launch == launch && path.capture == capture && path.launch_edge == edge) {
+                    Some(path) if path.min_period >= min_period => {},
+                    Some(path) => *path = TimingPath { launch, launch_edge: edge, capture, capture_edge, arrival: time, capture_clock_delay, components, min_period },
+                    None => paths.push(TimingPath { launch, launch_edge: edge, capture, capture_edge, arrival: time, capture_clock_delay, components, min_period }),
+                }
+            };
+
+            for &register in &registers {
+                let inputs = &elements[register].inputs;
+                let clock = inputs[2];
+                let delay = clock_delay[clock];
+                for &net in inputs[3..].iter().chain(Some(&inputs[1])) {
+                    let time = match arrival[net] {
+                        Some(time) => time,
+                        None => continue,
+                    };
+                    for &capture_edge in &[Edge::Rising, Edge::Falling] {
+                        if clock_edges[clock] & capture_edge.flag() == 0 {
+                            continue;
+                        }
+                        // The same edge comes again after a period, the other one after half a period
+                        let factor = if capture_edge == edge { 1 } else { 2 };
+                        add_path(register, Some(capture_edge), net, delay, time.saturating_sub(delay) * factor);
+                    }
+                }
+            }
+            for &sram in &srams {
+                for &net in &elements[sram].inputs[1..] {
+                    add_path(sram, None, net, 0, arrival[net].unwrap_or(0) * 2);
+                }
+            }
+        }
+
+        paths.sort_by(|a, b| b.min_period.cmp(&a.min_period).then(b.arrival.cmp(&a.arrival)));
+        Ok(TimingAnalysis { paths })
+    }
+
+    /// Shortest clock period at which every path settles in time, rounded up to an even number like the periods of
+    /// LogicSimulator clocks
+    pub fn min_period(&self) -> u64 {
+        let period = self.paths.first().map_or(2, |path| path.min_period.max(2));
+        period + period % 2
+    }
+}
@@ -0,0 +1,115 @@
+
+// The compiled backend against the event-driven simulation
+
+use circuit::{CircuitError, CompiledSimulation, ComponentKind, Netlist, Simulation};
+use circuit::testing::{Builder, component, pin};
+
+// A register and an SRAM that take turns: in even cycles the SRAM is read at the address in `r` and the word is loaded
+// into `s`, in odd cycles a word computed from `s` is written back at the next address. The data bus of the SRAM carries
+// its own output while reading, like the data bus of CPU.hdl.
+fn read_modify_write() -> (Builder, [usize; 4]) {
+    let mut builder = Builder::new();
+    let clock = builder.add(ComponentKind::Clock { period: 40 });
+    let one = builder.add(ComponentKind::Const { value: true });
+    let phase = builder.add(ComponentKind::Register { width: 1 });
+    let address = builder.add(ComponentKind::Register { width: 2 });
+    let data = builder.add(ComponentKind::Register { width: 2 });
+    let memory = builder.add(ComponentKind::Sram { address_width: 2, data_width: 2 });
+
+    let reading = builder.not(pin(phase, "Q0"));
+    builder.wire(&reading, &pin(phase, "D0"));
+    for register in &[phase, address, data] {
+        builder.wire(&pin(clock, "out"), &pin(*register, "C"));
+    }
+    builder.wire(&pin(one, "out"), &pin(phase, "EN"));
+    builder.wire(&reading, &pin(address, "EN"));
+    builder.wire(&reading, &pin(data, "EN"));
+
+    // Two-bit counter
+    let low = builder.not(pin(address, "Q0"));
+    builder.wire(&low, &pin(address, "D0"));
+    let high = builder.not(pin(address, "Q1"));
+    let carry = builder.and(&[pin(address, "Q1"), low.clone()]);
+    let sum = builder.and(&[high, pin(address, "Q0")]);
+    let next = builder.or(&[carry, sum]);
+    builder.wire(&next, &pin(address, "D1"));
+
+    // The SRAM writes in the second half of odd cycles
+    let clock_low = builder.not(pin(clock, "out"));
+    let write_enable = builder.and(&[clock_low, pin(phase, "Q0")]);
+    builder.wire(&write_enable, &pin(memory, "WE"));
+    builder.wire(&reading, &pin(memory, "OE"));
+    builder.wire(&pin(address, "Q0"), &pin(memory, "A0"));
+    builder.wire(&pin(address, "Q1"), &pin(memory, "A1"));
+
+    let written = [builder.not(pin(data, "Q0")), pin(data, "Q0")];
+    for (bit, written) in written.iter().enumerate() {
+        let read = builder.and(&[pin(memory, &format!("Q{}", bit)), reading.clone()]);
+        let write = builder.and(&[written.clone(), pin(phase, "Q0")]);
+        let bus = builder.or(&[read, write]);
+        builder.wire(&bus, &pin(memory, &format!("D{}", bit)));
+        builder.wire(&bus, &pin(data, &format!("D{}", bit)));
+    }
+
+    (builder, [phase, address, data, memory])
+}
+
+#[test]
+fn sram_output_feeding_its_input_is_no_loop() {
+    let mut builder = Builder::new();
+    let memory = builder.add(ComponentKind::Sram { address_width: 1, data_width: 1 });
+    builder.wire(&pin(memory, "Q0"), &pin(memory, "D0"));
+    let simulation = Simulation::new(&builder.file, builder.circuit()).unwrap();
+    assert!(CompiledSimulation::new(&simulation, 1).is_ok());
+}
+
+#[test]
+fn gates_feeding_back_are_a_loop() {
+    let mut builder = Builder::new();
+    let gate = builder.add(ComponentKind::Not);
+    builder.wire(&pin(gate, "out"), &pin(gate, "in"));
+    let simulation = Simulation::new(&builder.file, builder.circuit()).unwrap();
+    assert!(matches!(CompiledSimulation::new(&simulation, 1), Err(CircuitError::CombinationalLoop(_))));
+}
+
+#[test]
+fn compiled_matches_event_driven() {
+    let (builder, [phase, address, data, memory]) = read_modify_write();
+    assert!(builder.circuit().components.len() > 20);
+    let netlist = Netlist::extract(&builder.file, builder.circuit()).unwrap();
+    assert_eq!(netlist.issues, Vec::new());
+
+    let mut simulation = Simulation::new(&builder.file, builder.circuit()).unwrap();
+    let mut compiled = CompiledSimulation::new(&simulation, 1).unwrap();
+    let mut contents = Vec::new();
+    for cycle in 0..64 {
+        simulation.run_cycles(1);
+        compiled.run_cycles(1);
+        for &register in &[phase, address, data] {
+            assert_eq!(compiled.register(component(register)), simulation.register(component(register)), "register {} after cycle {}", register, cycle);
+        }
+        assert_eq!(compiled.memory(component(memory)), simulation.memory(component(memory)), "memory after cycle {}", cycle);
+        contents.push(simulation.memory(component(memory)).unwrap().to_vec());
+    }
+
+    // Every address was written, with words that change over time
+    contents.sort();
+    contents.dedup();
+    assert!(contents.len() > 4);
+    assert!(contents.last().unwrap().iter().all(|&word| word != 0));
+}
+
+#[test]
+fn compiled_roms_only_take_what_they_can_address() {
+    let mut builder = Builder::new();
+    let one = builder.add(ComponentKind::Const { value: true });
+    let rom = builder.add(ComponentKind::Rom { address_width: 1, word_size: 2, contents: String::new() });
+    builder.wire(&pin(one, "out"), &pin(rom, "OE"));
+    builder.wire(&pin(one, "out"), &pin(rom, "A0"));
+    let simulation = Simulation::new(&builder.file, builder.circuit()).unwrap();
+    let mut compiled = CompiledSimulation::new(&simulation, 1).unwrap();
+
+    assert!(compiled.load_rom(component(rom), &[1, 2]).is_ok());
+    assert!(matches!(compiled.load_rom(component(rom), &[1, 2, 3]), Err(CircuitError::RomOverflow(3, 2))));
+    assert!(matches!(compiled.load_rom(component(one), &[1]), Err(CircuitError::NotRom)));
+}
@@ -0,0 +1,57 @@
+
+// Reading and writing circuit files has to keep everything, including the fields that aren't modelled
+
+use std::fs;
+use std::path::Path;
+
+use circuit::{CircuitError, CircuitFile};
+use serde_json::Value;
+
+fn round_trip(text: &str) -> (Value, Value) {
+    let file = CircuitFile::parse(text).unwrap();
+    let original = serde_json::from_str::<Value>(text).unwrap();
+    (original, file.to_json())
+}
+
+#[test]
+fn testbench_round_trips() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../src/testbench.json");
+    let text = fs::read_to_string(path).unwrap();
+    let (original, written) = round_trip(&text);
+    assert_eq!(written, original);
+}
+
+#[test]
+fn unknown_fields_are_kept() {
+    let text = r#"{"version":1,"circuits":[{"name":"main","label":"Main","components":[{"type":"const","x":0,"y":0,"value":true,"rotation":90},{"type":"not","x":30,"y":0},{"type":"output","x":60,"y":0,"label":"out","note":{"text":"result"}}],"connections":[{"x1":6,"y1":2,"x2":29,"y2":2,"color":"red"},{"x1":36,"y1":2,"x2":59,"y2":2}],"zoom":2}],"author":"someone"}"#;
+    let (original, written) = round_trip(text);
+    assert_eq!(written, original);
+    // In the same order and form as well
+    assert_eq!(written.to_string(), text);
+
+    let file = CircuitFile::parse(text).unwrap();
+    let connection = &file.circuits[0].connections[0];
+    assert_eq!(connection.extra["color"], "red");
+    assert!(file.circuits[0].connections[1].extra.is_empty());
+}
+
+#[test]
+fn missing_fields_are_reported() {
+    let text = r#"{"version":1,"circuits":[{"name":"main","label":"main","components":[],"connections":[{"x1":0,"y1":0,"x2":1}]}]}"#;
+    match CircuitFile::parse(text) {
+        Err(CircuitError::Invalid(path)) => assert_eq!(path, "circuits[0].connections[0].y2"),
+        result => panic!("unexpected result {:?}", result),
+    }
+}
+
+#[test]
+fn address_widths_are_limited() {
+    let sram = |width: u64| format!(r#"{{"version":1,"circuits":[{{"name":"main","label":"main","components":[{{"type":"sram","x":0,"y":0,"addresswidth":{},"datawidth":8}}],"connections":[]}}]}}"#, width);
+    assert!(CircuitFile::parse(&sram(24)).is_ok());
+    for width in &[25, 64, 1 << 32] {
+        match CircuitFile::parse(&sram(*width)) {
+            Err(CircuitError::Invalid(path)) => assert_eq!(path, "circuits[0].components[0].addresswidth"),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
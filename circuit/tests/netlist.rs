@@ -0,0 +1,52 @@
+
+// Nets and issues of wire geometry that the extraction has to get right
+
+use circuit::{Circuit, CircuitFile, Component, ComponentKind, Connection, Netlist, NetlistIssue, PinRef};
+
+// A const at (0, 0) with its output at (6, 2) and a not at (30, 0) with its input at (29, 2)
+fn const_and_not(connections: &[(i64, i64, i64, i64)]) -> CircuitFile {
+    let mut circuit = Circuit::new("main");
+    circuit.components.push(Component::new(0, 0, ComponentKind::Const { value: true }));
+    circuit.components.push(Component::new(30, 0, ComponentKind::Not));
+    circuit.connections = connections.iter().map(|&(x1, y1, x2, y2)| Connection::new(x1, y1, x2, y2)).collect();
+    CircuitFile {
+        version: 1,
+        circuits: vec![circuit],
+        extra: Default::default(),
+    }
+}
+
+fn pins_on_one_net(netlist: &Netlist) -> bool {
+    netlist.net_of(0, "out") == netlist.net_of(1, "in")
+}
+
+#[test]
+fn overlapping_wires_are_a_short() {
+    let file = const_and_not(&[(6, 2, 20, 2), (15, 2, 29, 2)]);
+    let netlist = Netlist::extract(&file, &file.circuits[0]).unwrap();
+    assert!(pins_on_one_net(&netlist));
+    assert_eq!(netlist.issues, vec![NetlistIssue::Short {
+        connections: (0, 1),
+        pins: vec![PinRef { component: 0, pin: 0 }, PinRef { component: 1, pin: 0 }],
+    }]);
+}
+
+#[test]
+fn wires_meeting_at_their_ends_are_no_short() {
+    let file = const_and_not(&[(6, 2, 20, 2), (20, 2, 29, 2)]);
+    let netlist = Netlist::extract(&file, &file.circuits[0]).unwrap();
+    assert!(pins_on_one_net(&netlist));
+    assert_eq!(netlist.issues, Vec::new());
+}
+
+#[test]
+fn junctions_and_crossings() {
+    // A branch from the middle of the wire is connected, a wire that only crosses it isn't
+    let file = const_and_not(&[(6, 2, 29, 2), (15, 2, 15, 10), (20, -5, 20, 10)]);
+    let netlist = Netlist::extract(&file, &file.circuits[0]).unwrap();
+    assert!(pins_on_one_net(&netlist));
+    let net = netlist.net_of(0, "out").unwrap();
+    assert_eq!(netlist.nets[net].connections, vec![0, 1]);
+    assert_eq!(netlist.issues.len(), 3);
+    assert!(netlist.issues.contains(&NetlistIssue::DanglingWire { connection: 1, x: 15, y: 10 }));
+}
@@ -0,0 +1,157 @@
+
+// The event-driven simulation on its own: clocked logic reading a ROM, inputs, settling and ports of blocks
+
+use circuit::{CircuitError, ComponentKind, Simulation};
+use circuit::testing::{Builder, component, pin};
+
+// A 2-bit counter addresses a ROM, a register loads the word at every rising edge
+fn rom_reader() -> (Builder, usize, usize) {
+    let mut builder = Builder::new();
+    let clock = builder.add(ComponentKind::Clock { period: 40 });
+    let one = builder.add(ComponentKind::Const { value: true });
+    let counter = builder.add(ComponentKind::Register { width: 2 });
+    let rom = builder.add(ComponentKind::Rom { address_width: 2, word_size: 4, contents: String::new() });
+    let data = builder.add(ComponentKind::Register { width: 4 });
+    for &register in &[counter, data] {
+        builder.wire(&pin(clock, "out"), &pin(register, "C"));
+        builder.wire(&pin(one, "out"), &pin(register, "EN"));
+    }
+
+    let low = builder.not(pin(counter, "Q0"));
+    builder.wire(&low, &pin(counter, "D0"));
+    let high = builder.not(pin(counter, "Q1"));
+    let carry = builder.and(&[pin(counter, "Q1"), low]);
+    let sum = builder.and(&[high, pin(counter, "Q0")]);
+    let next = builder.or(&[carry, sum]);
+    builder.wire(&next, &pin(counter, "D1"));
+
+    builder.wire(&pin(one, "out"), &pin(rom, "OE"));
+    builder.wire(&pin(counter, "Q0"), &pin(rom, "A0"));
+    builder.wire(&pin(counter, "Q1"), &pin(rom, "A1"));
+    for bit in 0..4 {
+        builder.wire(&pin(rom, &format!("D{}", bit)), &pin(data, &format!("D{}", bit)));
+    }
+    (builder, rom, data)
+}
+
+#[test]
+fn clocked_logic_reads_a_loaded_rom() {
+    let (builder, rom, data) = rom_reader();
+    let mut simulation = Simulation::new(&builder.file, builder.circuit()).unwrap();
+    simulation.load_rom(component(rom), &[0x3, 0x5, 0xa, 0xc]).unwrap();
+
+    let mut loaded = Vec::new();
+    for _ in 0..7 {
+        simulation.run_cycles(1);
+        loaded.push(simulation.register(component(data)).unwrap());
+    }
+    // Nothing has settled at the first edge, the counter starts counting at the second
+    assert_eq!(loaded, [0x0, 0x3, 0x5, 0xa, 0xc, 0x3, 0x5]);
+    assert_eq!(simulation.time(), 7 * 40);
+}
+
+#[test]
+fn roms_only_take_what_they_can_address() {
+    let (builder, rom, data) = rom_reader();
+    let mut simulation = Simulation::new(&builder.file, builder.circuit()).unwrap();
+    match simulation.load_rom(component(rom), &[1, 2, 3, 4, 5]) {
+        Err(error @ CircuitError::RomOverflow(5, 4)) => assert_eq!(error.to_string(), "5 words don't fit into a ROM of 4 words"),
+        result => panic!("unexpected result {:?}", result),
+    }
+    assert!(matches!(simulation.load_rom(component(data), &[1]), Err(CircuitError::NotRom)));
+}
+
+#[test]
+fn inputs_and_toggle_buttons_are_set_from_outside() {
+    let mut builder = Builder::new();
+    let a = builder.add(ComponentKind::Input { label: "a".to_string() });
+    let button = builder.add(ComponentKind::ToggleButton);
+    let output = builder.add(ComponentKind::Output { label: "y".to_string() });
+    let gate = builder.and(&[pin(a, "out"), pin(button, "out")]);
+    builder.wire(&gate, &pin(output, "in"));
+    let file = &builder.file;
+    let mut simulation = Simulation::new(file, builder.circuit()).unwrap();
+
+    assert!(simulation.set_input("a", true, file));
+    simulation.settle(10).unwrap();
+    assert_eq!(simulation.port_value(0, "a", file), Some(true));
+    assert_eq!(simulation.port_value(0, "y", file), Some(false));
+
+    assert!(simulation.set_source(component(button), true));
+    simulation.settle(10).unwrap();
+    assert_eq!(simulation.port_value(0, "y", file), Some(true));
+    assert_eq!(simulation.pin_value(component(gate.0), "out"), Some(true));
+
+    // Only sources can be set
+    assert!(!simulation.set_input("y", true, file));
+    assert!(!simulation.set_input("b", true, file));
+    assert!(!simulation.set_source(component(gate.0), true));
+}
+
+#[test]
+fn settling_takes_a_step_per_gate() {
+    let mut builder = Builder::new();
+    let input = builder.add(ComponentKind::Input { label: "a".to_string() });
+    let output = (0..3).fold(pin(input, "out"), |output, _| builder.not(output));
+    let file = &builder.file;
+    let mut simulation = Simulation::new(file, builder.circuit()).unwrap();
+    simulation.settle(10).unwrap();
+    assert!(simulation.is_stable());
+    assert_eq!(simulation.pin_value(component(output.0), "out"), Some(true));
+
+    simulation.set_input("a", true, file);
+    assert_eq!(simulation.settle(10), Some(4));
+    assert_eq!(simulation.pin_value(component(output.0), "out"), Some(false));
+    assert_eq!(simulation.settle(10), Some(0));
+}
+
+#[test]
+fn oscillation_never_settles() {
+    let mut builder = Builder::new();
+    let gate = builder.add(ComponentKind::Not);
+    builder.wire(&pin(gate, "out"), &pin(gate, "in"));
+    let mut simulation = Simulation::new(&builder.file, builder.circuit()).unwrap();
+    assert_eq!(simulation.settle(100), None);
+    assert!(!simulation.is_stable());
+}
+
+#[test]
+fn ports_of_blocks_are_merged_with_the_nets_outside() {
+    let mut builder = Builder::new();
+    builder.add_circuit("Inverter");
+    builder.edit("Inverter");
+    let input = builder.add(ComponentKind::Input { label: "in".to_string() });
+    let inverted = builder.not(pin(input, "out"));
+    let output = builder.add(ComponentKind::Output { label: "out".to_string() });
+    builder.wire(&inverted, &pin(output, "in"));
+
+    builder.edit("main");
+    let a = builder.add(ComponentKind::Input { label: "a".to_string() });
+    let first = builder.add(ComponentKind::Custom { circuit_name: "Inverter".to_string() });
+    let second = builder.add(ComponentKind::Custom { circuit_name: "Inverter".to_string() });
+    let y = builder.add(ComponentKind::Output { label: "y".to_string() });
+    builder.wire(&pin(a, "out"), &pin(first, "in"));
+    builder.wire(&pin(first, "out"), &pin(second, "in"));
+    builder.wire(&pin(second, "out"), &pin(y, "in"));
+    let file = &builder.file;
+    let mut simulation = Simulation::new(file, builder.circuit()).unwrap();
+
+    let paths = simulation.instances().iter().map(|instance| instance.path.as_str()).collect::<Vec<_>>();
+    assert_eq!(paths, ["main", "main.Inverter1", "main.Inverter2"]);
+    let first = simulation.instance("main.Inverter1").unwrap();
+    let second = simulation.instance("main.Inverter2").unwrap();
+
+    // The output of the first block and the input of the second are one net
+    let net = |instance: usize, component: usize| simulation.net(instance, simulation.netlist(instance).pin_nets[component][0]);
+    assert_eq!(net(first, output), net(second, input));
+    assert_eq!(net(0, a), net(first, input));
+    assert_eq!(net(second, output), net(0, y));
+
+    for &(value, inner) in &[(true, false), (false, true)] {
+        simulation.set_input("a", value, file);
+        simulation.settle(10).unwrap();
+        assert_eq!(simulation.port_value(first, "out", file), Some(inner));
+        assert_eq!(simulation.port_value(second, "in", file), Some(inner));
+        assert_eq!(simulation.port_value(0, "y", file), Some(value));
+    }
+}
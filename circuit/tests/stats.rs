@@ -0,0 +1,87 @@
+
+// Resource counts of a hierarchy small enough to count by hand
+
+use circuit::{ComponentKind, GateCount, MemoryCount, Resources, instance_counts};
+use circuit::testing::{Builder, pin};
+
+// `main` contains an `Outer` block, which contains two `Inner` blocks
+fn hierarchy() -> Builder {
+    let mut builder = Builder::new();
+    builder.add_circuit("Outer");
+    builder.add_circuit("Inner");
+
+    builder.edit("Inner");
+    builder.add(ComponentKind::And { inputs: 3 });
+    builder.add(ComponentKind::And { inputs: 2 });
+    builder.add(ComponentKind::Not);
+    builder.add(ComponentKind::Register { width: 4 });
+    builder.add(ComponentKind::Sram { address_width: 2, data_width: 8 });
+
+    builder.edit("Outer");
+    builder.add(ComponentKind::Custom { circuit_name: "Inner".to_string() });
+    builder.add(ComponentKind::Custom { circuit_name: "Inner".to_string() });
+    builder.add(ComponentKind::Or { inputs: 2 });
+    builder.add(ComponentKind::Register { width: 1 });
+
+    builder.edit("main");
+    builder.add(ComponentKind::Custom { circuit_name: "Outer".to_string() });
+    builder.add(ComponentKind::Rom { address_width: 3, word_size: 16, contents: String::new() });
+    let not = builder.add(ComponentKind::Not);
+    let or = builder.add(ComponentKind::Or { inputs: 2 });
+    builder.wire(&pin(not, "out"), &pin(or, "in0"));
+    builder
+}
+
+fn gate(kind: &'static str, inputs: u32, count: u64) -> GateCount {
+    GateCount { kind, inputs, count }
+}
+
+#[test]
+fn instances_are_counted_per_circuit() {
+    let builder = hierarchy();
+    let counts = instance_counts(&builder.file, builder.circuit()).into_iter()
+        .map(|(circuit, count)| (circuit.name.as_str(), count))
+        .collect::<Vec<_>>();
+    assert_eq!(counts, [("main", 1), ("Outer", 1), ("Inner", 2)]);
+}
+
+#[test]
+fn own_resources_leave_out_blocks() {
+    let builder = hierarchy();
+    let outer = builder.file.circuit("Outer").unwrap();
+    assert_eq!(Resources::of(outer), Resources {
+        gates: vec![gate("or", 2, 1)],
+        registers: 1,
+        register_bits: 1,
+        memories: vec![],
+        components: 4,
+        connections: 0,
+    });
+
+    let main = Resources::of(builder.circuit());
+    assert_eq!(main.gates, [gate("not", 1, 1), gate("or", 2, 1)]);
+    assert_eq!((main.registers, main.register_bits), (0, 0));
+    assert_eq!((main.memory_bits("rom"), main.memory_bits("sram")), (128, 0));
+    assert_eq!((main.components, main.connections), (4, 1));
+}
+
+#[test]
+fn totals_include_every_instance() {
+    let builder = hierarchy();
+    let outer = Resources::total(&builder.file, builder.file.circuit("Outer").unwrap());
+    assert_eq!(outer.gates, [gate("and", 2, 2), gate("and", 3, 2), gate("not", 1, 2), gate("or", 2, 1)]);
+    assert_eq!((outer.registers, outer.register_bits), (3, 9));
+    assert_eq!(outer.memories, [MemoryCount { kind: "sram", address_width: 2, word_size: 8, count: 2 }]);
+    assert_eq!(outer.components, 4 + 2 * 5);
+
+    let total = Resources::total(&builder.file, builder.circuit());
+    assert_eq!(total.gates, [gate("and", 2, 2), gate("and", 3, 2), gate("not", 1, 3), gate("or", 2, 2)]);
+    assert_eq!((total.gate_count("and"), total.gate_count("not"), total.total_gates()), (4, 3, 9));
+    assert_eq!((total.registers, total.register_bits), (3, 9));
+    assert_eq!(total.memories, [
+        MemoryCount { kind: "rom", address_width: 3, word_size: 16, count: 1 },
+        MemoryCount { kind: "sram", address_width: 2, word_size: 8, count: 2 },
+    ]);
+    assert_eq!((total.memory_bits("rom"), total.memory_bits("sram")), (8 * 16, 2 * 4 * 8));
+    assert_eq!((total.components, total.connections), (4 + 14, 1));
+}
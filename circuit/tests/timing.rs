@@ -0,0 +1,87 @@
+
+// Path lengths of small circuits where they can be counted by hand
+
+use circuit::{ComponentKind, Edge, Simulation, TimingAnalysis};
+use circuit::testing::{Builder, component, pin};
+
+fn chain(builder: &mut Builder, start: (usize, String), gates: usize) -> (usize, String) {
+    (0..gates).fold(start, |output, _| builder.not(output))
+}
+
+#[test]
+fn register_paths_on_both_edges() {
+    let mut builder = Builder::new();
+    let clock = builder.add(ComponentKind::Clock { period: 60 });
+    let one = builder.add(ComponentKind::Const { value: true });
+    let rising = builder.add(ComponentKind::Register { width: 1 });
+    let falling = builder.add(ComponentKind::Register { width: 1 });
+    let inverted_clock = builder.not(pin(clock, "out"));
+    builder.wire(&pin(clock, "out"), &pin(rising, "C"));
+    builder.wire(&inverted_clock, &pin(falling, "C"));
+    builder.wire(&pin(one, "out"), &pin(rising, "EN"));
+    builder.wire(&pin(one, "out"), &pin(falling, "EN"));
+
+    // The register toggles through 9 gates and the falling-edge register loads its output through 12
+    let toggled = chain(&mut builder, pin(rising, "Q0"), 9);
+    builder.wire(&toggled, &pin(rising, "D0"));
+    let delayed = chain(&mut builder, pin(rising, "Q0"), 12);
+    builder.wire(&delayed, &pin(falling, "D0"));
+
+    let simulation = Simulation::new(&builder.file, builder.circuit()).unwrap();
+    let analysis = TimingAnalysis::new(&simulation).unwrap();
+    assert_eq!(analysis.paths.len(), 2);
+
+    // 1 delay of the register and 12 of the gates, minus the inverter on the clock of the capturing register. Half a
+    // period has to be longer than that.
+    let worst = &analysis.paths[0];
+    assert_eq!((worst.launch, worst.capture), (component(rising), component(falling)));
+    assert_eq!((worst.launch_edge, worst.capture_edge), (Edge::Rising, Some(Edge::Falling)));
+    assert_eq!((worst.arrival, worst.capture_clock_delay), (13, 1));
+    assert_eq!(worst.components.len(), 13);
+    assert_eq!(worst.min_period, 24);
+
+    let toggle = &analysis.paths[1];
+    assert_eq!((toggle.launch, toggle.capture), (component(rising), component(rising)));
+    assert_eq!((toggle.arrival, toggle.min_period), (10, 10));
+    assert_eq!(analysis.min_period(), 24);
+}
+
+#[test]
+fn paths_through_an_sram() {
+    let mut builder = Builder::new();
+    let clock = builder.add(ComponentKind::Clock { period: 60 });
+    let one = builder.add(ComponentKind::Const { value: true });
+    let address = builder.add(ComponentKind::Register { width: 1 });
+    let data = builder.add(ComponentKind::Register { width: 1 });
+    let memory = builder.add(ComponentKind::Sram { address_width: 1, data_width: 1 });
+    for &register in &[address, data] {
+        builder.wire(&pin(clock, "out"), &pin(register, "C"));
+        builder.wire(&pin(one, "out"), &pin(register, "EN"));
+    }
+    builder.wire(&pin(one, "out"), &pin(memory, "OE"));
+    builder.wire(&pin(clock, "out"), &pin(memory, "WE"));
+
+    // Address register, 3 gates, the SRAM, 2 gates, data register. The output of the SRAM also goes back to its input
+    // through 2 gates, which is no loop.
+    let next_address = chain(&mut builder, pin(address, "Q0"), 3);
+    builder.wire(&next_address, &pin(memory, "A0"));
+    builder.wire(&next_address, &pin(address, "D0"));
+    let output = chain(&mut builder, pin(memory, "Q0"), 2);
+    builder.wire(&output, &pin(data, "D0"));
+    builder.wire(&output, &pin(memory, "D0"));
+
+    let simulation = Simulation::new(&builder.file, builder.circuit()).unwrap();
+    let analysis = TimingAnalysis::new(&simulation).unwrap();
+
+    let into_register = analysis.paths.iter().find(|path| path.capture == component(data)).unwrap();
+    assert_eq!(into_register.launch, component(address));
+    assert_eq!(into_register.arrival, 7);
+    assert_eq!(into_register.components.len(), 7);
+    assert_eq!(into_register.components[4], component(memory));
+    assert_eq!(into_register.min_period, 7);
+
+    // Inputs of the SRAM have half a period
+    let into_memory = analysis.paths.iter().find(|path| path.capture == component(memory) && path.launch == component(address)).unwrap();
+    assert_eq!((into_memory.arrival, into_memory.capture_edge, into_memory.min_period), (7, None, 14));
+    assert_eq!(analysis.min_period(), 14);
+}
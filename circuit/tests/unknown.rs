@@ -0,0 +1,170 @@
+
+// How X spreads through gates, registers and SRAMs after `make_unknown`
+
+use circuit::{CircuitFile, ComponentKind, Simulation};
+use circuit::testing::{Builder, component, pin};
+
+// Whether a pin of a component in the top-level circuit is X
+fn is_unknown(simulation: &Simulation, (component, name): &(usize, String)) -> bool {
+    let net = simulation.netlist(0).net_of(*component, name).unwrap();
+    simulation.net_is_unknown(simulation.net(0, net))
+}
+
+// Sets inputs of the top-level circuit and waits for the circuit to settle
+fn set(simulation: &mut Simulation, file: &CircuitFile, inputs: &[(&str, bool)]) {
+    for &(label, value) in inputs {
+        assert!(simulation.set_input(label, value, file));
+    }
+    simulation.settle(10).unwrap();
+}
+
+// A 1-bit register that inverts itself at every rising edge, with CLR driven by the input `clr` if `clear` is set
+fn toggle(clear: bool) -> (Builder, usize) {
+    let mut builder = Builder::new();
+    let clock = builder.add(ComponentKind::Clock { period: 20 });
+    let one = builder.add(ComponentKind::Const { value: true });
+    let register = builder.add(ComponentKind::Register { width: 1 });
+    builder.wire(&pin(clock, "out"), &pin(register, "C"));
+    builder.wire(&pin(one, "out"), &pin(register, "EN"));
+    let inverted = builder.not(pin(register, "Q0"));
+    builder.wire(&inverted, &pin(register, "D0"));
+    if clear {
+        let input = builder.add(ComponentKind::Input { label: "clr".to_string() });
+        builder.wire(&pin(input, "out"), &pin(register, "CLR"));
+    }
+    (builder, register)
+}
+
+#[test]
+fn registers_without_a_reset_stay_unknown() {
+    let (builder, register) = toggle(false);
+    let mut simulation = Simulation::new(&builder.file, builder.circuit()).unwrap();
+    simulation.make_unknown();
+    for _ in 0..8 {
+        simulation.run_cycles(1);
+        assert_eq!(simulation.register_unknown(component(register)), Some(1));
+    }
+}
+
+#[test]
+fn clearing_makes_registers_known() {
+    let (builder, register) = toggle(true);
+    let file = &builder.file;
+    let mut simulation = Simulation::new(file, builder.circuit()).unwrap();
+    simulation.make_unknown();
+    simulation.run_cycles(2);
+    assert_eq!(simulation.register_unknown(component(register)), Some(1));
+
+    simulation.set_input("clr", true, file);
+    simulation.run_cycles(1);
+    simulation.set_input("clr", false, file);
+    let mut values = Vec::new();
+    for _ in 0..4 {
+        simulation.run_cycles(1);
+        assert_eq!(simulation.register_unknown(component(register)), Some(0));
+        values.push(simulation.register(component(register)).unwrap());
+    }
+    assert_eq!(values, [1, 0, 1, 0]);
+}
+
+#[test]
+fn known_inputs_can_decide_gates() {
+    // The register has no clock, so it keeps X forever
+    let mut builder = Builder::new();
+    let register = builder.add(ComponentKind::Register { width: 1 });
+    let zero = builder.add(ComponentKind::Const { value: false });
+    let one = builder.add(ComponentKind::Const { value: true });
+    let and_zero = builder.and(&[pin(register, "Q0"), pin(zero, "out")]);
+    let and_one = builder.and(&[pin(register, "Q0"), pin(one, "out")]);
+    let or_zero = builder.or(&[pin(register, "Q0"), pin(zero, "out")]);
+    let or_one = builder.or(&[pin(register, "Q0"), pin(one, "out")]);
+    let not = builder.not(pin(register, "Q0"));
+    let mut simulation = Simulation::new(&builder.file, builder.circuit()).unwrap();
+    simulation.make_unknown();
+    simulation.settle(10).unwrap();
+
+    assert!(is_unknown(&simulation, &pin(register, "Q0")));
+    assert!(!is_unknown(&simulation, &and_zero));
+    assert_eq!(simulation.pin_value(component(and_zero.0), "out"), Some(false));
+    assert!(!is_unknown(&simulation, &or_one));
+    assert_eq!(simulation.pin_value(component(or_one.0), "out"), Some(true));
+    for gate in &[and_one, or_zero, not] {
+        assert!(is_unknown(&simulation, gate), "{:?}", gate);
+    }
+}
+
+#[test]
+fn clock_edges_from_unknown_may_load() {
+    // The clock is X until `c` forces it to 1, D is 0b01, `clr` makes the register known beforehand
+    let mut builder = Builder::new();
+    let unknown = builder.add(ComponentKind::Register { width: 1 });
+    let input = builder.add(ComponentKind::Input { label: "c".to_string() });
+    let clear = builder.add(ComponentKind::Input { label: "clr".to_string() });
+    let one = builder.add(ComponentKind::Const { value: true });
+    let zero = builder.add(ComponentKind::Const { value: false });
+    let register = builder.add(ComponentKind::Register { width: 2 });
+    let clock = builder.or(&[pin(unknown, "Q0"), pin(input, "out")]);
+    builder.wire(&clock, &pin(register, "C"));
+    builder.wire(&pin(clear, "out"), &pin(register, "CLR"));
+    builder.wire(&pin(one, "out"), &pin(register, "EN"));
+    builder.wire(&pin(one, "out"), &pin(register, "D0"));
+    builder.wire(&pin(zero, "out"), &pin(register, "D1"));
+    let file = &builder.file;
+    let mut simulation = Simulation::new(file, builder.circuit()).unwrap();
+    simulation.make_unknown();
+    set(&mut simulation, file, &[("clr", true)]);
+    set(&mut simulation, file, &[("clr", false)]);
+    assert!(is_unknown(&simulation, &clock));
+    assert_eq!(simulation.register(component(register)), Some(0));
+    assert_eq!(simulation.register_unknown(component(register)), Some(0));
+
+    // Bit 1 is 0 whether the register loaded or not
+    set(&mut simulation, file, &[("c", true)]);
+    assert_eq!(simulation.register(component(register)), Some(0));
+    assert_eq!(simulation.register_unknown(component(register)), Some(0b01));
+}
+
+#[test]
+fn unknown_sram_addresses_merge_the_candidate_words() {
+    // The address is `a`, or X while `x` is set
+    let mut builder = Builder::new();
+    let unknown = builder.add(ComponentKind::Register { width: 1 });
+    let inputs = ["a", "x", "we", "d0", "d1"].iter()
+        .map(|&label| builder.add(ComponentKind::Input { label: label.to_string() }))
+        .collect::<Vec<_>>();
+    let one = builder.add(ComponentKind::Const { value: true });
+    let memory = builder.add(ComponentKind::Sram { address_width: 1, data_width: 2 });
+    let maybe = builder.and(&[pin(unknown, "Q0"), pin(inputs[1], "out")]);
+    let address = builder.or(&[maybe, pin(inputs[0], "out")]);
+    builder.wire(&address, &pin(memory, "A0"));
+    builder.wire(&pin(inputs[2], "out"), &pin(memory, "WE"));
+    builder.wire(&pin(inputs[3], "out"), &pin(memory, "D0"));
+    builder.wire(&pin(inputs[4], "out"), &pin(memory, "D1"));
+    builder.wire(&pin(one, "out"), &pin(memory, "OE"));
+    let file = &builder.file;
+    let mut simulation = Simulation::new(file, builder.circuit()).unwrap();
+    simulation.make_unknown();
+
+    let write = |simulation: &mut Simulation, d0, d1| {
+        set(simulation, file, &[("d0", d0), ("d1", d1), ("we", true)]);
+        set(simulation, file, &[("we", false)]);
+    };
+    set(&mut simulation, file, &[("a", false)]);
+    write(&mut simulation, true, false);
+    set(&mut simulation, file, &[("a", true)]);
+    write(&mut simulation, true, true);
+    assert_eq!(simulation.memory(component(memory)), Some(&[0b01, 0b11][..]));
+    assert_eq!(simulation.memory_unknown(component(memory)), Some(&[0, 0][..]));
+
+    // Reading either word gives 1 in bit 0
+    set(&mut simulation, file, &[("a", false), ("x", true)]);
+    assert!(is_unknown(&simulation, &address));
+    assert!(!is_unknown(&simulation, &pin(memory, "Q0")));
+    assert!(is_unknown(&simulation, &pin(memory, "Q1")));
+    assert_eq!(simulation.pin_value(component(memory), "Q0"), Some(true));
+
+    // Writing 0b10 may have hit either word
+    write(&mut simulation, false, true);
+    assert_eq!(simulation.memory(component(memory)), Some(&[0b00, 0b10][..]));
+    assert_eq!(simulation.memory_unknown(component(memory)), Some(&[0b11, 0b01][..]));
+}
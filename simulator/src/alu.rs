@@ -0,0 +1,72 @@
+
+// Function encoding, same as in ALU.hdl
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AluFunction {
+    And = 0x0,
+    Or = 0x1,
+    Xor = 0x2,
+    Not = 0x3,
+    ShiftLeft = 0x4,
+    ShiftRight = 0x5,
+    Add = 0x6,
+    Subtract = 0x7,
+}
+
+impl AluFunction {
+    pub fn from_code(code: u16) -> AluFunction {
+        match code & 0x7 {
+            0x0 => AluFunction::And,
+            0x1 => AluFunction::Or,
+            0x2 => AluFunction::Xor,
+            0x3 => AluFunction::Not,
+            0x4 => AluFunction::ShiftLeft,
+            0x5 => AluFunction::ShiftRight,
+            0x6 => AluFunction::Add,
+            _ => AluFunction::Subtract,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AluOutput {
+    pub result: u16,
+    pub carry: bool,
+    pub zero: bool,
+    pub equal: bool,
+    pub less_than: bool,
+}
+
+/// Evaluates the ALU exactly like ALU.hdl does, including the flag outputs of functions that don't use them
+pub fn evaluate(function: AluFunction, operand1: u16, operand2: u16) -> AluOutput {
+    // The adder is always active, it only inverts operand2 when subtracting
+    let subtract = function == AluFunction::Subtract;
+    let adder_operand2 = if subtract { !operand2 } else { operand2 };
+    let sum = operand1 as u32 + adder_operand2 as u32 + subtract as u32;
+    let adder_result = sum as u16;
+    let carry = sum > 0xffff;
+
+    // The shifter only looks at the lower 4 bits of operand2
+    let shift = operand2 & 0xf;
+
+    let result = match function {
+        AluFunction::And => operand1 & operand2,
+        AluFunction::Or => operand1 | operand2,
+        AluFunction::Xor => operand1 ^ operand2,
+        AluFunction::Not => !operand1,
+        AluFunction::ShiftLeft => operand1 << shift,
+        AluFunction::ShiftRight => operand1 >> shift,
+        AluFunction::Add | AluFunction::Subtract => adder_result,
+    };
+
+    let zero = result == 0;
+    let equal = zero;
+    let less_than = !(equal || carry);
+
+    AluOutput {
+        result,
+        carry,
+        zero,
+        equal,
+        less_than,
+    }
+}
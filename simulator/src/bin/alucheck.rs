@@ -0,0 +1,287 @@
+
+use std::{env, process};
+use std::path::{Path, PathBuf};
+use std::error::Error;
+
+use circuit::{CircuitFile, Harness};
+use simulator::{AluFunction, AluOutput, evaluate_alu, parse_number};
+
+const DEFAULT_CIRCUIT: &str = "ALU";
+const DEFAULT_RANDOM: u64 = 100_000;
+const DEFAULT_SEED: u64 = 1;
+const DEFAULT_LIMIT: usize = 20;
+
+// More than enough for the carry to ripple through the adder
+const MAX_SETTLE_STEPS: u64 = 1000;
+
+const FUNCTIONS: [AluFunction; 8] = [
+    AluFunction::And,
+    AluFunction::Or,
+    AluFunction::Xor,
+    AluFunction::Not,
+    AluFunction::ShiftLeft,
+    AluFunction::ShiftRight,
+    AluFunction::Add,
+    AluFunction::Subtract,
+];
+
+const OUTPUTS: [&str; 5] = ["result", "carry_flag", "zero_flag", "equal_flag", "less_than_flag"];
+
+struct Options {
+    circuit_path: PathBuf,
+    circuit_name: String,
+    random: u64,
+    seed: u64,
+    limit: usize,
+}
+
+fn print_usage(exec_path: &str) {
+    eprint!("Usage: ");
+    let exec_path = Path::new(exec_path);
+    if let Some(exec_name) = exec_path.file_name() {
+        eprint!("{}", exec_name.to_string_lossy());
+    } else {
+        eprint!("{}", exec_path.display());
+    }
+    eprintln!(" [OPTIONS] CIRCUIT");
+    eprintln!();
+    eprintln!("Evaluates the ALU in CIRCUIT (e.g. build/ALU.json) for every function and compares the result and the flags");
+    eprintln!("with the model of the simulator. Every pair of 8-bit operands is checked with both bytes of each operand set");
+    eprintln!("to the 8-bit value, followed by random 16-bit operands.");
+    eprintln!();
+    eprintln!("Options:");
+    eprintln!("    --circuit NAME        name of the ALU circuit in the file (default {})", DEFAULT_CIRCUIT);
+    eprintln!("    --random N            number of random operand pairs per function (default {})", DEFAULT_RANDOM);
+    eprintln!("    --seed N              seed of the random operands (default {})", DEFAULT_SEED);
+    eprintln!("    --limit N             list at most N mismatches (default {})", DEFAULT_LIMIT);
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut circuit_path = None;
+    let mut options = Options {
+        circuit_path: PathBuf::new(),
+        circuit_name: DEFAULT_CIRCUIT.to_string(),
+        random: DEFAULT_RANDOM,
+        seed: DEFAULT_SEED,
+        limit: DEFAULT_LIMIT,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next()
+            .ok_or_else(|| format!("missing value for {}", name));
+
+        match arg.as_str() {
+            "--circuit" => options.circuit_name = value(arg)?.clone(),
+            "--random" => {
+                let text = value(arg)?;
+                options.random = parse_number(text)
+                    .ok_or_else(|| format!("invalid number of operands \"{}\"", text))?;
+            },
+            "--seed" => {
+                let text = value(arg)?;
+                options.seed = parse_number(text)
+                    .ok_or_else(|| format!("invalid seed \"{}\"", text))?;
+            },
+            "--limit" => {
+                let text = value(arg)?;
+                options.limit = parse_number(text)
+                    .ok_or_else(|| format!("invalid limit \"{}\"", text))? as usize;
+            },
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ if circuit_path.is_none() => circuit_path = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument \"{}\"", arg)),
+        }
+    }
+
+    options.circuit_path = circuit_path.ok_or_else(|| "missing circuit".to_string())?;
+
+    Ok(options)
+}
+
+fn print_error(err: &dyn Error) {
+    eprintln!("error: {}", err);
+
+    let mut err = err;
+    while let Some(source) = err.source() {
+        eprintln!("reason: {}", source);
+        err = source;
+    }
+}
+
+fn function_name(function: AluFunction) -> &'static str {
+    match function {
+        AluFunction::And => "and",
+        AluFunction::Or => "or",
+        AluFunction::Xor => "xor",
+        AluFunction::Not => "not",
+        AluFunction::ShiftLeft => "sl",
+        AluFunction::ShiftRight => "sr",
+        AluFunction::Add => "add",
+        AluFunction::Subtract => "sub",
+    }
+}
+
+// Values of the outputs in the order of OUTPUTS
+fn output_values(output: &AluOutput) -> [u16; 5] {
+    [output.result, output.carry as u16, output.zero as u16, output.equal as u16, output.less_than as u16]
+}
+
+// xorshift64*, good enough to pick operands and reproducible without another dependency
+struct Random(u64);
+
+impl Random {
+    fn new(seed: u64) -> Random {
+        // The state must never be 0, which only this seed would give
+        match seed ^ 0x9e37_79b9_7f4a_7c15 {
+            0 => Random(0x9e37_79b9_7f4a_7c15),
+            state => Random(state),
+        }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+fn evaluate(harness: &mut Harness, function: AluFunction, operand1: u16, operand2: u16) -> Result<AluOutput, String> {
+    harness.set("fn", function as u64);
+    harness.set("operand1", operand1 as u64);
+    harness.set("operand2", operand2 as u64);
+    harness.settle(MAX_SETTLE_STEPS)
+        .ok_or_else(|| format!("the ALU doesn't settle for {} 0x{:04x}, 0x{:04x}", function_name(function), operand1, operand2))?;
+
+    let flag = |name| harness.get(name).unwrap_or(0) != 0;
+    Ok(AluOutput {
+        result: harness.get("result").unwrap_or(0) as u16,
+        carry: flag("carry_flag"),
+        zero: flag("zero_flag"),
+        equal: flag("equal_flag"),
+        less_than: flag("less_than_flag"),
+    })
+}
+
+struct Mismatch {
+    function: AluFunction,
+    operand1: u16,
+    operand2: u16,
+    // Name, expected and actual value of every output that differs
+    outputs: Vec<(&'static str, u16, u16)>,
+}
+
+#[derive(Default)]
+struct Summary {
+    checked: u64,
+    // Number of mismatches per function and output
+    counts: [[u64; 5]; 8],
+    mismatches: Vec<Mismatch>,
+}
+
+impl Summary {
+    fn check(&mut self, harness: &mut Harness, function: AluFunction, operand1: u16, operand2: u16) -> Result<(), String> {
+        let expected = output_values(&evaluate_alu(function, operand1, operand2));
+        let actual = output_values(&evaluate(harness, function, operand1, operand2)?);
+
+        let mut outputs = Vec::new();
+        for (index, (&expected, &actual)) in expected.iter().zip(&actual).enumerate() {
+            if expected != actual {
+                self.counts[function as usize][index] += 1;
+                outputs.push((OUTPUTS[index], expected, actual));
+            }
+        }
+
+        self.checked += 1;
+        if !outputs.is_empty() {
+            self.mismatches.push(Mismatch { function, operand1, operand2, outputs });
+        }
+        Ok(())
+    }
+}
+
+fn run(options: Options) -> Result<bool, Box<dyn Error>> {
+    let mut harness = Harness::new(CircuitFile::load(&options.circuit_path)?, &options.circuit_name)?;
+
+    let missing = OUTPUTS.iter()
+        .chain(&["fn", "operand1", "operand2"])
+        .filter(|&&name| harness.get(name).is_none())
+        .cloned()
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        return Err(format!("the circuit has no ports called {}", missing.join(", ")).into());
+    }
+
+    let mut summary = Summary::default();
+    let mut random = Random::new(options.seed);
+    for &function in FUNCTIONS.iter() {
+        // Repeating the byte lets carries cross from the low into the high byte and out of bit 15, and the low nibble
+        // of operand2 still covers every shift distance
+        for operand1 in 0..=0xff {
+            for operand2 in 0..=0xff {
+                summary.check(&mut harness, function, operand1 << 8 | operand1, operand2 << 8 | operand2)?;
+            }
+        }
+
+        for _ in 0..options.random {
+            let operands = random.next();
+            summary.check(&mut harness, function, operands as u16, (operands >> 16) as u16)?;
+        }
+    }
+
+    println!("Checked {} operations, {} mismatches", summary.checked, summary.mismatches.len());
+    if summary.mismatches.is_empty() {
+        return Ok(true);
+    }
+
+    println!();
+    print!("  {:<8}", "Function");
+    for name in OUTPUTS.iter() {
+        print!("  {:>14}", name);
+    }
+    println!();
+    for &function in FUNCTIONS.iter() {
+        print!("  {:<8}", function_name(function));
+        for count in summary.counts[function as usize].iter() {
+            print!("  {:>14}", count);
+        }
+        println!();
+    }
+
+    println!();
+    println!("Mismatches:");
+    for mismatch in summary.mismatches.iter().take(options.limit) {
+        let outputs = mismatch.outputs.iter()
+            .map(|(name, expected, actual)| format!("{}=0x{:x} (expected 0x{:x})", name, actual, expected))
+            .collect::<Vec<_>>();
+        println!("  {:<3} 0x{:04x}, 0x{:04x}: {}", function_name(mismatch.function), mismatch.operand1, mismatch.operand2, outputs.join(", "));
+    }
+    if summary.mismatches.len() > options.limit {
+        println!("  ... and {} more", summary.mismatches.len() - options.limit);
+    }
+
+    Ok(false)
+}
+
+fn main() {
+    let args = env::args().collect::<Vec<_>>();
+
+    let options = match parse_args(&args[1..]) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            print_usage(&args[0]);
+            process::exit(1);
+        },
+    };
+
+    match run(options) {
+        Ok(true) => {},
+        Ok(false) => process::exit(2),
+        Err(err) => {
+            print_error(&*err);
+            process::exit(1);
+        },
+    }
+}
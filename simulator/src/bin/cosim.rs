@@ -0,0 +1,198 @@
+
+use std::{env, process};
+use std::path::{Path, PathBuf};
+use std::error::Error;
+
+use assembler::Register;
+use circuit::CircuitFile;
+use simulator::{Cpu, DecodeMode, load_image, parse_number, register_name};
+use simulator::cosim::{BlockMap, CoSimulation, GateCpu};
+
+const DEFAULT_INSTRUCTIONS: u64 = 10_000;
+
+// The toggle button of src/testbench.json that lets the clock run
+const DEFAULT_RUN_TOGGLE: usize = 22;
+
+struct Options {
+    circuit_path: PathBuf,
+    image_path: PathBuf,
+    instructions: u64,
+    toggles: Vec<usize>,
+    blocks: Vec<(String, String)>,
+    buttons: u8,
+    decode_mode: DecodeMode,
+}
+
+fn print_usage(exec_path: &str) {
+    eprint!("Usage: ");
+    let exec_path = Path::new(exec_path);
+    if let Some(exec_name) = exec_path.file_name() {
+        eprint!("{}", exec_name.to_string_lossy());
+    } else {
+        eprint!("{}", exec_path.display());
+    }
+    eprintln!(" [OPTIONS] CIRCUIT IMAGE");
+    eprintln!();
+    eprintln!("Runs IMAGE on the ISA simulator and on the gate-level CPU in CIRCUIT (usually build/main.json) in lockstep and");
+    eprintln!("stops at the first instruction after which the registers or the memory writes differ.");
+    eprintln!("IMAGE is an assembly source (.asm), a hex file (.hex) or a raw binary as written by the assembler.");
+    eprintln!();
+    eprintln!("Options:");
+    eprintln!("    --instructions N      stop after N instructions (default {})", DEFAULT_INSTRUCTIONS);
+    eprintln!("    --toggle INDEX        toggle button of the main circuit that lets the clock run, can be repeated");
+    eprintln!("                          (default {})", DEFAULT_RUN_TOGGLE);
+    eprintln!("    --block NAME=PATH     use the instance at PATH for NAME, one of {}", BlockMap::NAMES.join(", "));
+    eprintln!("    --buttons VALUE       state of the buttons");
+    eprintln!("    --decode MODE         decode mode of the ISA simulator: strict, hardware (default) or signals");
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut paths = Vec::new();
+    let mut options = Options {
+        circuit_path: PathBuf::new(),
+        image_path: PathBuf::new(),
+        instructions: DEFAULT_INSTRUCTIONS,
+        toggles: Vec::new(),
+        blocks: Vec::new(),
+        buttons: 0,
+        decode_mode: DecodeMode::Hardware,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next()
+            .ok_or_else(|| format!("missing value for {}", name));
+
+        match arg.as_str() {
+            "--instructions" => {
+                let text = value(arg)?;
+                options.instructions = parse_number(text)
+                    .ok_or_else(|| format!("invalid instruction count \"{}\"", text))?;
+            },
+            "--toggle" => {
+                let text = value(arg)?;
+                let index = parse_number(text)
+                    .ok_or_else(|| format!("invalid component index \"{}\"", text))?;
+                options.toggles.push(index as usize);
+            },
+            "--block" => {
+                let text = value(arg)?;
+                let (name, path) = text.split_once('=')
+                    .ok_or_else(|| format!("invalid block mapping \"{}\"", text))?;
+                options.blocks.push((name.to_string(), path.to_string()));
+            },
+            "--buttons" => {
+                let text = value(arg)?;
+                options.buttons = parse_number(text)
+                    .filter(|&value| value <= 0xff)
+                    .ok_or_else(|| format!("invalid button state \"{}\"", text))? as u8;
+            },
+            "--decode" => {
+                let text = value(arg)?;
+                options.decode_mode = match text.as_str() {
+                    "strict" => DecodeMode::Strict,
+                    "hardware" => DecodeMode::Hardware,
+                    "signals" => DecodeMode::Signals,
+                    _ => return Err(format!("invalid decode mode \"{}\"", text)),
+                };
+            },
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ if paths.len() < 2 => paths.push(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument \"{}\"", arg)),
+        }
+    }
+
+    if paths.len() < 2 {
+        return Err("missing circuit or image".to_string());
+    }
+    options.image_path = paths.pop().unwrap();
+    options.circuit_path = paths.pop().unwrap();
+
+    if options.toggles.is_empty() {
+        options.toggles.push(DEFAULT_RUN_TOGGLE);
+    }
+
+    Ok(options)
+}
+
+fn print_error(err: &dyn Error) {
+    eprintln!("error: {}", err);
+
+    let mut err = err;
+    while let Some(source) = err.source() {
+        eprintln!("reason: {}", source);
+        err = source;
+    }
+}
+
+fn run(options: Options) -> Result<bool, Box<dyn Error>> {
+    let program = load_image(&options.image_path)?;
+    let file = CircuitFile::load(&options.circuit_path)?;
+
+    let mut gate = GateCpu::new(file, &program.words, &options.toggles)?;
+    for (name, path) in &options.blocks {
+        gate.set_block(name, path)?;
+    }
+    println!("Blocks:");
+    for name in BlockMap::NAMES.iter() {
+        println!("  {:<6} {}", name, gate.block_path(name).unwrap_or("(not found)"));
+    }
+    println!();
+
+    let mut cpu = Cpu::with_program(&program.words)?;
+    cpu.set_decode_mode(options.decode_mode);
+    let mut cosim = CoSimulation::new(cpu, gate);
+    cosim.set_buttons(options.buttons);
+
+    while cosim.instructions() < options.instructions {
+        if cosim.cpu.is_halted() {
+            println!("Halted at 0x{:04x} after {} instructions without a mismatch", cosim.cpu.registers.pc, cosim.instructions());
+            return Ok(true);
+        }
+
+        let mismatch = match cosim.step()? {
+            Some(mismatch) => mismatch,
+            None => continue,
+        };
+
+        println!("Mismatch after {}", mismatch);
+        println!();
+        println!("  Register  ISA     Gates");
+        for &register in &[Register::A, Register::B, Register::Addr, Register::SP, Register::SR, Register::PC] {
+            let (isa, gate) = (mismatch.isa.get(register), mismatch.gate.get(register));
+            let marker = if isa != gate { "  <-" } else { "" };
+            println!("  {:<8}  0x{:04x}  0x{:04x}{}", register_name(register), isa, gate, marker);
+        }
+        println!();
+        println!("Trace:");
+        for entry in &mismatch.trace {
+            println!("  {}", entry);
+        }
+        return Ok(false);
+    }
+
+    println!("No mismatch in {} instructions", cosim.instructions());
+    Ok(true)
+}
+
+fn main() {
+    let args = env::args().collect::<Vec<_>>();
+
+    let options = match parse_args(&args[1..]) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            print_usage(&args[0]);
+            process::exit(1);
+        },
+    };
+
+    match run(options) {
+        Ok(true) => {},
+        Ok(false) => process::exit(2),
+        Err(err) => {
+            print_error(&*err);
+            process::exit(1);
+        },
+    }
+}
@@ -0,0 +1,14 @@
+
+use std::io;
+use std::process;
+
+// Debug adapter that talks to the editor over stdin and stdout
+fn main() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+
+    if let Err(err) = simulator::dap::serve(stdin.lock(), stdout.lock()) {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,350 @@
+
+use std::{env, fs, process};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::error::Error;
+
+use assembler::Register;
+
+use simulator::{Cpu, Bus, Session, Snapshot, StopReason, Watchpoint, WatchKind, load_image, disassemble, parse_number};
+
+const HELP: &str = "\
+Locations are labels, source line numbers or addresses prefixed with `*`, e.g. `*0x0010`.
+
+break LOCATION        set a breakpoint (b)
+delete [LOCATION]     delete a breakpoint or all breakpoints (d)
+breakpoints           list breakpoints and watchpoints
+watch ADDRESS         stop after the memory at ADDRESS is written (rwatch: read, awatch: any access)
+unwatch ADDRESS       delete the watchpoints at ADDRESS
+step [N]              execute N instructions (s)
+next                  execute one instruction, stepping over jumps that return to the next line (n)
+continue              run until a breakpoint is hit or the program halts (c)
+back [N]              undo the last N instructions
+rcontinue             run backwards until a breakpoint is hit (rc)
+lastwrite ADDRESS     run backwards to the last instruction that wrote to ADDRESS
+registers             show registers and flags (r)
+x ADDRESS [COUNT]     examine memory
+set REGISTER VALUE    modify a register
+set *ADDRESS VALUE    modify memory, including the ROM
+disas [COUNT]         disassemble around the PC
+list                  show the source around the current line (l)
+buttons VALUE         set the state of the buttons at 0x0601
+snapshot save FILE    save the state of the machine to FILE
+snapshot load FILE    restore the state of the machine from FILE
+quit                  exit the debugger (q)
+
+An empty line repeats the last command.";
+
+struct Debugger {
+    session: Session,
+    source_name: String,
+}
+
+impl Debugger {
+    fn location(&self, address: u16) -> String {
+        match self.session.program.line_of(address) {
+            Some(line) => format!("0x{:04x} {}:{}", address, self.source_name, line),
+            None => format!("0x{:04x}", address),
+        }
+    }
+
+    fn print_current(&self) {
+        let pc = self.session.cpu.registers.pc;
+        let word = self.session.cpu.bus.peek(pc);
+        println!("{}  {}", self.location(pc), disassemble(word));
+
+        if let Some(text) = self.session.program.line_of(pc).and_then(|line| self.session.source_line(line)) {
+            println!("    {}", text.trim());
+        }
+    }
+
+    fn print_stop(&self, reason: StopReason) {
+        match reason {
+            StopReason::Step => {},
+            StopReason::Breakpoint(address) => println!("Breakpoint at {}", self.location(address)),
+            StopReason::Watchpoint(watchpoint) => println!("Watchpoint ({:?}) at 0x{:04x}: 0x{:04x}",
+                watchpoint.kind, watchpoint.address, self.session.cpu.bus.peek(watchpoint.address)),
+            StopReason::Halted => println!("Program halted"),
+            StopReason::Limit => println!("Stopped after running for too long"),
+            StopReason::HistoryStart => println!("Reached the start of the recorded history"),
+            StopReason::Error(err) => println!("Error: {}", err),
+        }
+        self.print_current();
+    }
+
+    fn print_registers(&self) {
+        let regs = &self.session.cpu.registers;
+        println!("A    0x{:04x} {:>6}", regs.a, regs.a);
+        println!("B    0x{:04x} {:>6}", regs.b, regs.b);
+        println!("Addr 0x{:04x} {:>6}", regs.addr, regs.addr);
+        println!("SP   0x{:04x} {:>6}", regs.sp, regs.sp);
+        println!("SR   0x{:04x}  [{}]", regs.sr, regs.format_flags());
+        println!("PC   0x{:04x}  {}", regs.pc, self.location(regs.pc));
+        println!("Cycles: {}, instructions: {}", self.session.cpu.cycles(), self.session.cpu.instructions());
+    }
+
+    fn disassemble_around(&self, count: u16) {
+        let pc = self.session.cpu.registers.pc;
+        let start = pc.saturating_sub(count / 2);
+
+        for address in (start as u32..start as u32 + count as u32).take_while(|&address| address <= 0xffff) {
+            let address = address as u16;
+            if let Some(label) = self.session.program.label_at(address) {
+                println!("{}:", label);
+            }
+            let marker = if address == pc { "=>" } else { "  " };
+            let breakpoint = if self.session.breakpoints().any(|bp| bp == address) { "*" } else { " " };
+            let line = self.session.program.line_of(address)
+                .map(|line| format!("{:>4}", line))
+                .unwrap_or_else(|| "    ".to_string());
+            let word = self.session.cpu.bus.peek(address);
+            println!("{}{} 0x{:04x} {} {:04x}  {}", marker, breakpoint, address, line, word, disassemble(word));
+        }
+    }
+
+    fn list(&self) {
+        let current = match self.session.program.line_of(self.session.cpu.registers.pc) {
+            Some(line) => line,
+            None => {
+                println!("No source available for the current location");
+                return;
+            },
+        };
+
+        let first = current.saturating_sub(5).max(1);
+        let last = (current + 5).min(self.session.source_line_count());
+        for line in first..=last {
+            let marker = if line == current { "=>" } else { "  " };
+            println!("{} {:>4} {}", marker, line, self.session.source_line(line).unwrap_or(""));
+        }
+    }
+
+    fn execute(&mut self, line: &str) -> Result<bool, String> {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let (command, args) = match words.split_first() {
+            Some((command, args)) => (*command, args),
+            None => return Ok(true),
+        };
+
+        let resolve = |session: &Session, location: &str| session.resolve_location(location)
+            .ok_or_else(|| format!("unknown location \"{}\"", location));
+        let number = |text: &str| parse_number(text)
+            .filter(|&value| value <= 0xffff)
+            .map(|value| value as u16)
+            .ok_or_else(|| format!("invalid number \"{}\"", text));
+
+        match command {
+            "break" | "b" => {
+                let location = args.first().ok_or("missing location")?;
+                let address = resolve(&self.session, location)?;
+                self.session.add_breakpoint(address);
+                println!("Breakpoint at {}", self.location(address));
+            },
+            "delete" | "d" => match args.first() {
+                Some(location) => {
+                    let address = resolve(&self.session, location)?;
+                    if !self.session.remove_breakpoint(address) {
+                        return Err(format!("no breakpoint at {}", self.location(address)));
+                    }
+                },
+                None => self.session.clear_breakpoints(),
+            },
+            "breakpoints" => {
+                for address in self.session.breakpoints() {
+                    println!("{}", self.location(address));
+                }
+                for watchpoint in self.session.watchpoints() {
+                    println!("0x{:04x} ({:?})", watchpoint.address, watchpoint.kind);
+                }
+            },
+            "watch" | "rwatch" | "awatch" | "unwatch" => {
+                let address = number(args.first().ok_or("missing address")?)?;
+                let kind = match command {
+                    "rwatch" => WatchKind::Read,
+                    "awatch" => WatchKind::Access,
+                    _ => WatchKind::Write,
+                };
+                if command == "unwatch" {
+                    for kind in &[WatchKind::Read, WatchKind::Write, WatchKind::Access] {
+                        self.session.remove_watchpoint(Watchpoint { kind: *kind, address });
+                    }
+                } else {
+                    self.session.add_watchpoint(Watchpoint { kind, address });
+                }
+            },
+            "step" | "s" => {
+                let count = args.first().map(|count| number(count)).transpose()?.unwrap_or(1);
+                let mut reason = StopReason::Step;
+                for _ in 0..count {
+                    reason = self.session.step();
+                    if let StopReason::Step = reason {
+                        continue;
+                    }
+                    break;
+                }
+                self.print_stop(reason);
+            },
+            "next" | "n" => {
+                let reason = self.session.step_over();
+                self.print_stop(reason);
+            },
+            "continue" | "c" => {
+                let reason = self.session.continue_execution();
+                self.print_stop(reason);
+            },
+            "back" => {
+                let count = args.first().map(|count| number(count)).transpose()?.unwrap_or(1);
+                let mut reason = StopReason::Step;
+                for _ in 0..count {
+                    reason = self.session.step_back();
+                    if let StopReason::Step = reason {
+                        continue;
+                    }
+                    break;
+                }
+                self.print_stop(reason);
+            },
+            "rcontinue" | "rc" => {
+                let reason = self.session.reverse_continue();
+                self.print_stop(reason);
+            },
+            "lastwrite" => {
+                let address = number(args.first().ok_or("missing address")?)?;
+                let reason = self.session.run_back_to_write(address);
+                self.print_stop(reason);
+            },
+            "snapshot" => match args {
+                ["save", path] => {
+                    Snapshot::capture(&self.session.cpu).save(path)
+                        .map_err(|err| err.to_string())?;
+                },
+                ["load", path] => {
+                    let snapshot = Snapshot::load(path).map_err(|err| err.to_string())?;
+                    self.session.restore_snapshot(&snapshot).map_err(|err| err.to_string())?;
+                    self.print_current();
+                },
+                _ => return Err("usage: snapshot save FILE or snapshot load FILE".to_string()),
+            },
+            "registers" | "regs" | "r" => self.print_registers(),
+            "x" => {
+                let address = number(args.first().ok_or("missing address")?)?;
+                let count = args.get(1).map(|count| number(count)).transpose()?.unwrap_or(8);
+                for row_start in (address as u32..address as u32 + count as u32).step_by(8) {
+                    let row_end = (row_start + 8).min(address as u32 + count as u32).min(0x10000);
+                    let words = (row_start..row_end)
+                        .map(|address| format!("{:04x}", self.session.cpu.bus.peek(address as u16)))
+                        .collect::<Vec<_>>();
+                    println!("0x{:04x}: {}", row_start, words.join(" "));
+                }
+            },
+            "set" => {
+                let (target, value) = match args {
+                    [target, value] => (*target, number(value)?),
+                    _ => return Err("usage: set REGISTER VALUE or set *ADDRESS VALUE".to_string()),
+                };
+                if let Some(address) = target.strip_prefix('*') {
+                    let address = number(address)?;
+                    self.session.poke(address, value);
+                } else {
+                    let register = Register::from_str(target)
+                        .ok_or_else(|| format!("unknown register \"{}\"", target))?;
+                    self.session.cpu.registers.set(register, value);
+                }
+            },
+            "disas" => {
+                let count = args.first().map(|count| number(count)).transpose()?.unwrap_or(16);
+                self.disassemble_around(count);
+            },
+            "list" | "l" => self.list(),
+            "buttons" => {
+                let value = number(args.first().ok_or("missing value")?)?;
+                let buttons = self.session.cpu.bus.device_mut::<simulator::peripherals::Buttons>()
+                    .ok_or("no buttons connected")?;
+                buttons.set_state(value as u8);
+            },
+            "help" | "h" => println!("{}", HELP),
+            "quit" | "q" => return Ok(false),
+            _ => return Err(format!("unknown command \"{}\", try \"help\"", command)),
+        }
+
+        Ok(true)
+    }
+}
+
+fn run(image_path: PathBuf) -> Result<(), Box<dyn Error>> {
+    let program = load_image(&image_path)?;
+
+    let source = if image_path.extension().is_some_and(|ext| ext == "asm") {
+        Some(fs::read_to_string(&image_path)?)
+    } else {
+        None
+    };
+
+    let cpu = Cpu::new(Bus::testbench(&program.words)?);
+    let source_name = image_path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut debugger = Debugger {
+        session: Session::new(cpu, program, source.as_deref()),
+        source_name,
+    };
+
+    debugger.print_current();
+
+    let stdin = io::stdin();
+    let mut last_command = String::new();
+    loop {
+        print!("(dbg) ");
+        io::stdout().flush()?;
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            break;
+        }
+
+        let line = line.trim();
+        let command = if line.is_empty() {
+            last_command.clone()
+        } else {
+            line.to_string()
+        };
+
+        match debugger.execute(&command) {
+            Ok(true) => {},
+            Ok(false) => break,
+            Err(message) => println!("{}", message),
+        }
+
+        last_command = command;
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let args = env::args().collect::<Vec<_>>();
+
+    if args.len() != 2 {
+        eprint!("Usage: ");
+        let exec_path = Path::new(&args[0]);
+        if let Some(exec_name) = exec_path.file_name() {
+            eprint!("{}", exec_name.to_string_lossy());
+        } else {
+            eprint!("{}", exec_path.display());
+        }
+        eprintln!(" IMAGE");
+        process::exit(1);
+    }
+
+    if let Err(err) = run(PathBuf::from(&args[1])) {
+        eprintln!("error: {}", err);
+
+        let mut err: &dyn Error = &*err;
+        while let Some(source) = err.source() {
+            eprintln!("reason: {}", source);
+            err = source;
+        }
+        process::exit(1);
+    }
+}
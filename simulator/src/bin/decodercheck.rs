@@ -0,0 +1,195 @@
+
+use std::{env, process};
+use std::path::{Path, PathBuf};
+use std::error::Error;
+
+use circuit::{CircuitFile, Harness};
+use simulator::{ControlSignals, Instruction, disassemble, parse_number};
+
+const DEFAULT_CIRCUIT: &str = "InstructionDecoder";
+const DEFAULT_LIMIT: usize = 20;
+
+// More than enough for the decoder to settle after the instruction changed
+const MAX_SETTLE_STEPS: u64 = 1000;
+
+struct Options {
+    circuit_path: Option<PathBuf>,
+    circuit_name: String,
+    limit: usize,
+}
+
+fn print_usage(exec_path: &str) {
+    eprint!("Usage: ");
+    let exec_path = Path::new(exec_path);
+    if let Some(exec_name) = exec_path.file_name() {
+        eprint!("{}", exec_name.to_string_lossy());
+    } else {
+        eprint!("{}", exec_path.display());
+    }
+    eprintln!(" [OPTIONS] CIRCUIT|--model");
+    eprintln!();
+    eprintln!("Evaluates the instruction decoder in CIRCUIT (e.g. build/InstructionDecoder.json) for all 65536 instruction");
+    eprintln!("words and compares its outputs with a reference decoder derived from the ISA. Words the assembler never emits");
+    eprintln!("are compared with the model of the hardware decoder instead. --model checks that model instead of a circuit.");
+    eprintln!();
+    eprintln!("Options:");
+    eprintln!("    --circuit NAME        name of the decoder circuit in the file (default {})", DEFAULT_CIRCUIT);
+    eprintln!("    --limit N             list at most N disagreeing words per kind (default {})", DEFAULT_LIMIT);
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut circuit_path = None;
+    let mut model = false;
+    let mut options = Options {
+        circuit_path: None,
+        circuit_name: DEFAULT_CIRCUIT.to_string(),
+        limit: DEFAULT_LIMIT,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next()
+            .ok_or_else(|| format!("missing value for {}", name));
+
+        match arg.as_str() {
+            "--model" => model = true,
+            "--circuit" => options.circuit_name = value(arg)?.clone(),
+            "--limit" => {
+                let text = value(arg)?;
+                options.limit = parse_number(text)
+                    .ok_or_else(|| format!("invalid limit \"{}\"", text))? as usize;
+            },
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ if circuit_path.is_none() => circuit_path = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument \"{}\"", arg)),
+        }
+    }
+
+    match (circuit_path, model) {
+        (Some(_), true) => return Err("--model doesn't take a circuit".to_string()),
+        (None, false) => return Err("missing circuit".to_string()),
+        (path, _) => options.circuit_path = path,
+    }
+
+    Ok(options)
+}
+
+fn print_error(err: &dyn Error) {
+    eprintln!("error: {}", err);
+
+    let mut err = err;
+    while let Some(source) = err.source() {
+        eprintln!("reason: {}", source);
+        err = source;
+    }
+}
+
+// Outputs of the decoder under test for one instruction word
+enum Decoder {
+    Circuit(Box<Harness>),
+    Model,
+}
+
+impl Decoder {
+    fn evaluate(&mut self, word: u16) -> Result<Vec<(&'static str, u16)>, String> {
+        let names = ControlSignals::default().outputs();
+        match self {
+            Decoder::Circuit(harness) => {
+                harness.set("instruction", word as u64);
+                harness.settle(MAX_SETTLE_STEPS)
+                    .ok_or_else(|| format!("the decoder doesn't settle for 0x{:04x}", word))?;
+                Ok(names.iter()
+                    .map(|&(name, _)| (name, harness.get(name).unwrap_or(0) as u16))
+                    .collect())
+            },
+            Decoder::Model => Ok(ControlSignals::decode(word).outputs().to_vec()),
+        }
+    }
+}
+
+struct Disagreement {
+    word: u16,
+    // Name, expected and actual value of every output that differs
+    outputs: Vec<(&'static str, u16, u16)>,
+}
+
+fn print_disagreements(title: &str, total: usize, disagreements: &[Disagreement], limit: usize) {
+    println!("{}: {} of {} words disagree", title, disagreements.len(), total);
+    for disagreement in disagreements.iter().take(limit) {
+        let outputs = disagreement.outputs.iter()
+            .map(|(name, expected, actual)| format!("{}={} (expected {})", name, actual, expected))
+            .collect::<Vec<_>>();
+        println!("  0x{:04x} {:<16} {}", disagreement.word, disassemble(disagreement.word), outputs.join(", "));
+    }
+    if disagreements.len() > limit {
+        println!("  ... and {} more", disagreements.len() - limit);
+    }
+}
+
+fn run(options: Options) -> Result<bool, Box<dyn Error>> {
+    let mut decoder = match &options.circuit_path {
+        Some(path) => {
+            let harness = Harness::new(CircuitFile::load(path)?, &options.circuit_name)?;
+
+            let missing = ControlSignals::default().outputs().iter()
+                .map(|&(name, _)| name)
+                .chain(Some("instruction"))
+                .filter(|&name| harness.get(name).is_none())
+                .collect::<Vec<_>>();
+            if !missing.is_empty() {
+                return Err(format!("the circuit has no ports called {}", missing.join(", ")).into());
+            }
+
+            Decoder::Circuit(Box::new(harness))
+        },
+        None => Decoder::Model,
+    };
+
+    let mut documented = (0, Vec::new());
+    let mut undocumented = (0, Vec::new());
+    for word in 0..=0xffff {
+        let (expected, kind) = match Instruction::decode(word) {
+            Some(instruction) => (ControlSignals::reference(instruction, word), &mut documented),
+            None => (ControlSignals::decode(word), &mut undocumented),
+        };
+        let actual = decoder.evaluate(word)?;
+
+        let outputs = expected.outputs().iter()
+            .zip(&actual)
+            .filter(|((_, expected), (_, actual))| expected != actual)
+            .map(|(&(name, expected), &(_, actual))| (name, expected, actual))
+            .collect::<Vec<_>>();
+
+        kind.0 += 1;
+        if !outputs.is_empty() {
+            kind.1.push(Disagreement { word, outputs });
+        }
+    }
+
+    print_disagreements("Encodings of the ISA, compared with the reference decoder", documented.0, &documented.1, options.limit);
+    print_disagreements("Other encodings, compared with the model of the hardware decoder", undocumented.0, &undocumented.1, options.limit);
+
+    Ok(documented.1.is_empty() && undocumented.1.is_empty())
+}
+
+fn main() {
+    let args = env::args().collect::<Vec<_>>();
+
+    let options = match parse_args(&args[1..]) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            print_usage(&args[0]);
+            process::exit(1);
+        },
+    };
+
+    match run(options) {
+        Ok(true) => {},
+        Ok(false) => process::exit(2),
+        Err(err) => {
+            print_error(&*err);
+            process::exit(1);
+        },
+    }
+}
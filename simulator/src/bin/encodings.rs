@@ -0,0 +1,48 @@
+
+use assembler::OpCode;
+use simulator::{ControlSignals, Operation, disassemble};
+
+fn mnemonic(text: &str) -> &str {
+    text.split_whitespace().next().unwrap_or(text)
+}
+
+// Prints what the instruction decoder does with every opcode and with the register codes that don't select a register
+// as Markdown tables, followed by every word that drives the data bus from more than one source
+fn main() {
+    println!("| Opcode | Assembler | Hardware |");
+    println!("|--------|-----------|----------|");
+    for opcode in 0..32u16 {
+        let word = opcode << 11;
+        let operation = Operation::decode(word).to_string();
+
+        let (assembler, hardware) = match OpCode::from_code(opcode) {
+            Some(_) => (format!("`{}`", mnemonic(&disassemble(word))), format!("`{}`", mnemonic(&operation))),
+            None => {
+                let hardware = match Operation::decode(word) {
+                    Operation::Nop => "nothing, only the PC increments".to_string(),
+                    _ if opcode & 0x10 != 0 => format!("`{}`, the immediate is ignored", mnemonic(&operation)),
+                    _ => format!("`{}`", mnemonic(&operation)),
+                };
+                ("-".to_string(), hardware)
+            },
+        };
+
+        println!("| `0x{:02x}` | {} | {} |", opcode, assembler, hardware);
+    }
+
+    println!();
+    println!("| Register code 6 or 7 | Hardware |");
+    println!("|----------------------|----------|");
+    println!("| `mov` target | the source is read, no register is loaded |");
+    println!("| `mov` source | nothing drives the data bus, the target is loaded with 0 (`mov PC` jumps to 0) |");
+    println!("| `jmp` source | jumps to 0 if the condition is met |");
+
+    let conflicts = (0..=0xffff)
+        .filter(|&word| ControlSignals::decode(word).data_bus_drivers().len() > 1)
+        .collect::<Vec<u16>>();
+    println!();
+    println!("Words that enable more than one data bus driver: {} of 65536", conflicts.len());
+    for word in conflicts {
+        println!("- `0x{:04x}`: {}", word, ControlSignals::decode(word).data_bus_drivers().join(", "));
+    }
+}
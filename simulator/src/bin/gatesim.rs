@@ -0,0 +1,402 @@
+
+use std::{env, process};
+use std::path::{Path, PathBuf};
+use std::error::Error;
+use std::time::Instant;
+
+use circuit::{CircuitFile, CompiledSimulation, ComponentRef, Simulation};
+use simulator::{load_image, parse_number};
+
+const DEFAULT_CYCLES: u64 = 1000;
+
+// Nets listed per instance that are still X, the rest is only counted
+const UNKNOWN_NET_LIMIT: usize = 8;
+
+struct Options {
+    circuit_path: PathBuf,
+    image_path: PathBuf,
+    cycles: u64,
+    toggles: Vec<usize>,
+    buttons: u8,
+    expect_gpio: Option<u16>,
+    unknown: bool,
+    compiled: bool,
+    compare: bool,
+}
+
+fn print_usage(exec_path: &str) {
+    eprint!("Usage: ");
+    let exec_path = Path::new(exec_path);
+    if let Some(exec_name) = exec_path.file_name() {
+        eprint!("{}", exec_name.to_string_lossy());
+    } else {
+        eprint!("{}", exec_path.display());
+    }
+    eprintln!(" [OPTIONS] CIRCUIT IMAGE");
+    eprintln!();
+    eprintln!("Simulates the main circuit of CIRCUIT (usually build/main.json) at gate level with IMAGE loaded into its ROM.");
+    eprintln!("IMAGE is an assembly source (.asm), a hex file (.hex) or a raw binary as written by the assembler.");
+    eprintln!();
+    eprintln!("Options:");
+    eprintln!("    --cycles N            run for N periods of the clock (default {})", DEFAULT_CYCLES);
+    eprintln!("    --toggle INDEX        switch on the toggle button that is component INDEX of the main circuit (can be");
+    eprintln!("                          repeated), in src/testbench.json 22 lets the clock run");
+    eprintln!("    --buttons VALUE       switch on the toggle buttons connected to the B inputs of the GPIO circuit");
+    eprintln!("    --expect-gpio VALUE   exit with status 2 unless the bargraph shows VALUE at the end");
+    eprintln!("    --unknown             start with all registers and the SRAM unknown (X) instead of 0, list what is still");
+    eprintln!("                          X at the end and exit with status 2 if any register bit is");
+    eprintln!("    --compiled            use the levelized simulation, which only evaluates the logic at clock edges");
+    eprintln!("    --compare             run the event-driven and the levelized simulation side by side and exit with");
+    eprintln!("                          status 2 at the first cycle after which registers or the SRAM differ");
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut paths = Vec::new();
+    let mut options = Options {
+        circuit_path: PathBuf::new(),
+        image_path: PathBuf::new(),
+        cycles: DEFAULT_CYCLES,
+        toggles: Vec::new(),
+        buttons: 0,
+        expect_gpio: None,
+        unknown: false,
+        compiled: false,
+        compare: false,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next()
+            .ok_or_else(|| format!("missing value for {}", name));
+
+        match arg.as_str() {
+            "--cycles" => {
+                let text = value(arg)?;
+                options.cycles = parse_number(text)
+                    .ok_or_else(|| format!("invalid cycle count \"{}\"", text))?;
+            },
+            "--toggle" => {
+                let text = value(arg)?;
+                let index = parse_number(text)
+                    .ok_or_else(|| format!("invalid component index \"{}\"", text))?;
+                options.toggles.push(index as usize);
+            },
+            "--buttons" => {
+                let text = value(arg)?;
+                options.buttons = parse_number(text)
+                    .filter(|&value| value <= 0xff)
+                    .ok_or_else(|| format!("invalid button state \"{}\"", text))? as u8;
+            },
+            "--expect-gpio" => {
+                let text = value(arg)?;
+                options.expect_gpio = Some(parse_number(text)
+                    .filter(|&value| value <= 0xffff)
+                    .ok_or_else(|| format!("invalid GPIO value \"{}\"", text))? as u16);
+            },
+            "--unknown" => options.unknown = true,
+            "--compiled" => options.compiled = true,
+            "--compare" => options.compare = true,
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ if paths.len() < 2 => paths.push(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument \"{}\"", arg)),
+        }
+    }
+
+    if paths.len() < 2 {
+        return Err("missing circuit or image".to_string());
+    }
+    options.image_path = paths.pop().unwrap();
+    options.circuit_path = paths.pop().unwrap();
+
+    if options.unknown && (options.compiled || options.compare) {
+        return Err("the levelized simulation doesn't support --unknown".to_string());
+    }
+
+    Ok(options)
+}
+
+fn print_error(err: &dyn Error) {
+    eprintln!("error: {}", err);
+
+    let mut err = err;
+    while let Some(source) = err.source() {
+        eprintln!("reason: {}", source);
+        err = source;
+    }
+}
+
+fn bargraph(value: u16) -> String {
+    (0..16).rev()
+        .map(|bit| if value & (1 << bit) != 0 { '#' } else { '.' })
+        .collect()
+}
+
+// Hex digits of a word, digits with unknown bits are shown as x
+fn format_word(value: u64, unknown: u64) -> String {
+    (0..4).rev()
+        .map(|digit| {
+            if (unknown >> (digit * 4)) & 0xf != 0 {
+                'x'
+            } else {
+                std::char::from_digit(((value >> (digit * 4)) & 0xf) as u32, 16).unwrap()
+            }
+        })
+        .collect()
+}
+
+// What gets reported, from either simulation. Both number nets and components the same way.
+trait State {
+    fn net_value(&self, net: usize) -> bool;
+    fn register(&self, component: ComponentRef) -> Option<u64>;
+    fn memory(&self, component: ComponentRef) -> Option<&[u64]>;
+}
+
+impl State for Simulation {
+    fn net_value(&self, net: usize) -> bool {
+        Simulation::net_value(self, net)
+    }
+
+    fn register(&self, component: ComponentRef) -> Option<u64> {
+        Simulation::register(self, component)
+    }
+
+    fn memory(&self, component: ComponentRef) -> Option<&[u64]> {
+        Simulation::memory(self, component)
+    }
+}
+
+impl State for CompiledSimulation {
+    fn net_value(&self, net: usize) -> bool {
+        CompiledSimulation::net_value(self, net)
+    }
+
+    fn register(&self, component: ComponentRef) -> Option<u64> {
+        CompiledSimulation::register(self, component)
+    }
+
+    fn memory(&self, component: ComponentRef) -> Option<&[u64]> {
+        CompiledSimulation::memory(self, component)
+    }
+}
+
+// Nets of the inputs of the first bargraph of the main circuit, which shows the GPIO outputs in the testbench
+fn gpio_nets(simulation: &Simulation, bargraph: Option<ComponentRef>) -> Vec<Option<usize>> {
+    let bargraph = match bargraph {
+        Some(bargraph) => bargraph,
+        None => return Vec::new(),
+    };
+    let netlist = simulation.netlist(bargraph.instance);
+    (0..16)
+        .map(|bit| netlist.net_of(bargraph.component, &format!("in{}", bit)).map(|net| simulation.net(bargraph.instance, net)))
+        .collect()
+}
+
+fn gpio_value(state: &dyn State, nets: &[Option<usize>]) -> u16 {
+    nets.iter()
+        .enumerate()
+        .fold(0, |value, (bit, net)| value | (net.is_some_and(|net| state.net_value(net)) as u16) << bit)
+}
+
+// Registers and SRAM words that differ between the two simulations
+fn differences(simulation: &Simulation, compiled: &CompiledSimulation, registers: &[ComponentRef], srams: &[ComponentRef]) -> Vec<String> {
+    let mut differences = Vec::new();
+    for &register in registers {
+        let (event, levelized) = (simulation.register(register).unwrap_or(0), compiled.register(register).unwrap_or(0));
+        if event != levelized {
+            let path = &simulation.instances()[register.instance].path;
+            differences.push(format!("{}.register{}: 0x{:04x} event-driven, 0x{:04x} levelized", path, register.component, event, levelized));
+        }
+    }
+    for &sram in srams {
+        let words = simulation.memory(sram).unwrap_or(&[]).iter().zip(compiled.memory(sram).unwrap_or(&[]));
+        for (address, (event, levelized)) in words.enumerate().filter(|(_, (event, levelized))| event != levelized) {
+            let path = &simulation.instances()[sram.instance].path;
+            differences.push(format!("{}.sram{}[0x{:04x}]: 0x{:04x} event-driven, 0x{:04x} levelized", path, sram.component, address, event, levelized));
+        }
+    }
+    differences
+}
+
+fn run(options: Options) -> Result<bool, Box<dyn Error>> {
+    let file = CircuitFile::load(&options.circuit_path)?;
+    let main_circuit = file.main_circuit().ok_or("the circuit file is empty")?;
+    let program = load_image(&options.image_path)?;
+
+    let mut simulation = Simulation::new(&file, main_circuit)?;
+    if options.unknown {
+        simulation.make_unknown();
+    }
+
+    let rom = match simulation.find_components("rom", &file).as_slice() {
+        [rom] => *rom,
+        roms => return Err(format!("expected exactly one ROM, found {}", roms.len()).into()),
+    };
+    simulation.load_rom(rom, &program.words)?;
+
+    for &index in &options.toggles {
+        if !simulation.set_source(ComponentRef { instance: 0, component: index }, true) {
+            return Err(format!("component {} of the main circuit is not a toggle button", index).into());
+        }
+    }
+
+    // The buttons are found through the nets of the B inputs of the GPIO circuit
+    let netlist = simulation.netlist(0);
+    let mut buttons = Vec::new();
+    for gpio in simulation.find_components("custom", &file).into_iter().filter(|gpio| gpio.instance == 0) {
+        for bit in 0..8 {
+            if let Some(net) = netlist.net_of(gpio.component, &format!("B{}", bit)) {
+                let toggle = netlist.drivers(net)
+                    .find(|pin| matches!(main_circuit.components[pin.component].kind, circuit::ComponentKind::ToggleButton));
+                if let Some(toggle) = toggle {
+                    buttons.push((bit, toggle.component));
+                }
+            }
+        }
+    }
+    for (bit, component) in buttons {
+        simulation.set_source(ComponentRef { instance: 0, component }, options.buttons & (1 << bit) != 0);
+    }
+
+    if simulation.clock_period().is_none() {
+        return Err("the circuit has no clock".into());
+    }
+    let display = simulation.find_components("bargraph", &file).into_iter().find(|bargraph| bargraph.instance == 0);
+    let display_nets = gpio_nets(&simulation, display);
+    let registers = simulation.find_components("register", &file);
+    let srams = simulation.find_components("sram", &file);
+
+    // The levelized simulation starts from the state set up above
+    let mut compiled = if options.compiled || options.compare {
+        Some(CompiledSimulation::new(&simulation, 1)?)
+    } else {
+        None
+    };
+
+    let start = Instant::now();
+    let mut history = Vec::new();
+    let mut gpio = 0;
+    for cycle in 0..options.cycles {
+        if !options.compiled {
+            simulation.run_cycles(1);
+        }
+        if let Some(compiled) = &mut compiled {
+            compiled.run_cycles(1);
+        }
+
+        if let (true, Some(compiled)) = (options.compare, &compiled) {
+            let differences = differences(&simulation, compiled, &registers, &srams);
+            if !differences.is_empty() {
+                println!("The simulations differ after cycle {}:", cycle + 1);
+                for difference in &differences {
+                    println!("  {}", difference);
+                }
+                return Ok(false);
+            }
+        }
+
+        let value = match &compiled {
+            Some(compiled) if options.compiled => gpio_value(compiled, &display_nets),
+            _ => gpio_value(&simulation, &display_nets),
+        };
+        if value != gpio {
+            history.push((cycle + 1, value));
+            gpio = value;
+        }
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+
+    let state: &dyn State = match &compiled {
+        Some(compiled) if options.compiled => compiled,
+        _ => &simulation,
+    };
+    let time = options.cycles * simulation.clock_period().unwrap_or(0);
+    println!("Cycles: {}, time: {}, {:.0} cycles/s", options.cycles, time, options.cycles as f64 / elapsed.max(1e-9));
+    if options.compare {
+        println!("The event-driven and the levelized simulation agree");
+    }
+    println!();
+
+    println!("Registers:");
+    for &register in &registers {
+        let instance = &simulation.instances()[register.instance];
+        let (value, unknown) = (state.register(register).unwrap_or(0), simulation.register_unknown(register).unwrap_or(0));
+        println!("  {}.register{:<4} 0x{}", instance.path, register.component, format_word(value, unknown));
+    }
+
+    for &sram in &srams {
+        let memory = state.memory(sram).unwrap_or(&[]);
+        let unknown = simulation.memory_unknown(sram).unwrap_or(&[]);
+        println!();
+        println!("{}.sram{} 0x0000-0x0010:", simulation.instances()[sram.instance].path, sram.component);
+        let words = memory.iter().zip(unknown).take(0x10).collect::<Vec<_>>();
+        for (row, words) in words.chunks(8).enumerate() {
+            let words = words.iter().map(|&(&value, &unknown)| format_word(value, unknown)).collect::<Vec<_>>();
+            println!("  {:04x}: {}", row * 8, words.join(" "));
+        }
+    }
+
+    println!();
+    println!("GPIO history:");
+    if history.is_empty() {
+        println!("  (no changes)");
+    }
+    for &(cycle, value) in &history {
+        println!("  {:>10}: 0x{:04x} {}", cycle, value, bargraph(value));
+    }
+
+    let mut success = true;
+    if options.unknown {
+        println!();
+        println!("Unknown after {} cycles:", options.cycles);
+        let unknown_bits = registers.iter()
+            .map(|&register| simulation.register_unknown(register).unwrap_or(0).count_ones())
+            .sum::<u32>();
+        println!("  register bits: {}", unknown_bits);
+        for (index, instance) in simulation.instances().iter().enumerate() {
+            let netlist = simulation.netlist(index);
+            let nets = (0..netlist.nets.len())
+                .filter(|&net| simulation.net_is_unknown(simulation.net(index, net)))
+                .map(|net| netlist.nets[net].name.as_str())
+                .collect::<Vec<_>>();
+            if nets.is_empty() {
+                continue;
+            }
+            let more = if nets.len() > UNKNOWN_NET_LIMIT { ", ..." } else { "" };
+            println!("  {}: {} of {} nets ({}{})", instance.path, nets.len(), netlist.nets.len(),
+                     nets[..nets.len().min(UNKNOWN_NET_LIMIT)].join(", "), more);
+        }
+        if unknown_bits > 0 {
+            eprintln!("error: {} register bits are still unknown", unknown_bits);
+            success = false;
+        }
+    }
+
+    if options.expect_gpio.is_some_and(|expected| expected != gpio) {
+        eprintln!("error: the GPIO output differs from the expected value");
+        success = false;
+    }
+
+    Ok(success)
+}
+
+fn main() {
+    let args = env::args().collect::<Vec<_>>();
+
+    let options = match parse_args(&args[1..]) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            print_usage(&args[0]);
+            process::exit(1);
+        },
+    };
+
+    match run(options) {
+        Ok(true) => {},
+        Ok(false) => process::exit(2),
+        Err(err) => {
+            print_error(&*err);
+            process::exit(1);
+        },
+    }
+}
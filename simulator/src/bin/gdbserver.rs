@@ -0,0 +1,100 @@
+
+use std::{env, fs, process};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::error::Error;
+
+use simulator::{Cpu, Bus, Session, load_image, parse_number};
+
+const DEFAULT_PORT: u16 = 1234;
+
+struct Options {
+    image_path: PathBuf,
+    port: u16,
+}
+
+fn print_usage(exec_path: &str) {
+    eprint!("Usage: ");
+    let exec_path = Path::new(exec_path);
+    if let Some(exec_name) = exec_path.file_name() {
+        eprint!("{}", exec_name.to_string_lossy());
+    } else {
+        eprint!("{}", exec_path.display());
+    }
+    eprintln!(" [--port PORT] IMAGE");
+    eprintln!();
+    eprintln!("Waits for a GDB connection on 127.0.0.1:PORT (default {}) and debugs IMAGE.", DEFAULT_PORT);
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut image_path = None;
+    let mut port = DEFAULT_PORT;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => {
+                let text = args.next().ok_or("missing value for --port")?;
+                port = parse_number(text)
+                    .filter(|&value| value <= 0xffff)
+                    .ok_or_else(|| format!("invalid port \"{}\"", text))? as u16;
+            },
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ if image_path.is_none() => image_path = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument \"{}\"", arg)),
+        }
+    }
+
+    Ok(Options {
+        image_path: image_path.ok_or("missing image")?,
+        port,
+    })
+}
+
+fn run(options: Options) -> Result<(), Box<dyn Error>> {
+    let program = load_image(&options.image_path)?;
+    let source = if options.image_path.extension().is_some_and(|ext| ext == "asm") {
+        Some(fs::read_to_string(&options.image_path)?)
+    } else {
+        None
+    };
+
+    let cpu = Cpu::new(Bus::testbench(&program.words)?);
+    let mut session = Session::new(cpu, program, source.as_deref());
+
+    let listener = TcpListener::bind(("127.0.0.1", options.port))?;
+    eprintln!("Listening on {}", listener.local_addr()?);
+
+    let (stream, peer) = listener.accept()?;
+    eprintln!("Connection from {}", peer);
+    stream.set_nodelay(true)?;
+
+    simulator::gdb::serve(&mut session, stream)?;
+    eprintln!("Connection closed");
+
+    Ok(())
+}
+
+fn main() {
+    let args = env::args().collect::<Vec<_>>();
+
+    let options = match parse_args(&args[1..]) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            print_usage(&args[0]);
+            process::exit(1);
+        },
+    };
+
+    if let Err(err) = run(options) {
+        eprintln!("error: {}", err);
+
+        let mut err: &dyn Error = &*err;
+        while let Some(source) = err.source() {
+            eprintln!("reason: {}", source);
+            err = source;
+        }
+        process::exit(1);
+    }
+}
@@ -0,0 +1,365 @@
+
+use std::{env, fs, process};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::error::Error;
+
+use simulator::{Cpu, Bus, Phase, DecodeMode, Snapshot, SRAM_BASE, load_image, parse_number};
+use simulator::peripherals::{GpioOut, Buttons, Uart};
+use simulator::faults::FaultDetector;
+use simulator::stimulus::Stimulus;
+use simulator::trace::Tracer;
+use simulator::vcd::VcdWriter;
+
+const DEFAULT_CYCLES: u64 = 1_000_000;
+
+struct Options {
+    image_path: PathBuf,
+    cycles: u64,
+    until: Option<String>,
+    stop_on_halt: bool,
+    dumps: Vec<(u16, u16)>,
+    buttons: u8,
+    extended: bool,
+    uart_input: Option<PathBuf>,
+    vcd: Option<PathBuf>,
+    trace: Option<PathBuf>,
+    stimulus: Option<PathBuf>,
+    load_snapshot: Option<PathBuf>,
+    save_snapshot: Option<PathBuf>,
+    decode_mode: DecodeMode,
+    faults: bool,
+    stack: Option<(u16, u16)>,
+}
+
+fn print_usage(exec_path: &str) {
+    eprint!("Usage: ");
+    let exec_path = Path::new(exec_path);
+    if let Some(exec_name) = exec_path.file_name() {
+        eprint!("{}", exec_name.to_string_lossy());
+    } else {
+        eprint!("{}", exec_path.display());
+    }
+    eprintln!(" [OPTIONS] IMAGE");
+    eprintln!();
+    eprintln!("IMAGE is an assembly source (.asm), a hex file (.hex) or a raw binary as written by the assembler.");
+    eprintln!();
+    eprintln!("Options:");
+    eprintln!("    --cycles N            stop after N clock cycles (default {})", DEFAULT_CYCLES);
+    eprintln!("    --until LABEL         stop when the PC reaches LABEL (or an address)");
+    eprintln!("    --no-halt             keep running when the program jumps to itself");
+    eprintln!("    --dump START:END      print the memory from START up to END (can be repeated)");
+    eprintln!("    --buttons VALUE       state of the buttons at 0x0601");
+    eprintln!("    --extended            map the UART, timer and 7-segment display, the UART is connected to stdout");
+    eprintln!("    --uart-input FILE     feed FILE to the UART instead of stdin");
+    eprintln!("    --decode MODE         strict (default) stops at undocumented encodings, hardware executes them like the");
+    eprintln!("                          instruction decoder, signals additionally moves values over the modelled buses and");
+    eprintln!("                          reports bus conflicts");
+    eprintln!("    --stimulus FILE       drive the buttons and the UART with the events in FILE");
+    eprintln!("    --load-snapshot FILE  start from the machine state saved in FILE");
+    eprintln!("    --save-snapshot FILE  save the state of the machine to FILE when the simulation stops");
+    eprintln!("    --trace FILE          write a trace of every executed instruction to FILE");
+    eprintln!("    --vcd FILE            write the registers, the memory bus and the GPIO pins of every cycle to FILE");
+    eprintln!("    --faults              report suspicious behaviour like stores to ROM or reads of uninitialized SRAM");
+    eprintln!("    --stack START:END     also report SP leaving the stack from START up to END (implies --faults)");
+}
+
+fn parse_address(text: &str) -> Option<u16> {
+    parse_number(text).filter(|&value| value <= 0xffff).map(|value| value as u16)
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut image_path = None;
+    let mut options = Options {
+        image_path: PathBuf::new(),
+        cycles: DEFAULT_CYCLES,
+        until: None,
+        stop_on_halt: true,
+        dumps: Vec::new(),
+        buttons: 0,
+        extended: false,
+        uart_input: None,
+        vcd: None,
+        trace: None,
+        stimulus: None,
+        load_snapshot: None,
+        save_snapshot: None,
+        decode_mode: DecodeMode::Strict,
+        faults: false,
+        stack: None,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next()
+            .ok_or_else(|| format!("missing value for {}", name));
+
+        match arg.as_str() {
+            "--cycles" => {
+                let text = value(arg)?;
+                options.cycles = parse_number(text)
+                    .ok_or_else(|| format!("invalid cycle count \"{}\"", text))?;
+            },
+            "--until" => options.until = Some(value(arg)?.clone()),
+            "--no-halt" => options.stop_on_halt = false,
+            "--dump" => {
+                let text = value(arg)?;
+                let range = text.split_once(':')
+                    .and_then(|(start, end)| Some((parse_address(start)?, parse_address(end)?)))
+                    .ok_or_else(|| format!("invalid memory range \"{}\"", text))?;
+                options.dumps.push(range);
+            },
+            "--decode" => {
+                let text = value(arg)?;
+                options.decode_mode = match text.as_str() {
+                    "strict" => DecodeMode::Strict,
+                    "hardware" => DecodeMode::Hardware,
+                    "signals" => DecodeMode::Signals,
+                    _ => return Err(format!("invalid decode mode \"{}\"", text)),
+                };
+            },
+            "--faults" => options.faults = true,
+            "--stack" => {
+                let text = value(arg)?;
+                let range = text.split_once(':')
+                    .and_then(|(start, end)| Some((parse_address(start)?, parse_address(end)?)))
+                    .ok_or_else(|| format!("invalid stack region \"{}\"", text))?;
+                options.stack = Some(range);
+                options.faults = true;
+            },
+            "--buttons" => {
+                let text = value(arg)?;
+                options.buttons = parse_number(text)
+                    .filter(|&value| value <= 0xff)
+                    .ok_or_else(|| format!("invalid button state \"{}\"", text))? as u8;
+            },
+            "--extended" => options.extended = true,
+            "--uart-input" => options.uart_input = Some(PathBuf::from(value(arg)?)),
+            "--vcd" => options.vcd = Some(PathBuf::from(value(arg)?)),
+            "--stimulus" => options.stimulus = Some(PathBuf::from(value(arg)?)),
+            "--load-snapshot" => options.load_snapshot = Some(PathBuf::from(value(arg)?)),
+            "--save-snapshot" => options.save_snapshot = Some(PathBuf::from(value(arg)?)),
+            "--trace" => options.trace = Some(PathBuf::from(value(arg)?)),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ if image_path.is_none() => image_path = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument \"{}\"", arg)),
+        }
+    }
+
+    options.image_path = image_path.ok_or_else(|| "missing image".to_string())?;
+
+    if options.dumps.is_empty() {
+        options.dumps.push((SRAM_BASE, SRAM_BASE + 0x10));
+    }
+
+    Ok(options)
+}
+
+fn print_error(err: &dyn Error) {
+    eprintln!("error: {}", err);
+
+    let mut err = err;
+    while let Some(source) = err.source() {
+        eprintln!("reason: {}", source);
+        err = source;
+    }
+}
+
+fn bargraph(value: u16) -> String {
+    (0..16).rev()
+        .map(|bit| if value & (1 << bit) != 0 { '#' } else { '.' })
+        .collect()
+}
+
+fn run(options: Options) -> Result<(), Box<dyn Error>> {
+    let program = load_image(&options.image_path)?;
+
+    let until = match &options.until {
+        Some(label) => Some(program.labels.get(label).copied()
+            .or_else(|| parse_address(label))
+            .ok_or_else(|| format!("unknown label \"{}\"", label))?),
+        None => None,
+    };
+
+    let bus = if options.extended {
+        let mut bus = Bus::extended(&program.words)?;
+        let uart = bus.device_mut::<Uart>().unwrap();
+        uart.set_output(Box::new(std::io::stdout()));
+        match &options.uart_input {
+            Some(path) => uart.push_input(&fs::read(path)?),
+            None => uart.connect_input(std::io::stdin()),
+        }
+        bus
+    } else {
+        Bus::testbench(&program.words)?
+    };
+
+    let mut cpu = Cpu::new(bus);
+    cpu.set_decode_mode(options.decode_mode);
+    cpu.bus.device_mut::<Buttons>().unwrap().set_state(options.buttons);
+    let mut stimulus = match &options.stimulus {
+        Some(path) => Some(Stimulus::load(path, &program)?),
+        None => None,
+    };
+    if let Some(path) = &options.load_snapshot {
+        Snapshot::load(path)?.restore(&mut cpu)?;
+    }
+
+    let mut vcd = match &options.vcd {
+        Some(path) => Some(VcdWriter::new(BufWriter::new(File::create(path)?), &cpu)?),
+        None => None,
+    };
+    let mut tracer = match &options.trace {
+        Some(path) => Some(Tracer::new(BufWriter::new(File::create(path)?))),
+        None => None,
+    };
+    let mut faults = if options.faults {
+        let mut faults = FaultDetector::new(&cpu, &program);
+        if let Some((start, end)) = options.stack {
+            faults.set_stack_region(start..end);
+        }
+        Some(faults)
+    } else {
+        None
+    };
+
+    let mut conflicts = Vec::new();
+
+    let mut reason = format!("cycle limit of {} reached", options.cycles);
+    while cpu.cycles() < options.cycles {
+        if let Some(stimulus) = &mut stimulus {
+            if stimulus.apply(&mut cpu)? {
+                reason = format!("stimulus stopped the simulation at cycle {}", cpu.cycles());
+                break;
+            }
+        }
+
+        if cpu.phase() == Phase::Fetch {
+            if Some(cpu.registers.pc) == until {
+                reason = format!("reached {} at 0x{:04x}", options.until.as_ref().unwrap(), cpu.registers.pc);
+                break;
+            }
+            if options.stop_on_halt && cpu.is_halted() {
+                reason = format!("halted at 0x{:04x}", cpu.registers.pc);
+                break;
+            }
+        }
+
+        if let Err(err) = cpu.step_cycle() {
+            reason = err.to_string();
+            break;
+        }
+
+        if let Some(vcd) = &mut vcd {
+            vcd.sample(&cpu)?;
+        }
+        if let Some(tracer) = &mut tracer {
+            tracer.sample(&cpu)?;
+        }
+        if let Some(faults) = &mut faults {
+            faults.sample(&cpu);
+        }
+        if let Some(conflict) = cpu.bus_conflict() {
+            if !conflicts.contains(conflict) {
+                conflicts.push(conflict.clone());
+            }
+        }
+    }
+
+    if let Some(vcd) = vcd {
+        vcd.finish(&cpu)?;
+    }
+    if let Some(tracer) = tracer {
+        tracer.finish()?;
+    }
+    if let Some(path) = &options.save_snapshot {
+        Snapshot::capture(&cpu).save(path)?;
+    }
+
+    if options.extended {
+        println!();
+    }
+    println!("Stopped: {}", reason);
+    println!("Cycles: {}, instructions: {}, time: {}", cpu.cycles(), cpu.instructions(), cpu.time());
+    if let Some(stimulus) = &stimulus {
+        println!("Stimulus: {} of {} events fired", stimulus.fired().len(), stimulus.events().len());
+    }
+
+    if options.decode_mode == DecodeMode::Signals {
+        println!();
+        println!("Bus conflicts:");
+        if conflicts.is_empty() {
+            println!("  (none)");
+        }
+        for conflict in &conflicts {
+            println!("  {}", conflict);
+        }
+    }
+
+    if let Some(faults) = &faults {
+        println!();
+        println!("Faults:");
+        if faults.faults().is_empty() {
+            println!("  (none)");
+        }
+        for fault in faults.faults() {
+            println!("  {}", fault);
+            for entry in &fault.trace {
+                println!("      {}", entry);
+            }
+        }
+    }
+    println!();
+
+    let regs = &cpu.registers;
+    println!("Registers:");
+    println!("  A    0x{:04x}", regs.a);
+    println!("  B    0x{:04x}", regs.b);
+    println!("  Addr 0x{:04x}", regs.addr);
+    println!("  SP   0x{:04x}", regs.sp);
+    println!("  SR   0x{:04x} [{}]", regs.sr, regs.format_flags());
+    println!("  PC   0x{:04x}", regs.pc);
+
+    for &(start, end) in &options.dumps {
+        println!();
+        println!("Memory 0x{:04x}-0x{:04x}:", start, end);
+        for row_start in (start as u32..end as u32).step_by(8) {
+            let row_end = (row_start + 8).min(end as u32);
+            let words = (row_start..row_end)
+                .map(|address| format!("{:04x}", cpu.bus.peek(address as u16)))
+                .collect::<Vec<_>>();
+            println!("  {:04x}: {}", row_start, words.join(" "));
+        }
+    }
+
+    println!();
+    println!("GPIO history:");
+    let gpio = cpu.bus.device::<GpioOut>().unwrap();
+    if gpio.history().is_empty() {
+        println!("  (no writes)");
+    }
+    for &(cycle, value) in gpio.history() {
+        println!("  {:>10}: 0x{:04x} {}", cycle, value, bargraph(value));
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let args = env::args().collect::<Vec<_>>();
+
+    let options = match parse_args(&args[1..]) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            print_usage(&args[0]);
+            process::exit(1);
+        },
+    };
+
+    if let Err(err) = run(options) {
+        print_error(&*err);
+        process::exit(1);
+    }
+}
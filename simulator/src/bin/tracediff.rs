@@ -0,0 +1,133 @@
+
+use std::{env, process};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::error::Error;
+
+use simulator::parse_number;
+use simulator::trace::{TraceEntry, read_trace, first_divergence};
+
+const DEFAULT_CONTEXT: usize = 5;
+
+struct Options {
+    expected_path: PathBuf,
+    actual_path: PathBuf,
+    context: usize,
+    compare_cycles: bool,
+}
+
+fn print_usage(exec_path: &str) {
+    eprint!("Usage: ");
+    let exec_path = Path::new(exec_path);
+    if let Some(exec_name) = exec_path.file_name() {
+        eprint!("{}", exec_name.to_string_lossy());
+    } else {
+        eprint!("{}", exec_path.display());
+    }
+    eprintln!(" [OPTIONS] EXPECTED ACTUAL");
+    eprintln!();
+    eprintln!("Compares two traces written by `sim --trace` and reports the first instruction in which they differ.");
+    eprintln!();
+    eprintln!("Options:");
+    eprintln!("    --context N    number of matching instructions to show before the divergence (default {})", DEFAULT_CONTEXT);
+    eprintln!("    --cycles       also compare the cycles in which the instructions were fetched");
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut paths = Vec::new();
+    let mut context = DEFAULT_CONTEXT;
+    let mut compare_cycles = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--context" => {
+                let text = args.next().ok_or("missing value for --context")?;
+                context = parse_number(text)
+                    .ok_or_else(|| format!("invalid context \"{}\"", text))? as usize;
+            },
+            "--cycles" => compare_cycles = true,
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    if paths.len() != 2 {
+        return Err("expected two traces".to_string());
+    }
+    let actual_path = paths.pop().unwrap();
+    let expected_path = paths.pop().unwrap();
+
+    Ok(Options {
+        expected_path,
+        actual_path,
+        context,
+        compare_cycles,
+    })
+}
+
+fn load(path: &Path) -> Result<Vec<TraceEntry>, Box<dyn Error>> {
+    let file = File::open(path)
+        .map_err(|err| format!("failed to open {}: {}", path.display(), err))?;
+    Ok(read_trace(BufReader::new(file))?)
+}
+
+fn print_entry(marker: &str, entry: Option<&TraceEntry>) {
+    match entry {
+        Some(entry) => println!("{} {}", marker, entry),
+        None => println!("{} (end of trace)", marker),
+    }
+}
+
+// Returns whether the traces are the same
+fn run(options: Options) -> Result<bool, Box<dyn Error>> {
+    let expected = load(&options.expected_path)?;
+    let actual = load(&options.actual_path)?;
+
+    let index = match first_divergence(&expected, &actual, options.compare_cycles) {
+        Some(index) => index,
+        None => {
+            println!("Traces match ({} instructions)", expected.len());
+            return Ok(true);
+        },
+    };
+
+    println!("Traces diverge at instruction {}:", index);
+    println!();
+    for entry in &expected[index.saturating_sub(options.context)..index] {
+        print_entry(" ", Some(entry));
+    }
+    print_entry("-", expected.get(index));
+    print_entry("+", actual.get(index));
+
+    Ok(false)
+}
+
+fn main() {
+    let args = env::args().collect::<Vec<_>>();
+
+    let options = match parse_args(&args[1..]) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            print_usage(&args[0]);
+            process::exit(2);
+        },
+    };
+
+    match run(options) {
+        Ok(true) => {},
+        Ok(false) => process::exit(1),
+        Err(err) => {
+            eprintln!("error: {}", err);
+
+            let mut err: &dyn Error = &*err;
+            while let Some(source) = err.source() {
+                eprintln!("reason: {}", source);
+                err = source;
+            }
+            process::exit(2);
+        },
+    }
+}
@@ -0,0 +1,231 @@
+
+use std::any::Any;
+use std::{error, fmt};
+
+use crate::peripherals::{Rom, Ram, GpioOut, Buttons, Uart, Timer, SevenSegment};
+
+// Memory map of src/testbench.json
+pub const ROM_BASE: u16 = 0x0000;
+pub const ROM_SIZE: usize = 1 << 10;
+pub const SRAM_BASE: u16 = 0x0400;
+pub const SRAM_SIZE: usize = 1 << 9;
+pub const GPIO_OUT_ADDRESS: u16 = 0x0600;
+pub const BUTTONS_ADDRESS: u16 = 0x0601;
+
+// Default addresses of the additional simulated peripherals in the IO window
+pub const UART_BASE: u16 = 0x0610;
+pub const TIMER_BASE: u16 = 0x0620;
+pub const SEVEN_SEGMENT_BASE: u16 = 0x0630;
+
+/// A device that can be mapped into the address space of the CPU.
+///
+/// Addresses passed to the device are relative to the start of its mapping.
+pub trait Peripheral: Any {
+    /// Number of words the device occupies
+    fn size(&self) -> usize;
+
+    fn read(&mut self, offset: u16) -> u16;
+
+    /// Reads without side effects, used for inspecting memory from outside of the CPU
+    fn peek(&self, offset: u16) -> u16;
+
+    fn write(&mut self, offset: u16, value: u16);
+
+    /// Called once per clock cycle of the CPU
+    fn tick(&mut self) {}
+
+    /// Internal state of the device for snapshots, devices whose state can't be saved return nothing
+    fn save_state(&self) -> Vec<u16> {
+        Vec::new()
+    }
+
+    /// Restores a state returned by `save_state`
+    fn restore_state(&mut self, _state: &[u16]) {}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// An access of the CPU to the memory bus
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BusAccess {
+    pub kind: AccessKind,
+    pub address: u16,
+    pub data: u16,
+}
+
+#[derive(Debug)]
+pub enum BusError {
+    OutOfRange(String),
+    Overlap(String, String),
+    ProgramTooLarge(usize),
+}
+
+impl fmt::Display for BusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            BusError::OutOfRange(name) => write!(f, "\"{}\" exceeds the address space", name),
+            BusError::Overlap(name, other) => write!(f, "\"{}\" overlaps with \"{}\"", name, other),
+            BusError::ProgramTooLarge(words) => write!(f, "program of {} words doesn't fit into the ROM of {} words", words, ROM_SIZE),
+        }
+    }
+}
+
+impl error::Error for BusError {}
+
+pub struct Mapping {
+    pub name: String,
+    pub base: u16,
+    pub size: usize,
+    device: Box<dyn Peripheral>,
+}
+
+impl Mapping {
+    pub fn contains(&self, address: u16) -> bool {
+        address >= self.base && ((address - self.base) as usize) < self.size
+    }
+
+    pub fn device(&self) -> &dyn Peripheral {
+        &*self.device
+    }
+
+    /// Checks whether the mapped device is of type `T`
+    pub fn is<T: Peripheral>(&self) -> bool {
+        (&*self.device as &dyn Any).is::<T>()
+    }
+}
+
+/// The memory bus, routes accesses of the CPU to the mapped peripherals
+#[derive(Default)]
+pub struct Bus {
+    mappings: Vec<Mapping>,
+}
+
+impl Bus {
+    pub fn new() -> Bus {
+        Bus {
+            mappings: Vec::new(),
+        }
+    }
+
+    /// Creates a bus with the same peripherals as src/testbench.json and `program` loaded into the ROM, fails if the
+    /// program doesn't fit into the ROM
+    pub fn testbench(program: &[u16]) -> Result<Bus, BusError> {
+        let mut rom = Rom::new(ROM_SIZE);
+        if !rom.load(program) {
+            return Err(BusError::ProgramTooLarge(program.len()));
+        }
+
+        let mut bus = Bus::new();
+        bus.map("rom", ROM_BASE, rom).unwrap();
+        bus.map("sram", SRAM_BASE, Ram::new(SRAM_SIZE)).unwrap();
+        bus.map("gpio", GPIO_OUT_ADDRESS, GpioOut::new()).unwrap();
+        bus.map("buttons", BUTTONS_ADDRESS, Buttons::new()).unwrap();
+        Ok(bus)
+    }
+
+    /// Like `testbench`, with a UART, a timer and a 7-segment display added at their default addresses
+    pub fn extended(program: &[u16]) -> Result<Bus, BusError> {
+        let mut bus = Bus::testbench(program)?;
+        bus.map("uart", UART_BASE, Uart::new()).unwrap();
+        bus.map("timer", TIMER_BASE, Timer::new()).unwrap();
+        bus.map("7seg", SEVEN_SEGMENT_BASE, SevenSegment::default()).unwrap();
+        Ok(bus)
+    }
+
+    pub fn map<P: Peripheral>(&mut self, name: &str, base: u16, device: P) -> Result<(), BusError> {
+        let size = device.size();
+
+        if base as usize + size > 0x10000 {
+            return Err(BusError::OutOfRange(name.to_string()));
+        }
+
+        let end = base as usize + size;
+        for mapping in &self.mappings {
+            let other_end = mapping.base as usize + mapping.size;
+            if (base as usize) < other_end && (mapping.base as usize) < end {
+                return Err(BusError::Overlap(name.to_string(), mapping.name.clone()));
+            }
+        }
+
+        self.mappings.push(Mapping {
+            name: name.to_string(),
+            base,
+            size,
+            device: Box::new(device),
+        });
+
+        Ok(())
+    }
+
+    pub fn mappings(&self) -> &[Mapping] {
+        &self.mappings
+    }
+
+    pub fn mapping_at(&self, address: u16) -> Option<&Mapping> {
+        self.mappings.iter()
+            .find(|mapping| mapping.contains(address))
+    }
+
+    pub fn is_mapped(&self, address: u16) -> bool {
+        self.mapping_at(address).is_some()
+    }
+
+    /// Returns the first mapped device of type `T`
+    pub fn device<T: Peripheral>(&self) -> Option<&T> {
+        self.mappings.iter()
+            .find_map(|mapping| (&*mapping.device as &dyn Any).downcast_ref())
+    }
+
+    pub fn device_mut<T: Peripheral>(&mut self) -> Option<&mut T> {
+        self.mappings.iter_mut()
+            .find_map(|mapping| (&mut *mapping.device as &mut dyn Any).downcast_mut())
+    }
+
+    /// Returns the device of type `T` mapped at `address`
+    pub fn device_at<T: Peripheral>(&self, address: u16) -> Option<&T> {
+        self.mapping_at(address)
+            .and_then(|mapping| (&*mapping.device as &dyn Any).downcast_ref())
+    }
+
+    pub fn device_at_mut<T: Peripheral>(&mut self, address: u16) -> Option<&mut T> {
+        self.mappings.iter_mut()
+            .find(|mapping| mapping.contains(address))
+            .and_then(|mapping| (&mut *mapping.device as &mut dyn Any).downcast_mut())
+    }
+
+    /// Restores the state of the device of the mapping at `index` in `mappings`
+    pub fn restore_state(&mut self, index: usize, state: &[u16]) {
+        self.mappings[index].device.restore_state(state);
+    }
+
+    // Nothing drives the data bus when an unmapped address is read, so the CPU sees 0
+    pub fn read(&mut self, address: u16) -> u16 {
+        match self.mappings.iter_mut().find(|mapping| mapping.contains(address)) {
+            Some(mapping) => mapping.device.read(address - mapping.base),
+            None => 0,
+        }
+    }
+
+    pub fn peek(&self, address: u16) -> u16 {
+        match self.mapping_at(address) {
+            Some(mapping) => mapping.device.peek(address - mapping.base),
+            None => 0,
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u16) {
+        if let Some(mapping) = self.mappings.iter_mut().find(|mapping| mapping.contains(address)) {
+            mapping.device.write(address - mapping.base, value);
+        }
+    }
+
+    pub fn tick(&mut self) {
+        for mapping in &mut self.mappings {
+            mapping.device.tick();
+        }
+    }
+}
@@ -0,0 +1,425 @@
+
+use std::collections::VecDeque;
+use std::{error, fmt};
+
+use assembler::Register;
+use circuit::{CircuitError, CircuitFile, ComponentKind, ComponentRef, Simulation};
+
+use crate::bus::{AccessKind, SRAM_BASE, GPIO_OUT_ADDRESS};
+use crate::cpu::{Cpu, Registers, SimulatorError};
+use crate::peripherals::Buttons;
+use crate::trace::TraceEntry;
+
+/// Number of instructions shown before a mismatch
+pub const TRACE_LENGTH: usize = 8;
+
+/// Blocks of CPU.hdl that hold the architectural state, in the order the registers are declared there
+const REGISTER_BLOCKS: [&str; 5] = ["A", "B", "Addr", "SP", "IR"];
+
+#[derive(Debug)]
+pub enum CoSimError {
+    Circuit(CircuitError),
+    Simulator(SimulatorError),
+    /// A block of the CPU that couldn't be found in the circuit, give its instance path explicitly
+    MissingBlock(String),
+    UnknownBlockName(String),
+    UnknownInstance(String),
+    MissingComponent(&'static str),
+    NoClock,
+    NotAToggleButton(usize),
+}
+
+impl fmt::Display for CoSimError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            CoSimError::Circuit(_) => write!(f, "failed to load the gate-level CPU"),
+            CoSimError::Simulator(err) => write!(f, "{}", err),
+            CoSimError::MissingBlock(name) => write!(f, "could not find the block of register {} in the circuit", name),
+            CoSimError::UnknownBlockName(name) => write!(f, "unknown register \"{}\"", name),
+            CoSimError::UnknownInstance(path) => write!(f, "no instance at \"{}\"", path),
+            CoSimError::MissingComponent(type_name) => write!(f, "the main circuit contains no {} or more than one", type_name),
+            CoSimError::NoClock => write!(f, "the main circuit has no clock"),
+            CoSimError::NotAToggleButton(index) => write!(f, "component {} of the main circuit is not a toggle button", index),
+        }
+    }
+}
+
+impl error::Error for CoSimError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            CoSimError::Circuit(err) => Some(err),
+            CoSimError::Simulator(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<CircuitError> for CoSimError {
+    fn from(err: CircuitError) -> CoSimError {
+        CoSimError::Circuit(err)
+    }
+}
+
+impl From<SimulatorError> for CoSimError {
+    fn from(err: SimulatorError) -> CoSimError {
+        CoSimError::Simulator(err)
+    }
+}
+
+/// Instances of the blocks in the gate-level CPU that hold its state.
+///
+/// hdlc doesn't keep the names of block instances, so `detect` relies on their order: the `Register` blocks inside the
+/// `CPU` circuit are A, B, Addr, SP and IR like in CPU.hdl. Paths given to `set` take precedence.
+#[derive(Clone, Debug, Default)]
+pub struct BlockMap {
+    blocks: Vec<(String, usize)>,
+}
+
+impl BlockMap {
+    /// Names that can be mapped: the registers, `IR`, `cycle` for the cycle counter and `GPIO`
+    pub const NAMES: [&'static str; 9] = ["A", "B", "Addr", "SP", "SR", "PC", "IR", "cycle", "GPIO"];
+
+    pub fn detect(simulation: &Simulation) -> BlockMap {
+        let instances = simulation.instances();
+        let cpu = instances.iter().position(|instance| instance.circuit == "CPU").unwrap_or(0);
+
+        let mut children = instances.iter()
+            .enumerate()
+            .filter(|(_, instance)| instance.parent.is_some_and(|parent| parent.instance == cpu))
+            .collect::<Vec<_>>();
+        children.sort_by_key(|(_, instance)| instance.parent.map(|parent| parent.component));
+
+        let mut map = BlockMap::default();
+        let registers = children.iter().filter(|(_, instance)| instance.circuit == "Register");
+        for (&name, &(index, _)) in REGISTER_BLOCKS.iter().zip(registers) {
+            map.blocks.push((name.to_string(), index));
+        }
+        for (name, circuit) in [("SR", "StatusRegister"), ("PC", "ProgramCounter"), ("cycle", "CycleCounter")] {
+            if let Some(&(index, _)) = children.iter().find(|(_, instance)| instance.circuit == circuit) {
+                map.blocks.push((name.to_string(), index));
+            }
+        }
+        if let Some(index) = instances.iter().position(|instance| instance.circuit == "GPIO") {
+            map.blocks.push(("GPIO".to_string(), index));
+        }
+        map
+    }
+
+    /// Maps a block to the instance at `path`, e.g. `main.CPU0.Register3`
+    pub fn set(&mut self, name: &str, path: &str, simulation: &Simulation) -> Result<(), CoSimError> {
+        if !BlockMap::NAMES.contains(&name) {
+            return Err(CoSimError::UnknownBlockName(name.to_string()));
+        }
+        let instance = simulation.instance(path)
+            .ok_or_else(|| CoSimError::UnknownInstance(path.to_string()))?;
+        self.blocks.retain(|(block, _)| block != name);
+        self.blocks.push((name.to_string(), instance));
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<usize> {
+        self.blocks.iter().find(|(block, _)| block == name).map(|&(_, instance)| instance)
+    }
+}
+
+/// The CPU of a circuit file, simulated at gate level and stepped one instruction at a time
+pub struct GateCpu {
+    file: CircuitFile,
+    simulation: Simulation,
+    blocks: BlockMap,
+    sram: ComponentRef,
+    period: u64,
+}
+
+impl GateCpu {
+    /// Loads `program` into the ROM of the main circuit. `run_toggles` are the toggle buttons that have to be switched
+    /// on for the clock to reach the CPU.
+    pub fn new(file: CircuitFile, program: &[u16], run_toggles: &[usize]) -> Result<GateCpu, CoSimError> {
+        let main_circuit = file.main_circuit().ok_or(CoSimError::MissingComponent("circuit"))?;
+        let mut simulation = Simulation::new(&file, main_circuit)?;
+
+        let single = |type_name: &'static str| match simulation.find_components(type_name, &file).as_slice() {
+            [component] => Ok(*component),
+            _ => Err(CoSimError::MissingComponent(type_name)),
+        };
+        let rom = single("rom")?;
+        let sram = single("sram")?;
+        simulation.load_rom(rom, program)?;
+
+        let period = simulation.clock_period().ok_or(CoSimError::NoClock)?;
+        let blocks = BlockMap::detect(&simulation);
+
+        let mut gate_cpu = GateCpu {
+            file,
+            simulation,
+            blocks,
+            sram,
+            period,
+        };
+
+        // Power up with the clock held off and switch it on at the start of a low phase, so that the first rising edge
+        // reaches a settled circuit. Instructions start right before a rising edge, when the cycle counter has settled
+        // after the falling edge.
+        gate_cpu.simulation.settle(gate_cpu.period);
+        let low = gate_cpu.period / 2;
+        let wait = (gate_cpu.period + low - gate_cpu.simulation.time() % gate_cpu.period) % gate_cpu.period;
+        gate_cpu.simulation.run(wait);
+        for &index in run_toggles {
+            if !gate_cpu.simulation.set_source(ComponentRef { instance: 0, component: index }, true) {
+                return Err(CoSimError::NotAToggleButton(index));
+            }
+        }
+        gate_cpu.simulation.run(gate_cpu.period - low);
+
+        Ok(gate_cpu)
+    }
+
+    pub fn simulation(&self) -> &Simulation {
+        &self.simulation
+    }
+
+    /// Maps a block to the instance at `path`, overriding the detected one
+    pub fn set_block(&mut self, name: &str, path: &str) -> Result<(), CoSimError> {
+        self.blocks.set(name, path, &self.simulation)
+    }
+
+    /// Path of the instance a block is mapped to
+    pub fn block_path(&self, name: &str) -> Option<&str> {
+        self.blocks.get(name).map(|instance| self.simulation.instances()[instance].path.as_str())
+    }
+
+    /// Sets the toggle buttons connected to the B inputs of the GPIO circuit
+    pub fn set_buttons(&mut self, state: u8) {
+        let main_circuit = &self.file.circuits[0];
+        let netlist = self.simulation.netlist(0);
+        let mut buttons = Vec::new();
+        for (index, component) in main_circuit.components.iter().enumerate() {
+            if !matches!(&component.kind, ComponentKind::Custom { circuit_name } if circuit_name == "GPIO") {
+                continue;
+            }
+            for bit in 0..8 {
+                let toggle = netlist.net_of(index, &format!("B{}", bit))
+                    .and_then(|net| netlist.drivers(net).find(|pin| matches!(main_circuit.components[pin.component].kind, ComponentKind::ToggleButton)));
+                if let Some(toggle) = toggle {
+                    buttons.push((bit, toggle.component));
+                }
+            }
+        }
+        for (bit, component) in buttons {
+            self.simulation.set_source(ComponentRef { instance: 0, component }, state & (1 << bit) != 0);
+        }
+    }
+
+    // Value of the `register` components in an instance and its children, the first one holds the lowest bits
+    fn instance_value(&self, instance: usize) -> u64 {
+        let mut value = 0;
+        let mut shift = 0;
+        let mut pending = vec![instance];
+        while let Some(instance) = pending.pop() {
+            let circuit = match self.file.circuit(&self.simulation.instances()[instance].circuit) {
+                Some(circuit) => circuit,
+                None => continue,
+            };
+            let mut children = Vec::new();
+            for (component, kind) in circuit.components.iter().map(|component| &component.kind).enumerate() {
+                match kind {
+                    ComponentKind::Register { width } => {
+                        let bits = self.simulation.register(ComponentRef { instance, component }).unwrap_or(0);
+                        if shift < 64 {
+                            value |= bits << shift;
+                        }
+                        shift += width;
+                    },
+                    ComponentKind::Custom { .. } => children.push(component),
+                    _ => {},
+                }
+            }
+            // Visit the children in the order of their components
+            let child_instances = self.simulation.instances().iter()
+                .enumerate()
+                .filter(|(_, child)| child.parent.is_some_and(|parent| parent.instance == instance && children.contains(&parent.component)))
+                .map(|(index, _)| index)
+                .collect::<Vec<_>>();
+            pending.extend(child_instances.into_iter().rev());
+        }
+        value
+    }
+
+    /// Value of a block mapped by `BlockMap`
+    pub fn block(&self, name: &str) -> Result<u16, CoSimError> {
+        let instance = self.blocks.get(name)
+            .ok_or_else(|| CoSimError::MissingBlock(name.to_string()))?;
+        Ok(self.instance_value(instance) as u16)
+    }
+
+    pub fn registers(&self) -> Result<Registers, CoSimError> {
+        Ok(Registers {
+            a: self.block("A")?,
+            b: self.block("B")?,
+            addr: self.block("Addr")?,
+            sp: self.block("SP")?,
+            sr: self.block("SR")?,
+            pc: self.block("PC")?,
+        })
+    }
+
+    pub fn ir(&self) -> Result<u16, CoSimError> {
+        self.block("IR")
+    }
+
+    /// Contents of the SRAM, indexed by the address relative to `SRAM_BASE`
+    pub fn sram(&self) -> Vec<u16> {
+        self.simulation.memory(self.sram).unwrap_or(&[]).iter().map(|&word| word as u16).collect()
+    }
+
+    pub fn gpio(&self) -> Option<u16> {
+        self.block("GPIO").ok()
+    }
+
+    pub fn step_cycle(&mut self) {
+        self.simulation.run(self.period);
+    }
+
+    /// Runs a fetch and an execute cycle. If the cycle counter was found, cycles are added until it is back at fetch.
+    pub fn step_instruction(&mut self) {
+        self.step_cycle();
+        self.step_cycle();
+        for _ in 0..2 {
+            match self.block("cycle") {
+                Ok(0) | Err(_) => break,
+                Ok(_) => self.step_cycle(),
+            }
+        }
+    }
+}
+
+/// The first instruction after which the ISA simulator and the gate-level CPU disagree
+#[derive(Clone, Debug)]
+pub struct Mismatch {
+    pub instruction: u64,
+    pub pc: u16,
+    pub word: u16,
+    pub isa: Registers,
+    pub gate: Registers,
+    pub differences: Vec<String>,
+    /// The last instructions up to and including the one that diverged, as executed by the ISA simulator
+    pub trace: Vec<TraceEntry>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "instruction {} (0x{:04x} at 0x{:04x}): {}", self.instruction, self.word, self.pc, self.differences.join(", "))
+    }
+}
+
+/// Runs a program on the ISA simulator and the gate-level CPU side by side and compares the registers and the memory
+/// writes after every instruction
+pub struct CoSimulation {
+    pub cpu: Cpu,
+    pub gate: GateCpu,
+    trace: VecDeque<TraceEntry>,
+    instructions: u64,
+}
+
+impl CoSimulation {
+    pub fn new(cpu: Cpu, gate: GateCpu) -> CoSimulation {
+        CoSimulation {
+            cpu,
+            gate,
+            trace: VecDeque::new(),
+            instructions: 0,
+        }
+    }
+
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    pub fn set_buttons(&mut self, state: u8) {
+        self.cpu.bus.device_mut::<Buttons>().unwrap().set_state(state);
+        self.gate.set_buttons(state);
+    }
+
+    /// Executes one instruction on both sides, returns the mismatch if they disagree afterwards
+    pub fn step(&mut self) -> Result<Option<Mismatch>, CoSimError> {
+        let before = self.cpu.registers;
+        let cycle = self.cpu.cycles();
+        self.cpu.step_instruction()?;
+        let access = self.cpu.bus_access();
+        let entry = TraceEntry::new(cycle, self.cpu.ir(), &before, &self.cpu.registers, access);
+
+        let sram_before = self.gate.sram();
+        let gpio_before = self.gate.gpio();
+        self.gate.step_instruction();
+        let sram_after = self.gate.sram();
+        let gpio_after = self.gate.gpio();
+
+        if self.trace.len() == TRACE_LENGTH {
+            self.trace.pop_front();
+        }
+        self.trace.push_back(entry);
+        self.instructions += 1;
+
+        let isa = self.cpu.registers;
+        let gate = self.gate.registers()?;
+        let mut differences = Vec::new();
+
+        let gate_ir = self.gate.ir()?;
+        if gate_ir != self.cpu.ir() {
+            differences.push(format!("IR is 0x{:04x} instead of 0x{:04x}", gate_ir, self.cpu.ir()));
+        }
+        for &register in &[Register::A, Register::B, Register::Addr, Register::SP, Register::SR, Register::PC] {
+            if gate.get(register) != isa.get(register) {
+                differences.push(format!("{} is 0x{:04x} instead of 0x{:04x}", crate::isa::register_name(register), gate.get(register), isa.get(register)));
+            }
+        }
+
+        // Writes of the ISA simulator have to show up in the gate-level memory, and nothing else may change there
+        let write = access.filter(|access| access.kind == AccessKind::Write);
+        let sram_index = |address: u16| address.checked_sub(SRAM_BASE).map(usize::from).filter(|&index| index < sram_after.len());
+        let expected_index = write.and_then(|access| sram_index(access.address));
+        if let (Some(access), Some(index)) = (write, expected_index) {
+            if sram_after[index] != access.data {
+                differences.push(format!("[{:04x}] is 0x{:04x} instead of 0x{:04x}", access.address, sram_after[index], access.data));
+            }
+        }
+        for (index, (&old, &new)) in sram_before.iter().zip(&sram_after).enumerate() {
+            if old != new && Some(index) != expected_index {
+                differences.push(format!("unexpected write [{:04x}]<-{:04x}", SRAM_BASE as usize + index, new));
+            }
+        }
+        let gpio_write = write.filter(|access| access.address == GPIO_OUT_ADDRESS);
+        match (gpio_write, gpio_after) {
+            (Some(access), Some(value)) if value != access.data => {
+                differences.push(format!("GPIO is 0x{:04x} instead of 0x{:04x}", value, access.data));
+            },
+            (None, Some(value)) if gpio_before != Some(value) => {
+                differences.push(format!("unexpected GPIO write 0x{:04x}", value));
+            },
+            _ => {},
+        }
+
+        if differences.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Mismatch {
+            instruction: self.instructions - 1,
+            pc: before.pc,
+            word: self.cpu.ir(),
+            isa,
+            gate,
+            differences,
+            trace: self.trace.iter().cloned().collect(),
+        }))
+    }
+
+    /// Steps until the first mismatch or until `limit` instructions were executed
+    pub fn run(&mut self, limit: u64) -> Result<Option<Mismatch>, CoSimError> {
+        for _ in 0..limit {
+            if let Some(mismatch) = self.step()? {
+                return Ok(Some(mismatch));
+            }
+        }
+        Ok(None)
+    }
+}
@@ -0,0 +1,551 @@
+
+use std::{error, fmt};
+
+use assembler::{Register, Condition};
+
+use crate::alu::{self, AluFunction};
+use crate::bus::{Bus, BusAccess, AccessKind, BusError};
+use crate::isa::{Instruction, Operation};
+use crate::signals::{BusConflict, ControlSignals};
+
+// Period of the clock in testbench.json, in LogicSimulator time units
+pub const DEFAULT_CLOCK_PERIOD: u64 = 60;
+
+// Every instruction takes a fetch and an execute cycle, see CycleCounter.hdl
+pub const CYCLES_PER_INSTRUCTION: u64 = 2;
+
+// Bit positions in the status register, see StatusRegister.hdl
+pub const FLAG_CARRY: u16 = 1 << 0;
+pub const FLAG_ZERO: u16 = 1 << 1;
+pub const FLAG_EQUAL: u16 = 1 << 2;
+pub const FLAG_LESS_THAN: u16 = 1 << 3;
+
+#[derive(Debug)]
+pub enum SimulatorError {
+    IllegalInstruction(u16, u16),
+}
+
+impl fmt::Display for SimulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            SimulatorError::IllegalInstruction(address, word) => write!(f, "illegal instruction 0x{:04x} at address 0x{:04x}", word, address),
+        }
+    }
+}
+
+impl error::Error for SimulatorError {}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Registers {
+    pub a: u16,
+    pub b: u16,
+    pub addr: u16,
+    pub sp: u16,
+    pub sr: u16,
+    pub pc: u16,
+}
+
+impl Registers {
+    pub fn get(&self, register: Register) -> u16 {
+        match register {
+            Register::A => self.a,
+            Register::B => self.b,
+            Register::Addr => self.addr,
+            Register::SP => self.sp,
+            Register::SR => self.sr,
+            Register::PC => self.pc,
+        }
+    }
+
+    pub fn set(&mut self, register: Register, value: u16) {
+        match register {
+            Register::A => self.a = value,
+            Register::B => self.b = value,
+            Register::Addr => self.addr = value,
+            Register::SP => self.sp = value,
+            Register::SR => self.sr = value,
+            Register::PC => self.pc = value,
+        }
+    }
+
+    pub fn flag(&self, flag: u16) -> bool {
+        self.sr & flag != 0
+    }
+
+    /// Formats the flags of the status register, e.g. `C=0 Z=1 EQ=1 LT=0`
+    pub fn format_flags(&self) -> String {
+        format!("C={} Z={} EQ={} LT={}",
+                self.flag(FLAG_CARRY) as u8,
+                self.flag(FLAG_ZERO) as u8,
+                self.flag(FLAG_EQUAL) as u8,
+                self.flag(FLAG_LESS_THAN) as u8)
+    }
+
+    fn set_flag(&mut self, flag: u16, value: bool) {
+        if value {
+            self.sr |= flag;
+        } else {
+            self.sr &= !flag;
+        }
+    }
+
+    fn condition_met(&self, condition: Condition) -> bool {
+        // Same logic as ConditionChecker.hdl
+        let zero = self.flag(FLAG_ZERO);
+        let equal = self.flag(FLAG_EQUAL);
+        let less_than = self.flag(FLAG_LESS_THAN);
+
+        match condition {
+            Condition::None => true,
+            Condition::Zero => zero,
+            Condition::Equal => equal,
+            Condition::NotEqual => !equal,
+            Condition::LessThan => less_than,
+            Condition::LessThanOrEqual => less_than || equal,
+            Condition::GreaterThan => !less_than && !equal,
+            Condition::GreaterThanOrEqual => !less_than,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Fetch,
+    Execute,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TimingMode {
+    /// `step` executes a whole instruction
+    Instruction,
+    /// `step` advances the CPU by a single clock cycle, alternating between fetch and execute like the hardware
+    Cycle,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DecodeMode {
+    /// Only the encodings the assembler emits are executed, anything else is an illegal instruction
+    Strict,
+    /// Every word is executed the way InstructionDecoder.hdl decodes it, see `Operation`
+    Hardware,
+    /// Like `Hardware`, but the execute cycle evaluates the control signals of the decoder and moves the values over
+    /// the data bus like CPU.hdl does, see `ControlSignals`. Instructions that enable more than one bus driver are
+    /// recorded as bus conflicts.
+    Signals,
+}
+
+/// Everything the CPU itself stores, without the memory and peripherals on the bus
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CpuState {
+    pub registers: Registers,
+    pub ir: u16,
+    pub phase: Phase,
+    pub instructions: u64,
+    pub cycles: u64,
+}
+
+pub struct Cpu {
+    pub registers: Registers,
+    ir: u16,
+    phase: Phase,
+    pub bus: Bus,
+    bus_access: Option<BusAccess>,
+    bus_conflict: Option<BusConflict>,
+    instructions: u64,
+    cycles: u64,
+    timing_mode: TimingMode,
+    decode_mode: DecodeMode,
+    clock_period: u64,
+}
+
+impl Cpu {
+    pub fn new(bus: Bus) -> Cpu {
+        Cpu {
+            registers: Registers::default(),
+            ir: 0,
+            // The cycle counter powers up in the fetch phase
+            phase: Phase::Fetch,
+            bus,
+            bus_access: None,
+            bus_conflict: None,
+            instructions: 0,
+            cycles: 0,
+            timing_mode: TimingMode::Instruction,
+            decode_mode: DecodeMode::Strict,
+            clock_period: DEFAULT_CLOCK_PERIOD,
+        }
+    }
+
+    /// Creates a CPU connected to the peripherals of the testbench, with `program` loaded into the ROM, see
+    /// `Bus::testbench`
+    pub fn with_program(program: &[u16]) -> Result<Cpu, BusError> {
+        Ok(Cpu::new(Bus::testbench(program)?))
+    }
+
+    pub fn state(&self) -> CpuState {
+        CpuState {
+            registers: self.registers,
+            ir: self.ir,
+            phase: self.phase,
+            instructions: self.instructions,
+            cycles: self.cycles,
+        }
+    }
+
+    /// Restores the CPU without touching the bus, the last memory access is forgotten
+    pub fn restore_state(&mut self, state: &CpuState) {
+        self.registers = state.registers;
+        self.ir = state.ir;
+        self.phase = state.phase;
+        self.instructions = state.instructions;
+        self.cycles = state.cycles;
+        self.bus_access = None;
+        self.bus_conflict = None;
+    }
+
+    /// The instruction register, holds the instruction fetched in the last fetch cycle
+    pub fn ir(&self) -> u16 {
+        self.ir
+    }
+
+    /// The phase of the next clock cycle
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    /// The memory access of the last clock cycle, if there was one
+    pub fn bus_access(&self) -> Option<BusAccess> {
+        self.bus_access
+    }
+
+    /// The bus conflict of the last clock cycle, only detected in the `Signals` decode mode
+    pub fn bus_conflict(&self) -> Option<&BusConflict> {
+        self.bus_conflict.as_ref()
+    }
+
+    /// Number of instructions executed so far
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Number of clock cycles executed so far
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn timing_mode(&self) -> TimingMode {
+        self.timing_mode
+    }
+
+    pub fn set_timing_mode(&mut self, timing_mode: TimingMode) {
+        self.timing_mode = timing_mode;
+    }
+
+    pub fn decode_mode(&self) -> DecodeMode {
+        self.decode_mode
+    }
+
+    pub fn set_decode_mode(&mut self, decode_mode: DecodeMode) {
+        self.decode_mode = decode_mode;
+    }
+
+    pub fn clock_period(&self) -> u64 {
+        self.clock_period
+    }
+
+    pub fn set_clock_period(&mut self, clock_period: u64) {
+        self.clock_period = clock_period;
+    }
+
+    /// Converts a number of clock cycles into LogicSimulator time
+    pub fn cycles_to_time(&self, cycles: u64) -> u64 {
+        cycles * self.clock_period
+    }
+
+    /// Simulated LogicSimulator time elapsed so far
+    pub fn time(&self) -> u64 {
+        self.cycles_to_time(self.cycles)
+    }
+
+    /// Checks whether the CPU is stuck in an unconditional jump to itself, the usual way to end a program.
+    ///
+    /// Both a jump to its own address and `ldi` of its own address followed by `jmp A` are recognized.
+    pub fn is_halted(&self) -> bool {
+        if self.phase != Phase::Fetch {
+            return false;
+        }
+
+        let pc = self.registers.pc;
+        let jump_source = |word| match Instruction::decode(word) {
+            Some(Instruction::Jmp(Condition::None, source)) | Some(Instruction::Mov(Register::PC, source)) => Some(source),
+            _ => None,
+        };
+
+        match Instruction::decode(self.bus.peek(pc)) {
+            Some(Instruction::Ldi(value)) if value == pc => match jump_source(self.bus.peek(pc.wrapping_add(1))) {
+                Some(Register::A) => true,
+                Some(source) => self.registers.get(source) == pc,
+                None => false,
+            },
+            _ => jump_source(self.bus.peek(pc)).map(|source| self.registers.get(source)) == Some(pc),
+        }
+    }
+
+    /// Executes an instruction or a single clock cycle, depending on the timing mode
+    pub fn step(&mut self) -> Result<(), SimulatorError> {
+        match self.timing_mode {
+            TimingMode::Instruction => self.step_instruction(),
+            TimingMode::Cycle => self.step_cycle(),
+        }
+    }
+
+    /// Finishes the current instruction, starting a new one if the CPU is about to fetch
+    pub fn step_instruction(&mut self) -> Result<(), SimulatorError> {
+        if self.phase == Phase::Fetch {
+            self.step_cycle()?;
+        }
+        self.step_cycle()
+    }
+
+    pub fn step_cycle(&mut self) -> Result<(), SimulatorError> {
+        self.bus_access = None;
+        self.bus_conflict = None;
+
+        match self.phase {
+            Phase::Fetch => {
+                self.ir = self.bus.read(self.registers.pc);
+                self.bus_access = Some(BusAccess {
+                    kind: AccessKind::Read,
+                    address: self.registers.pc,
+                    data: self.ir,
+                });
+                self.phase = Phase::Execute;
+            },
+            Phase::Execute => {
+                match self.decode_mode {
+                    DecodeMode::Strict => {
+                        let instruction = Instruction::decode(self.ir)
+                            .ok_or(SimulatorError::IllegalInstruction(self.registers.pc, self.ir))?;
+
+                        self.execute(instruction);
+                    },
+                    DecodeMode::Hardware => self.execute_operation(Operation::decode(self.ir)),
+                    DecodeMode::Signals => self.execute_signals(ControlSignals::decode(self.ir)),
+                }
+                self.instructions += 1;
+                self.phase = Phase::Fetch;
+            },
+        }
+
+        self.cycles += 1;
+        self.bus.tick();
+
+        Ok(())
+    }
+
+    /// Executes `count` instructions or clock cycles, depending on the timing mode.
+    ///
+    /// Stops early at the first illegal instruction.
+    pub fn run(&mut self, count: u64) -> Result<(), SimulatorError> {
+        for _ in 0..count {
+            self.step()?;
+        }
+
+        Ok(())
+    }
+
+    fn execute(&mut self, instruction: Instruction) {
+        let regs = &mut self.registers;
+
+        // The PC only increments if it wasn't loaded by the instruction
+        let mut next_pc = regs.pc.wrapping_add(1);
+
+        match instruction {
+            Instruction::Mov(target, source) => {
+                let value = regs.get(source);
+                if target == Register::PC {
+                    next_pc = value;
+                } else {
+                    regs.set(target, value);
+                }
+            },
+            Instruction::Ld => {
+                regs.a = self.bus.read(regs.addr);
+                self.bus_access = Some(BusAccess {
+                    kind: AccessKind::Read,
+                    address: regs.addr,
+                    data: regs.a,
+                });
+            },
+            Instruction::Ldi(value) => regs.a = value,
+            Instruction::St => {
+                self.bus.write(regs.addr, regs.a);
+                self.bus_access = Some(BusAccess {
+                    kind: AccessKind::Write,
+                    address: regs.addr,
+                    data: regs.a,
+                });
+            },
+            Instruction::And => Cpu::alu(regs, AluFunction::And, None),
+            Instruction::Andi(value) => Cpu::alu(regs, AluFunction::And, Some(value)),
+            Instruction::Or => Cpu::alu(regs, AluFunction::Or, None),
+            Instruction::Ori(value) => Cpu::alu(regs, AluFunction::Or, Some(value)),
+            Instruction::Xor => Cpu::alu(regs, AluFunction::Xor, None),
+            Instruction::Xori(value) => Cpu::alu(regs, AluFunction::Xor, Some(value)),
+            Instruction::Not => Cpu::alu(regs, AluFunction::Not, None),
+            Instruction::Add => Cpu::alu(regs, AluFunction::Add, None),
+            Instruction::Addi(value) => Cpu::alu(regs, AluFunction::Add, Some(value)),
+            Instruction::Sub => Cpu::alu(regs, AluFunction::Subtract, None),
+            Instruction::Sl => Cpu::alu(regs, AluFunction::ShiftLeft, None),
+            Instruction::Sli(value) => Cpu::alu(regs, AluFunction::ShiftLeft, Some(value)),
+            Instruction::Sr => Cpu::alu(regs, AluFunction::ShiftRight, None),
+            Instruction::Sri(value) => Cpu::alu(regs, AluFunction::ShiftRight, Some(value)),
+            Instruction::Cmp => Cpu::compare(regs, None),
+            Instruction::Cmpi(value) => Cpu::compare(regs, Some(value)),
+            Instruction::Jmp(condition, source) => {
+                if regs.condition_met(condition) {
+                    next_pc = regs.get(source);
+                }
+            },
+        }
+
+        regs.pc = next_pc;
+    }
+
+    fn execute_operation(&mut self, operation: Operation) {
+        let regs = &mut self.registers;
+        // Without a selected source register nothing drives the data bus
+        let source_value = |regs: &Registers, source: Option<Register>| source.map_or(0, |source| regs.get(source));
+
+        match operation {
+            Operation::Instruction(instruction) => return self.execute(instruction),
+            Operation::Mov(target, source) => {
+                let value = source_value(regs, source);
+                match target {
+                    Some(Register::PC) => {
+                        regs.pc = value;
+                        return;
+                    },
+                    Some(target) => regs.set(target, value),
+                    None => {},
+                }
+            },
+            Operation::Jmp(condition, source) => if regs.condition_met(condition) {
+                regs.pc = source_value(regs, source);
+                return;
+            },
+            Operation::Nop => {},
+        }
+
+        regs.pc = regs.pc.wrapping_add(1);
+    }
+
+    // Evaluates one execute cycle of CPU.hdl
+    fn execute_signals(&mut self, signals: ControlSignals) {
+        let regs = &mut self.registers;
+
+        let immediate = signals.immediate_value;
+        let operand2 = if signals.operand2_is_immediate { immediate } else { regs.b };
+        let alu = alu::evaluate(AluFunction::from_code(signals.alu_fn), regs.a, operand2);
+
+        // All enabled sources are ORed onto the data bus
+        let mut data_bus = 0;
+        for (enabled, value) in [
+            (signals.immediate_out, immediate),
+            (signals.a_out, regs.a),
+            (signals.b_out, regs.b),
+            (signals.addr_out, regs.addr),
+            (signals.sp_out, regs.sp),
+            (signals.sr_out, regs.sr),
+            (signals.pc_out, regs.pc),
+            (signals.alu_out, alu.result),
+        ] {
+            if enabled {
+                data_bus |= value;
+            }
+        }
+        if signals.mem_read {
+            let value = self.bus.read(regs.addr);
+            data_bus |= value;
+            self.bus_access = Some(BusAccess {
+                kind: AccessKind::Read,
+                address: regs.addr,
+                data: value,
+            });
+        }
+        if signals.mem_write {
+            self.bus.write(regs.addr, data_bus);
+            self.bus_access = Some(BusAccess {
+                kind: AccessKind::Write,
+                address: regs.addr,
+                data: data_bus,
+            });
+        }
+
+        let drivers = signals.data_bus_drivers();
+        if drivers.len() > 1 {
+            self.bus_conflict = Some(BusConflict {
+                pc: regs.pc,
+                word: self.ir,
+                drivers,
+            });
+        }
+
+        // Registers load the data bus at the end of the cycle, the condition uses the flags from before
+        let jump = signals.pc_in && regs.condition_met(Condition::from_code(signals.pc_in_cond).unwrap());
+        if signals.a_in {
+            regs.a = data_bus;
+        }
+        if signals.b_in {
+            regs.b = data_bus;
+        }
+        if signals.addr_in {
+            regs.addr = data_bus;
+        }
+        if signals.sp_in {
+            regs.sp = data_bus;
+        }
+
+        // Masked load of StatusRegister.hdl, the flag inputs replace the lower bits of the bus value
+        let flags = [
+            (signals.sr_carry_in, FLAG_CARRY, alu.carry),
+            (signals.sr_zero_in, FLAG_ZERO, alu.zero),
+            (signals.sr_equal_in, FLAG_EQUAL, alu.equal),
+            (signals.sr_less_than_in, FLAG_LESS_THAN, alu.less_than),
+        ];
+        let mut flag_mask = 0;
+        let mut flag_values = 0;
+        for (load, flag, value) in flags {
+            if load {
+                flag_mask |= flag;
+                if value {
+                    flag_values |= flag;
+                }
+            }
+        }
+        let value_in = if signals.sr_in { data_bus } else { 0 };
+        let mask = if signals.sr_in { 0xffff } else { flag_mask };
+        let merged = value_in & !flag_mask | flag_values;
+        regs.sr = merged & mask | regs.sr & !mask;
+
+        regs.pc = if jump { data_bus } else { regs.pc.wrapping_add(1) };
+    }
+
+    fn alu(regs: &mut Registers, function: AluFunction, immediate: Option<u16>) {
+        let output = alu::evaluate(function, regs.a, immediate.unwrap_or(regs.b));
+
+        regs.a = output.result;
+        regs.set_flag(FLAG_ZERO, output.zero);
+        // Only addition and subtraction update the carry flag
+        if function == AluFunction::Add || function == AluFunction::Subtract {
+            regs.set_flag(FLAG_CARRY, output.carry);
+        }
+    }
+
+    fn compare(regs: &mut Registers, immediate: Option<u16>) {
+        let output = alu::evaluate(AluFunction::Subtract, regs.a, immediate.unwrap_or(regs.b));
+
+        regs.set_flag(FLAG_EQUAL, output.equal);
+        regs.set_flag(FLAG_LESS_THAN, output.less_than);
+    }
+}
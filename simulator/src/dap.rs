@@ -0,0 +1,489 @@
+
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+use serde_json::{json, Value};
+
+use assembler::Register;
+
+use crate::bus::{Bus, SRAM_BASE, SRAM_SIZE};
+use crate::cpu::{Cpu, FLAG_CARRY, FLAG_ZERO, FLAG_EQUAL, FLAG_LESS_THAN};
+use crate::debugger::{Session, StopReason};
+use crate::image::load_image;
+use crate::isa::register_name;
+
+// The CPU has a single thread of execution
+const THREAD_ID: u64 = 1;
+
+const REGISTERS_REFERENCE: u64 = 1;
+const FLAGS_REFERENCE: u64 = 2;
+const SRAM_REFERENCE: u64 = 3;
+
+const REGISTERS: [Register; 6] = [Register::A, Register::B, Register::Addr, Register::SP, Register::SR, Register::PC];
+const FLAGS: [(&str, u16); 4] = [("C", FLAG_CARRY), ("Z", FLAG_ZERO), ("EQ", FLAG_EQUAL), ("LT", FLAG_LESS_THAN)];
+
+struct Adapter<W> {
+    writer: W,
+    seq: u64,
+    session: Option<Session>,
+    source_path: PathBuf,
+    stop_on_entry: bool,
+}
+
+/// Serves the Debug Adapter Protocol on `reader` and `writer` until the client disconnects.
+///
+/// Like the GDB stub, memory references are byte addresses with each word at `2 * address`.
+pub fn serve<R: BufRead, W: Write>(mut reader: R, writer: W) -> io::Result<()> {
+    let mut adapter = Adapter {
+        writer,
+        seq: 1,
+        session: None,
+        source_path: PathBuf::new(),
+        stop_on_entry: false,
+    };
+
+    while let Some(message) = read_message(&mut reader)? {
+        if message["type"] == "request" && !adapter.handle(&message)? {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Value>> {
+    let mut content_length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(length) = line.strip_prefix("Content-Length:") {
+            content_length = length.trim().parse::<usize>().ok();
+        }
+    }
+
+    let content_length = content_length
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header"))?;
+    let mut content = vec![0; content_length];
+    reader.read_exact(&mut content)?;
+
+    serde_json::from_slice(&content)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn encode_base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::new();
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| bits | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+fn parse_value(text: &str) -> Option<u16> {
+    let value = match text.strip_prefix("0x") {
+        Some(digits) => u32::from_str_radix(digits, 16).ok()?,
+        None => text.parse().ok()?,
+    };
+    if value <= 0xffff {
+        Some(value as u16)
+    } else {
+        None
+    }
+}
+
+impl<W: Write> Adapter<W> {
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        message["seq"] = json!(self.seq);
+        self.seq += 1;
+
+        let content = message.to_string();
+        write!(self.writer, "Content-Length: {}\r\n\r\n{}", content.len(), content)?;
+        self.writer.flush()
+    }
+
+    fn respond(&mut self, request: &Value, result: Result<Value, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+        });
+        match result {
+            Ok(body) => {
+                response["success"] = json!(true);
+                response["body"] = body;
+            },
+            Err(message) => {
+                response["success"] = json!(false);
+                response["message"] = json!(message);
+            },
+        }
+        self.send(response)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({
+            "type": "event",
+            "event": event,
+            "body": body,
+        }))
+    }
+
+    fn stopped(&mut self, reason: StopReason) -> io::Result<()> {
+        let (reason, description) = match reason {
+            StopReason::Step => ("step", None),
+            StopReason::Breakpoint(_) => ("breakpoint", None),
+            StopReason::Watchpoint(_) => ("data breakpoint", None),
+            StopReason::Halted => ("pause", Some("Program halted".to_string())),
+            StopReason::Limit => ("pause", Some("Stopped after running for too long".to_string())),
+            StopReason::HistoryStart => ("pause", Some("Reached the start of the recorded history".to_string())),
+            StopReason::Error(err) => ("exception", Some(err.to_string())),
+        };
+
+        let mut body = json!({
+            "reason": reason,
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        });
+        if let Some(description) = description {
+            body["description"] = json!(description);
+            body["text"] = json!(description);
+        }
+        self.event("stopped", body)
+    }
+
+    // Returns `false` when the client disconnected
+    fn handle(&mut self, request: &Value) -> io::Result<bool> {
+        let command = request["command"].as_str().unwrap_or("");
+        let args = &request["arguments"];
+
+        match command {
+            "initialize" => {
+                self.respond(request, Ok(json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsSetVariable": true,
+                    "supportsReadMemoryRequest": true,
+                    "supportsStepBack": true,
+                })))?;
+                self.event("initialized", json!({}))?;
+            },
+            "launch" => {
+                let result = self.launch(args);
+                self.respond(request, result)?;
+            },
+            "setBreakpoints" => {
+                let result = self.set_breakpoints(args);
+                self.respond(request, result)?;
+            },
+            "setExceptionBreakpoints" => self.respond(request, Ok(json!({})))?,
+            "configurationDone" => {
+                self.respond(request, Ok(json!({})))?;
+                if self.stop_on_entry {
+                    self.event("stopped", json!({
+                        "reason": "entry",
+                        "threadId": THREAD_ID,
+                        "allThreadsStopped": true,
+                    }))?;
+                } else {
+                    self.resume(Session::run)?;
+                }
+            },
+            "threads" => self.respond(request, Ok(json!({
+                "threads": [{ "id": THREAD_ID, "name": "CPU" }],
+            })))?,
+            "stackTrace" => {
+                let result = self.stack_trace();
+                self.respond(request, result)?;
+            },
+            "scopes" => self.respond(request, Ok(json!({
+                "scopes": [
+                    { "name": "Registers", "variablesReference": REGISTERS_REFERENCE, "expensive": false },
+                    { "name": "Flags", "variablesReference": FLAGS_REFERENCE, "expensive": false },
+                    {
+                        "name": "SRAM",
+                        "variablesReference": SRAM_REFERENCE,
+                        "indexedVariables": SRAM_SIZE,
+                        "expensive": true,
+                    },
+                ],
+            })))?,
+            "variables" => {
+                let result = self.variables(args);
+                self.respond(request, result)?;
+            },
+            "setVariable" => {
+                let result = self.set_variable(args);
+                self.respond(request, result)?;
+            },
+            "readMemory" => {
+                let result = self.read_memory(args);
+                self.respond(request, result)?;
+            },
+            "continue" => {
+                self.respond(request, Ok(json!({ "allThreadsContinued": true })))?;
+                self.resume(Session::continue_execution)?;
+            },
+            "next" => {
+                self.respond(request, Ok(json!({})))?;
+                self.resume(Session::step_over)?;
+            },
+            "stepBack" => {
+                self.respond(request, Ok(json!({})))?;
+                self.resume(Session::step_back)?;
+            },
+            "reverseContinue" => {
+                self.respond(request, Ok(json!({})))?;
+                self.resume(Session::reverse_continue)?;
+            },
+            // There are no calls to step into, so this is a single step
+            "stepIn" => {
+                self.respond(request, Ok(json!({})))?;
+                self.resume(Session::step)?;
+            },
+            // Subroutines return with an ordinary jump to an address of their choice, there is no return to run to
+            "stepOut" => self.respond(request, Err("step out is not supported, the CPU has no subroutine calls".to_string()))?,
+            // Execution is synchronous, the program is already stopped when the request arrives
+            "pause" => {
+                self.respond(request, Ok(json!({})))?;
+                self.stopped(StopReason::Step)?;
+            },
+            "disconnect" | "terminate" => {
+                self.respond(request, Ok(json!({})))?;
+                self.event("terminated", json!({}))?;
+                return Ok(false);
+            },
+            _ => self.respond(request, Err(format!("unsupported request \"{}\"", command)))?,
+        }
+
+        Ok(true)
+    }
+
+    fn resume<F: FnOnce(&mut Session) -> StopReason>(&mut self, run: F) -> io::Result<()> {
+        match self.session.as_mut() {
+            Some(session) => {
+                let reason = run(session);
+                self.stopped(reason)
+            },
+            None => Ok(()),
+        }
+    }
+
+    fn session(&self) -> Result<&Session, String> {
+        self.session.as_ref().ok_or_else(|| "no program launched".to_string())
+    }
+
+    fn session_mut(&mut self) -> Result<&mut Session, String> {
+        self.session.as_mut().ok_or_else(|| "no program launched".to_string())
+    }
+
+    fn launch(&mut self, args: &Value) -> Result<Value, String> {
+        let path = args["program"].as_str()
+            .ok_or("missing program")?;
+        let path = fs::canonicalize(path)
+            .map_err(|err| format!("{}: {}", path, err))?;
+
+        let program = load_image(&path).map_err(|err| err.to_string())?;
+        let source = fs::read_to_string(&path).ok();
+
+        let cpu = Cpu::new(Bus::testbench(&program.words).map_err(|err| err.to_string())?);
+        self.session = Some(Session::new(cpu, program, source.as_deref()));
+        self.source_path = path;
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+
+        Ok(json!({}))
+    }
+
+    // Replaces the breakpoints of one source file. Only the launched program has code, breakpoints in any other file
+    // are left unverified and don't touch those of the program.
+    fn set_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let session = self.session.as_mut().ok_or("no program launched")?;
+
+        let lines: Vec<u64> = args["breakpoints"].as_array()
+            .map(|breakpoints| breakpoints.iter().filter_map(|breakpoint| breakpoint["line"].as_u64()).collect())
+            .unwrap_or_default();
+
+        let path = args["source"]["path"].as_str().map(|path| fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path)));
+        if path.as_ref() != Some(&self.source_path) {
+            let breakpoints = lines.iter()
+                .map(|&line| json!({
+                    "verified": false,
+                    "line": line,
+                    "message": "Not part of the program",
+                }))
+                .collect::<Vec<_>>();
+            return Ok(json!({ "breakpoints": breakpoints }));
+        }
+
+        session.clear_breakpoints();
+
+        let breakpoints = lines.iter()
+            .map(|&line| match session.program.address_of_line(line as usize) {
+                Some(address) => {
+                    session.add_breakpoint(address);
+                    json!({
+                        "verified": true,
+                        "line": session.program.line_of(address),
+                        "instructionReference": format!("0x{:x}", address as u32 * 2),
+                    })
+                },
+                None => json!({
+                    "verified": false,
+                    "line": line,
+                    "message": "No code at this line",
+                }),
+            })
+            .collect::<Vec<_>>();
+
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn stack_trace(&self) -> Result<Value, String> {
+        let session = self.session()?;
+        let pc = session.cpu.registers.pc;
+
+        // Name the frame after the closest label before the PC
+        let name = session.program.labels.iter()
+            .filter(|(_, &address)| address <= pc)
+            .max_by_key(|(_, &address)| address)
+            .map(|(label, _)| label.clone())
+            .unwrap_or_else(|| format!("0x{:04x}", pc));
+
+        let mut frame = json!({
+            "id": 0,
+            "name": name,
+            "line": 0,
+            "column": 0,
+            "instructionPointerReference": format!("0x{:x}", pc as u32 * 2),
+        });
+        if let Some(line) = session.program.line_of(pc) {
+            frame["line"] = json!(line);
+            frame["column"] = json!(1);
+            frame["source"] = json!({
+                "name": self.source_path.file_name().map(|name| name.to_string_lossy().into_owned()),
+                "path": self.source_path.to_string_lossy(),
+            });
+        }
+
+        Ok(json!({
+            "stackFrames": [frame],
+            "totalFrames": 1,
+        }))
+    }
+
+    fn variables(&self, args: &Value) -> Result<Value, String> {
+        let session = self.session()?;
+        let registers = &session.cpu.registers;
+
+        let variables = match args["variablesReference"].as_u64() {
+            Some(REGISTERS_REFERENCE) => REGISTERS.iter()
+                .map(|&register| {
+                    let value = registers.get(register);
+                    let mut variable = json!({
+                        "name": register_name(register),
+                        "value": format!("0x{:04x}", value),
+                        "variablesReference": 0,
+                    });
+                    if register == Register::Addr || register == Register::SP {
+                        variable["memoryReference"] = json!(format!("0x{:x}", value as u32 * 2));
+                    }
+                    variable
+                })
+                .collect(),
+            Some(FLAGS_REFERENCE) => FLAGS.iter()
+                .map(|&(name, flag)| json!({
+                    "name": name,
+                    "value": if registers.flag(flag) { "1" } else { "0" },
+                    "variablesReference": 0,
+                }))
+                .collect(),
+            Some(SRAM_REFERENCE) => {
+                let start = args["start"].as_u64().unwrap_or(0).min(SRAM_SIZE as u64) as usize;
+                let count = args["count"].as_u64().map_or(SRAM_SIZE, |count| count as usize).min(SRAM_SIZE - start);
+                (start..start + count)
+                    .map(|offset| {
+                        let address = SRAM_BASE + offset as u16;
+                        json!({
+                            "name": format!("0x{:04x}", address),
+                            "value": format!("0x{:04x}", session.cpu.bus.peek(address)),
+                            "variablesReference": 0,
+                            "memoryReference": format!("0x{:x}", address as u32 * 2),
+                        })
+                    })
+                    .collect()
+            },
+            _ => return Err("unknown variables reference".to_string()),
+        };
+
+        Ok(json!({ "variables": Value::Array(variables) }))
+    }
+
+    fn set_variable(&mut self, args: &Value) -> Result<Value, String> {
+        let name = args["name"].as_str().unwrap_or("");
+        let text = args["value"].as_str().unwrap_or("");
+        let value = parse_value(text.trim())
+            .ok_or_else(|| format!("invalid value \"{}\"", text))?;
+
+        let session = self.session_mut()?;
+        match args["variablesReference"].as_u64() {
+            Some(REGISTERS_REFERENCE) => {
+                let register = Register::from_str(name).ok_or("unknown register")?;
+                session.cpu.registers.set(register, value);
+            },
+            Some(FLAGS_REFERENCE) => {
+                let flag = FLAGS.iter().find(|&&(flag_name, _)| flag_name == name).ok_or("unknown flag")?.1;
+                let sr = &mut session.cpu.registers.sr;
+                match value {
+                    0 => *sr &= !flag,
+                    1 => *sr |= flag,
+                    _ => return Err("flags can only be 0 or 1".to_string()),
+                }
+            },
+            Some(SRAM_REFERENCE) => {
+                let address = parse_value(name).ok_or("unknown address")?;
+                session.poke(address, value);
+            },
+            _ => return Err("unknown variables reference".to_string()),
+        }
+
+        Ok(json!({ "value": format!("0x{:04x}", value) }))
+    }
+
+    fn read_memory(&self, args: &Value) -> Result<Value, String> {
+        let session = self.session()?;
+
+        let reference = args["memoryReference"].as_str().unwrap_or("");
+        let base = reference.strip_prefix("0x")
+            .and_then(|digits| i64::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| format!("invalid memory reference \"{}\"", reference))?;
+        let start = (base + args["offset"].as_i64().unwrap_or(0)).clamp(0, 0x20000);
+        let end = (start + args["count"].as_i64().unwrap_or(0).max(0)).min(0x20000);
+
+        let data = (start..end)
+            .map(|byte_address| {
+                let word = session.cpu.bus.peek((byte_address / 2) as u16);
+                (word >> (8 * (byte_address % 2))) as u8
+            })
+            .collect::<Vec<_>>();
+
+        Ok(json!({
+            "address": format!("0x{:x}", start),
+            "data": encode_base64(&data),
+            "unreadableBytes": 0,
+        }))
+    }
+}
@@ -0,0 +1,389 @@
+
+use std::collections::{BTreeSet, VecDeque};
+
+use assembler::{Program, Register};
+
+use crate::cpu::{Cpu, CpuState, DecodeMode, SimulatorError};
+use crate::isa::{Instruction, Operation};
+use crate::peripherals::{Rom, Ram};
+use crate::signals::ControlSignals;
+use crate::snapshot::{Snapshot, SnapshotError};
+
+// Upper bound for `next` and `continue` so that a program that never stops doesn't hang the debugger
+pub const DEFAULT_RUN_LIMIT: u64 = 10_000_000;
+
+// Number of instructions that can be undone, the oldest ones are dropped first
+pub const DEFAULT_HISTORY_LIMIT: usize = 1_000_000;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access,
+}
+
+impl WatchKind {
+    fn matches(self, write: bool) -> bool {
+        match self {
+            WatchKind::Read => !write,
+            WatchKind::Write => write,
+            WatchKind::Access => true,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub kind: WatchKind,
+    pub address: u16,
+}
+
+#[derive(Debug)]
+pub enum StopReason {
+    Step,
+    Breakpoint(u16),
+    /// The last instruction accessed the address of the watchpoint
+    Watchpoint(Watchpoint),
+    Halted,
+    Limit,
+    /// Stepping backwards reached the oldest recorded instruction
+    HistoryStart,
+    Error(SimulatorError),
+}
+
+// Everything an instruction changes. Memory is the only part of the machine that is saved selectively, the other
+// devices are small enough to save their whole state.
+struct Delta {
+    cpu: CpuState,
+    memory: Option<(u16, u16)>,
+    devices: Vec<(usize, Vec<u16>)>,
+}
+
+/// A debugging session: a CPU running a program with breakpoints and optional source code
+pub struct Session {
+    pub cpu: Cpu,
+    pub program: Program,
+    source: Vec<String>,
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+    run_limit: u64,
+    history: VecDeque<Delta>,
+    history_limit: usize,
+}
+
+impl Session {
+    pub fn new(cpu: Cpu, program: Program, source: Option<&str>) -> Session {
+        Session {
+            cpu,
+            program,
+            source: source.map(|source| source.lines().map(str::to_string).collect()).unwrap_or_default(),
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            run_limit: DEFAULT_RUN_LIMIT,
+            history: VecDeque::new(),
+            history_limit: DEFAULT_HISTORY_LIMIT,
+        }
+    }
+
+    pub fn set_run_limit(&mut self, run_limit: u64) {
+        self.run_limit = run_limit;
+    }
+
+    /// Sets the number of instructions that are recorded for stepping backwards, 0 disables recording
+    pub fn set_history_limit(&mut self, history_limit: usize) {
+        self.history_limit = history_limit;
+        while self.history.len() > history_limit {
+            self.history.pop_front();
+        }
+    }
+
+    /// Number of instructions that can be stepped back
+    pub fn history_len(&self) -> usize {
+        self.history.len()
+    }
+
+    /// Source line (starting at 1) with its text
+    pub fn source_line(&self, line: usize) -> Option<&str> {
+        self.source.get(line.checked_sub(1)?).map(String::as_str)
+    }
+
+    pub fn source_line_count(&self) -> usize {
+        self.source.len()
+    }
+
+    /// Resolves a location given as label, source line number or `*ADDRESS`
+    pub fn resolve_location(&self, location: &str) -> Option<u16> {
+        if let Some(address) = location.strip_prefix('*') {
+            parse_number(address).filter(|&value| value <= 0xffff).map(|value| value as u16)
+        } else if location.chars().all(|c| c.is_ascii_digit()) {
+            self.program.address_of_line(location.parse().ok()?)
+        } else {
+            self.program.labels.get(location).copied()
+        }
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.insert(address)
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        if self.watchpoints.contains(&watchpoint) {
+            return false;
+        }
+        self.watchpoints.push(watchpoint);
+        true
+    }
+
+    pub fn remove_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|&other| other != watchpoint);
+        self.watchpoints.len() != len
+    }
+
+    /// Writes to memory like `Bus::write` does, except that the contents of the ROM can be changed as well
+    pub fn poke(&mut self, address: u16, value: u16) {
+        let base = self.cpu.bus.mapping_at(address).map(|mapping| mapping.base);
+        match (base, self.cpu.bus.device_at_mut::<Rom>(address)) {
+            (Some(base), Some(rom)) => rom.words_mut()[(address - base) as usize] = value,
+            _ => self.cpu.bus.write(address, value),
+        }
+    }
+
+    // Only `ld` and `st` access the data memory, always at the address in the Addr register. The instruction is decoded
+    // like the CPU does, so that undocumented encodings that load or store count as well.
+    fn data_access(&self) -> Option<(u16, bool)> {
+        let word = self.cpu.bus.peek(self.cpu.registers.pc);
+        let address = self.cpu.registers.addr;
+        let instruction = match self.cpu.decode_mode() {
+            DecodeMode::Strict => Instruction::decode(word)?,
+            DecodeMode::Hardware => match Operation::decode(word) {
+                Operation::Instruction(instruction) => instruction,
+                _ => return None,
+            },
+            DecodeMode::Signals => {
+                let signals = ControlSignals::decode(word);
+                return match (signals.mem_read, signals.mem_write) {
+                    (_, true) => Some((address, true)),
+                    (true, false) => Some((address, false)),
+                    (false, false) => None,
+                };
+            },
+        };
+        match instruction {
+            Instruction::Ld => Some((address, false)),
+            Instruction::St => Some((address, true)),
+            _ => None,
+        }
+    }
+
+    // Whether the current instruction may load the PC, decoded like `data_access`
+    fn may_jump(&self) -> bool {
+        let word = self.cpu.bus.peek(self.cpu.registers.pc);
+        match self.cpu.decode_mode() {
+            DecodeMode::Strict => matches!(Instruction::decode(word), Some(Instruction::Jmp(..)) | Some(Instruction::Mov(Register::PC, _))),
+            DecodeMode::Hardware => matches!(Operation::decode(word),
+                Operation::Instruction(Instruction::Jmp(..)) | Operation::Instruction(Instruction::Mov(Register::PC, _))
+                | Operation::Jmp(..) | Operation::Mov(Some(Register::PC), _)),
+            DecodeMode::Signals => ControlSignals::decode(word).pc_in,
+        }
+    }
+
+    // Whether the program can jump to `address` again. Without call instructions subroutines return by loading the
+    // address of a label after the call with `ldi`, anything else is a loop or a jump that doesn't come back.
+    fn is_return_address(&self, address: u16) -> bool {
+        self.program.words.iter()
+            .enumerate()
+            .filter(|&(word_address, _)| !self.program.is_data(word_address as u16))
+            .any(|(_, &word)| Instruction::decode(word) == Some(Instruction::Ldi(address)))
+    }
+
+    fn watchpoint_hit(&self) -> Option<Watchpoint> {
+        self.data_access().and_then(|(address, write)| self.watchpoints.iter()
+            .find(|watchpoint| watchpoint.address == address && watchpoint.kind.matches(write))
+            .copied())
+    }
+
+    fn record(&mut self) {
+        if self.history_limit == 0 {
+            return;
+        }
+
+        let bus = &self.cpu.bus;
+        let memory = match self.data_access() {
+            Some((address, true)) if bus.mapping_at(address).is_some_and(|mapping| mapping.is::<Ram>()) => {
+                Some((address, bus.peek(address)))
+            },
+            _ => None,
+        };
+        let devices = bus.mappings().iter()
+            .enumerate()
+            .filter(|(_, mapping)| !mapping.is::<Rom>() && !mapping.is::<Ram>())
+            .map(|(index, mapping)| (index, mapping.device().save_state()))
+            .filter(|(_, state)| !state.is_empty())
+            .collect();
+
+        if self.history.len() == self.history_limit {
+            self.history.pop_front();
+        }
+        self.history.push_back(Delta {
+            cpu: self.cpu.state(),
+            memory,
+            devices,
+        });
+    }
+
+    /// Executes a single instruction
+    pub fn step(&mut self) -> StopReason {
+        let watchpoint = self.watchpoint_hit();
+        self.record();
+
+        match self.cpu.step_instruction() {
+            Ok(()) => match watchpoint {
+                Some(watchpoint) => StopReason::Watchpoint(watchpoint),
+                None => StopReason::Step,
+            },
+            Err(err) => StopReason::Error(err),
+        }
+    }
+
+    /// Executes the current instruction and, if it jumped away, keeps running until execution gets back
+    /// to the instruction after it. This steps over subroutine calls. Jumps that can't come back, like loops, stop at
+    /// their target like `step`.
+    pub fn step_over(&mut self) -> StopReason {
+        let return_address = self.cpu.registers.pc.wrapping_add(1);
+        let jumps = self.may_jump();
+
+        match self.step() {
+            StopReason::Step => {},
+            reason => return reason,
+        }
+
+        if !jumps || self.cpu.registers.pc == return_address || !self.is_return_address(return_address) {
+            return StopReason::Step;
+        }
+
+        self.run_until(|pc| pc == return_address)
+            .unwrap_or(StopReason::Step)
+    }
+
+    /// Runs until a breakpoint is hit or the program halts
+    pub fn continue_execution(&mut self) -> StopReason {
+        // Don't stop at the breakpoint we're currently sitting on
+        match self.step() {
+            StopReason::Step => {},
+            reason => return reason,
+        }
+
+        self.run_until(|_| false)
+            .unwrap_or(StopReason::Step)
+    }
+
+    /// Runs until a breakpoint is hit or the program halts, stops immediately if there is a breakpoint at the PC
+    pub fn run(&mut self) -> StopReason {
+        self.run_until(|_| false)
+            .unwrap_or(StopReason::Step)
+    }
+
+    /// Undoes the last instruction
+    pub fn step_back(&mut self) -> StopReason {
+        let delta = match self.history.pop_back() {
+            Some(delta) => delta,
+            None => return StopReason::HistoryStart,
+        };
+
+        self.cpu.restore_state(&delta.cpu);
+        if let Some((address, value)) = delta.memory {
+            self.cpu.bus.write(address, value);
+        }
+        for (index, state) in &delta.devices {
+            self.cpu.bus.restore_state(*index, state);
+        }
+
+        match self.watchpoint_hit() {
+            Some(watchpoint) => StopReason::Watchpoint(watchpoint),
+            None => StopReason::Step,
+        }
+    }
+
+    /// Runs backwards until a breakpoint or watchpoint is hit or the history is exhausted
+    pub fn reverse_continue(&mut self) -> StopReason {
+        loop {
+            match self.step_back() {
+                StopReason::Step => {},
+                reason => return reason,
+            }
+            let pc = self.cpu.registers.pc;
+            if self.breakpoints.contains(&pc) {
+                return StopReason::Breakpoint(pc);
+            }
+        }
+    }
+
+    /// Runs backwards until just before the last instruction that wrote to `address`
+    pub fn run_back_to_write(&mut self, address: u16) -> StopReason {
+        loop {
+            if let StopReason::HistoryStart = self.step_back() {
+                return StopReason::HistoryStart;
+            }
+            if self.data_access() == Some((address, true)) {
+                return StopReason::Watchpoint(Watchpoint {
+                    kind: WatchKind::Write,
+                    address,
+                });
+            }
+        }
+    }
+
+    /// Restores a snapshot, this clears the history
+    pub fn restore_snapshot(&mut self, snapshot: &Snapshot) -> Result<(), SnapshotError> {
+        snapshot.restore(&mut self.cpu)?;
+        self.history.clear();
+        Ok(())
+    }
+
+    // Returns `None` if `target` was reached
+    fn run_until<F: Fn(u16) -> bool>(&mut self, target: F) -> Option<StopReason> {
+        for _ in 0..self.run_limit {
+            let pc = self.cpu.registers.pc;
+            if target(pc) {
+                return None;
+            }
+            if self.breakpoints.contains(&pc) {
+                return Some(StopReason::Breakpoint(pc));
+            }
+            if self.cpu.is_halted() {
+                return Some(StopReason::Halted);
+            }
+            match self.step() {
+                StopReason::Step => {},
+                reason => return Some(reason),
+            }
+        }
+
+        Some(StopReason::Limit)
+    }
+}
+
+pub fn parse_number(text: &str) -> Option<u64> {
+    if let Some(digits) = text.strip_prefix("0x") {
+        u64::from_str_radix(digits, 16).ok()
+    } else {
+        text.parse().ok()
+    }
+}
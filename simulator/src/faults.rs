@@ -0,0 +1,206 @@
+
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::ops::Range;
+
+use assembler::{OpCode, Program};
+
+use crate::bus::{AccessKind, BusAccess};
+use crate::cpu::{Cpu, Phase, Registers};
+use crate::isa::{Instruction, Operation};
+use crate::peripherals::{Ram, Rom};
+use crate::trace::TraceEntry;
+
+/// Number of executed instructions that are kept for the trace tail of a report
+pub const TRACE_TAIL_LENGTH: usize = 8;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FaultKind {
+    /// Store to an address of the ROM, the hardware ignores it
+    RomWrite(u16),
+    /// Access to an address that no device is mapped at
+    Unmapped(AccessKind, u16),
+    /// Read of an SRAM address that hasn't been written before
+    UninitializedRead(u16),
+    /// The PC was set to a word emitted by `.word`
+    JumpIntoData(u16),
+    /// An instruction word that can't be decoded at all
+    Undecodable(u16),
+    /// An opcode the assembler never emits, which the decoder treats by its low 4 bits
+    AliasedOpcode(u16),
+    /// SP left the stack region
+    StackOutOfRange(u16),
+}
+
+impl fmt::Display for FaultKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            FaultKind::RomWrite(address) => write!(f, "store to ROM address 0x{:04x}", address),
+            FaultKind::Unmapped(AccessKind::Read, address) => write!(f, "read of unmapped address 0x{:04x}", address),
+            FaultKind::Unmapped(AccessKind::Write, address) => write!(f, "write to unmapped address 0x{:04x}", address),
+            FaultKind::UninitializedRead(address) => write!(f, "read of uninitialized SRAM at 0x{:04x}", address),
+            FaultKind::JumpIntoData(address) => write!(f, "jump into .word data at 0x{:04x}", address),
+            FaultKind::Undecodable(word) => write!(f, "undecodable instruction 0x{:04x} is decoded as {}", word, Operation::decode(*word)),
+            FaultKind::AliasedOpcode(word) => write!(f, "opcode 0x{:02x} of 0x{:04x} is decoded as {}", word >> 11, word, Operation::decode(*word)),
+            FaultKind::StackOutOfRange(sp) => write!(f, "SP left the stack region (SP=0x{:04x})", sp),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fault {
+    pub kind: FaultKind,
+    pub cycle: u64,
+    /// Address of the instruction that caused the fault
+    pub pc: u16,
+    /// Source line of that instruction, if known
+    pub line: Option<usize>,
+    /// The last executed instructions, oldest first
+    pub trace: Vec<TraceEntry>,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "cycle {}, PC 0x{:04x}", self.cycle, self.pc)?;
+        if let Some(line) = self.line {
+            write!(f, " (line {})", line)?;
+        }
+        write!(f, ": {}", self.kind)
+    }
+}
+
+/// Watches a simulation for suspicious behaviour, call `sample` after every clock cycle.
+///
+/// Every fault is only reported once per instruction address.
+pub struct FaultDetector {
+    program: Program,
+    stack: Option<Range<u16>>,
+    // Only leaving the stack region is a fault, SP starts outside of it until the program initializes it
+    in_stack: bool,
+    // Base address and written flag of every word of every mapped RAM
+    ram: Vec<(u16, Vec<bool>)>,
+    tail: VecDeque<TraceEntry>,
+    start: Option<(u64, Registers)>,
+    reported: HashSet<(u16, FaultKind)>,
+    faults: Vec<Fault>,
+}
+
+impl FaultDetector {
+    pub fn new(cpu: &Cpu, program: &Program) -> FaultDetector {
+        FaultDetector {
+            program: program.clone(),
+            stack: None,
+            in_stack: false,
+            ram: cpu.bus.mappings().iter()
+                .filter(|mapping| mapping.is::<Ram>())
+                .map(|mapping| (mapping.base, vec![false; mapping.size]))
+                .collect(),
+            tail: VecDeque::new(),
+            start: None,
+            reported: HashSet::new(),
+            faults: Vec::new(),
+        }
+    }
+
+    /// Reports SP leaving `stack` once it has been inside
+    pub fn set_stack_region(&mut self, stack: Range<u16>) {
+        self.stack = Some(stack);
+    }
+
+    pub fn faults(&self) -> &[Fault] {
+        &self.faults
+    }
+
+    pub fn sample(&mut self, cpu: &Cpu) {
+        match cpu.phase() {
+            // An instruction was just fetched
+            Phase::Execute => {
+                let pc = cpu.registers.pc;
+                self.start = Some((cpu.cycles() - 1, cpu.registers));
+                if let Some(access) = cpu.bus_access() {
+                    self.check_access(cpu, access, pc, cpu.cycles() - 1);
+                }
+                self.check_opcode(cpu.ir(), pc, cpu.cycles() - 1);
+            },
+            // An instruction completed
+            Phase::Fetch => if let Some((cycle, before)) = self.start.take() {
+                let entry = TraceEntry::new(cycle, cpu.ir(), &before, &cpu.registers, cpu.bus_access());
+                if self.tail.len() == TRACE_TAIL_LENGTH {
+                    self.tail.pop_front();
+                }
+                self.tail.push_back(entry);
+
+                if let Some(access) = cpu.bus_access() {
+                    self.check_access(cpu, access, before.pc, cycle);
+                }
+                // Running on through further data words is the same fault
+                if self.program.is_data(cpu.registers.pc) && !self.program.is_data(before.pc) {
+                    self.report(FaultKind::JumpIntoData(cpu.registers.pc), before.pc, cycle);
+                }
+                self.check_stack(cpu.registers.sp, before.pc, cycle);
+            },
+        }
+    }
+
+    fn check_access(&mut self, cpu: &Cpu, access: BusAccess, pc: u16, cycle: u64) {
+        let address = access.address;
+
+        let mapping = match cpu.bus.mapping_at(address) {
+            Some(mapping) => mapping,
+            None => {
+                self.report(FaultKind::Unmapped(access.kind, address), pc, cycle);
+                return;
+            },
+        };
+        if mapping.is::<Rom>() && access.kind == AccessKind::Write {
+            self.report(FaultKind::RomWrite(address), pc, cycle);
+        }
+
+        let ram = self.ram.iter_mut()
+            .find(|(base, written)| address >= *base && ((address - base) as usize) < written.len());
+        if let Some((base, written)) = ram {
+            let offset = (address - *base) as usize;
+            match access.kind {
+                AccessKind::Write => written[offset] = true,
+                AccessKind::Read => if !written[offset] {
+                    self.report(FaultKind::UninitializedRead(address), pc, cycle);
+                },
+            }
+        }
+    }
+
+    fn check_opcode(&mut self, word: u16, pc: u16, cycle: u64) {
+        let opcode = word >> 11;
+        if OpCode::from_code(opcode).is_none() && opcode & 0x10 != 0 {
+            self.report(FaultKind::AliasedOpcode(word), pc, cycle);
+        } else if Instruction::decode(word).is_none() {
+            self.report(FaultKind::Undecodable(word), pc, cycle);
+        }
+    }
+
+    fn check_stack(&mut self, sp: u16, pc: u16, cycle: u64) {
+        let Some(stack) = &self.stack else {
+            return;
+        };
+
+        let in_stack = stack.contains(&sp);
+        if self.in_stack && !in_stack {
+            self.report(FaultKind::StackOutOfRange(sp), pc, cycle);
+        }
+        self.in_stack = in_stack;
+    }
+
+    fn report(&mut self, kind: FaultKind, pc: u16, cycle: u64) {
+        if !self.reported.insert((pc, kind)) {
+            return;
+        }
+
+        self.faults.push(Fault {
+            kind,
+            cycle,
+            pc,
+            line: self.program.line_of(pc),
+            trace: self.tail.iter().cloned().collect(),
+        });
+    }
+}
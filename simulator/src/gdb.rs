@@ -0,0 +1,437 @@
+
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+
+use assembler::Register;
+
+use crate::debugger::{Session, StopReason, Watchpoint, WatchKind};
+
+// GDB expects a byte-addressed target while the CPU addresses 16-bit words. The stub presents every word as two bytes
+// in little-endian order at byte address `2 * address`. The PC holds a code address and is therefore reported as byte
+// address as well, which takes 17 bits and is therefore sent as a 32-bit register. All other registers are reported
+// unchanged.
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="de.maxkl.hdl-cpu.core">
+    <flags id="sr_flags" size="2">
+      <field name="C" start="0" end="0"/>
+      <field name="Z" start="1" end="1"/>
+      <field name="EQ" start="2" end="2"/>
+      <field name="LT" start="3" end="3"/>
+    </flags>
+    <reg name="a" bitsize="16" type="uint16" regnum="0"/>
+    <reg name="b" bitsize="16" type="uint16" regnum="1"/>
+    <reg name="addr" bitsize="16" type="uint16" regnum="2"/>
+    <reg name="sp" bitsize="16" type="uint16" regnum="3"/>
+    <reg name="sr" bitsize="16" type="sr_flags" regnum="4"/>
+    <reg name="pc" bitsize="32" type="code_ptr" regnum="5"/>
+  </feature>
+</target>
+"#;
+
+const REGISTER_COUNT: u16 = 6;
+
+// Size of the byte address space, 64K words of two bytes
+const BYTE_ADDRESSES: u32 = 0x20000;
+
+// Signal numbers used in stop replies
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+enum Incoming {
+    Packet(Vec<u8>),
+    Interrupt,
+}
+
+struct Connection<'a, S> {
+    session: &'a mut Session,
+    stream: S,
+    ack: bool,
+    swbreak: bool,
+    last_reply: Vec<u8>,
+}
+
+/// Serves a single GDB connection until the debugger detaches, kills the target or closes the connection.
+///
+/// `continue` can't be interrupted, it stops after the run limit of the session and reports SIGINT.
+pub fn serve<S: Read + Write>(session: &mut Session, stream: S) -> io::Result<()> {
+    let mut connection = Connection {
+        session,
+        stream,
+        ack: true,
+        swbreak: false,
+        last_reply: Vec::new(),
+    };
+
+    while let Some(incoming) = connection.receive()? {
+        let reply = match incoming {
+            Incoming::Packet(packet) => match connection.handle(&packet) {
+                Some(reply) => reply,
+                None => {
+                    connection.send(b"OK")?;
+                    return Ok(());
+                },
+            },
+            Incoming::Interrupt => format!("S{:02x}", SIGINT),
+        };
+        connection.send(reply.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+fn parse_hex(text: &[u8]) -> Option<u32> {
+    u32::from_str_radix(std::str::from_utf8(text).ok()?, 16).ok()
+}
+
+fn decode_hex_bytes(text: &[u8]) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    text.chunks(2)
+        .map(|pair| parse_hex(pair).map(|value| value as u8))
+        .collect()
+}
+
+// Size of a register in bytes as GDB sees it
+fn register_size(code: u16) -> usize {
+    if Register::from_code(code) == Some(Register::PC) { 4 } else { 2 }
+}
+
+// Registers are sent in little-endian byte order
+fn encode_register(value: u32, size: usize) -> String {
+    (0..size).map(|byte| format!("{:02x}", value >> (8 * byte) & 0xff)).collect()
+}
+
+fn decode_register(text: &[u8], size: usize) -> Option<u32> {
+    let bytes = decode_hex_bytes(text)?;
+    if bytes.len() != size {
+        return None;
+    }
+    Some(bytes.iter().rev().fold(0, |value, &byte| value << 8 | byte as u32))
+}
+
+// Splits "ADDR,LENGTH" into byte address and length
+fn parse_range(text: &[u8]) -> Option<(u32, u32)> {
+    let comma = text.iter().position(|&c| c == b',')?;
+    Some((parse_hex(&text[..comma])?, parse_hex(&text[comma + 1..])?))
+}
+
+// End of a range of byte addresses, `None` unless the range lies within the address space
+fn range_end(address: u32, length: u32) -> Option<u32> {
+    address.checked_add(length).filter(|&end| end <= BYTE_ADDRESSES)
+}
+
+// Word addresses covered by a range of byte addresses that `range_end` accepted
+fn words_in_range(address: u32, length: u32) -> impl Iterator<Item = u16> {
+    let first = address / 2;
+    let last = (address + length.max(1) - 1) / 2;
+    (first..=last.min(0xffff)).map(|word| word as u16)
+}
+
+impl<'a, S: Read + Write> Connection<'a, S> {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        match self.stream.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    fn receive(&mut self) -> io::Result<Option<Incoming>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(0x03) => return Ok(Some(Incoming::Interrupt)),
+                Some(b'-') => {
+                    let reply = self.last_reply.clone();
+                    self.stream.write_all(&reply)?;
+                    self.stream.flush()?;
+                },
+                Some(b'$') => {
+                    let mut packet = Vec::new();
+                    loop {
+                        match self.read_byte()? {
+                            None => return Ok(None),
+                            Some(b'#') => break,
+                            Some(byte) => packet.push(byte),
+                        }
+                    }
+
+                    let mut checksum = [0; 2];
+                    self.stream.read_exact(&mut checksum)?;
+
+                    if self.ack {
+                        let expected = packet.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+                        if parse_hex(&checksum) != Some(expected as u32) {
+                            self.stream.write_all(b"-")?;
+                            self.stream.flush()?;
+                            continue;
+                        }
+                        self.stream.write_all(b"+")?;
+                    }
+
+                    return Ok(Some(Incoming::Packet(packet)));
+                },
+                // Acknowledgements and noise between packets
+                Some(_) => {},
+            }
+        }
+    }
+
+    fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        let mut packet = vec![b'$'];
+        for &byte in data {
+            if let b'$' | b'#' | b'}' | b'*' = byte {
+                packet.push(b'}');
+                packet.push(byte ^ 0x20);
+            } else {
+                packet.push(byte);
+            }
+        }
+        let checksum = packet[1..].iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        packet.extend_from_slice(format!("#{:02x}", checksum).as_bytes());
+
+        self.stream.write_all(&packet)?;
+        self.stream.flush()?;
+        self.last_reply = packet;
+        Ok(())
+    }
+
+    // Returns `None` when the session ends
+    fn handle(&mut self, packet: &[u8]) -> Option<String> {
+        let (&command, args) = match packet.split_first() {
+            Some(split) => split,
+            None => return Some(String::new()),
+        };
+
+        let reply = match command {
+            b'?' => format!("S{:02x}", SIGTRAP),
+            b'g' => (0..REGISTER_COUNT)
+                .map(|code| encode_register(self.read_register(code), register_size(code)))
+                .collect(),
+            b'G' => {
+                if args.len() != (0..REGISTER_COUNT).map(|code| 2 * register_size(code)).sum::<usize>() {
+                    return Some("E01".to_string());
+                }
+                let mut start = 0;
+                for code in 0..REGISTER_COUNT {
+                    let end = start + 2 * register_size(code);
+                    let written = decode_register(&args[start..end], register_size(code))
+                        .is_some_and(|value| self.write_register(code, value));
+                    if !written {
+                        return Some("E01".to_string());
+                    }
+                    start = end;
+                }
+                "OK".to_string()
+            },
+            b'p' => match parse_hex(args) {
+                Some(code) if code < REGISTER_COUNT as u32 => encode_register(self.read_register(code as u16), register_size(code as u16)),
+                _ => "E01".to_string(),
+            },
+            b'P' => {
+                let parsed = args.iter().position(|&c| c == b'=')
+                    .and_then(|equals| {
+                        let code = parse_hex(&args[..equals]).filter(|&code| code < REGISTER_COUNT as u32)? as u16;
+                        Some((code, decode_register(&args[equals + 1..], register_size(code))?))
+                    });
+                match parsed {
+                    Some((code, value)) if self.write_register(code, value) => "OK".to_string(),
+                    _ => "E01".to_string(),
+                }
+            },
+            b'm' => match parse_range(args) {
+                Some((address, length)) => match range_end(address, length) {
+                    Some(end) => (address..end)
+                        .map(|byte_address| format!("{:02x}", self.read_byte_at(byte_address)))
+                        .collect(),
+                    None => "E01".to_string(),
+                },
+                _ => "E01".to_string(),
+            },
+            b'M' => {
+                let parsed = args.iter().position(|&c| c == b':')
+                    .and_then(|colon| Some((parse_range(&args[..colon])?, decode_hex_bytes(&args[colon + 1..])?)));
+                match parsed {
+                    Some(((address, length), data)) if data.len() == length as usize && range_end(address, length).is_some() => {
+                        for (byte_address, byte) in (address..).zip(data) {
+                            self.write_byte_at(byte_address, byte);
+                        }
+                        "OK".to_string()
+                    },
+                    _ => "E01".to_string(),
+                }
+            },
+            b'Z' | b'z' => self.handle_point(command == b'Z', args),
+            b's' | b'c' => {
+                if !args.is_empty() {
+                    match parse_hex(args) {
+                        Some(address) if address < BYTE_ADDRESSES => self.session.cpu.registers.pc = (address / 2) as u16,
+                        _ => return Some("E01".to_string()),
+                    }
+                }
+                let reason = if command == b's' {
+                    self.session.step()
+                } else {
+                    self.session.continue_execution()
+                };
+                self.stop_reply(reason)
+            },
+            b'b' => match args {
+                b"s" => {
+                    let reason = self.session.step_back();
+                    self.stop_reply(reason)
+                },
+                b"c" => {
+                    let reason = self.session.reverse_continue();
+                    self.stop_reply(reason)
+                },
+                _ => String::new(),
+            },
+            b'H' => "OK".to_string(),
+            b'T' => "OK".to_string(),
+            b'D' | b'k' => return None,
+            b'q' | b'Q' | b'v' => return self.handle_query(packet),
+            _ => String::new(),
+        };
+
+        Some(reply)
+    }
+
+    fn handle_query(&mut self, packet: &[u8]) -> Option<String> {
+        let packet = String::from_utf8_lossy(packet);
+
+        let reply = if let Some(features) = packet.strip_prefix("qSupported") {
+            self.swbreak = features.contains("swbreak+");
+            "PacketSize=1000;qXfer:features:read+;swbreak+;hwbreak+;QStartNoAckMode+;ReverseStep+;ReverseContinue+".to_string()
+        } else if packet == "QStartNoAckMode" {
+            // The OK is still acknowledged by GDB
+            self.ack = false;
+            "OK".to_string()
+        } else if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            match parse_range(range.as_bytes()) {
+                Some((offset, length)) => {
+                    let start = (offset as usize).min(TARGET_XML.len());
+                    let end = (start + length as usize).min(TARGET_XML.len());
+                    let marker = if end == TARGET_XML.len() { 'l' } else { 'm' };
+                    format!("{}{}", marker, &TARGET_XML[start..end])
+                },
+                None => "E01".to_string(),
+            }
+        } else if packet == "qAttached" {
+            "1".to_string()
+        } else if packet == "qC" {
+            "QC1".to_string()
+        } else if packet == "qfThreadInfo" {
+            "m1".to_string()
+        } else if packet == "qsThreadInfo" {
+            "l".to_string()
+        } else if packet == "qSymbol::" {
+            "OK".to_string()
+        } else if packet == "vKill" || packet.starts_with("vKill;") {
+            return None;
+        } else {
+            String::new()
+        };
+
+        Some(reply)
+    }
+
+    fn handle_point(&mut self, insert: bool, args: &[u8]) -> String {
+        let mut fields = args.split(|&c| c == b',');
+        let kind = fields.next().and_then(parse_hex);
+        let address = fields.next().and_then(parse_hex);
+        let length = fields.next().and_then(parse_hex);
+
+        let (kind, address, length) = match (kind, address, length) {
+            (Some(kind), Some(address), Some(length)) if range_end(address, length).is_some() => (kind, address, length),
+            _ => return "E01".to_string(),
+        };
+
+        let watch_kind = match kind {
+            // Software and hardware breakpoints are the same thing in the simulator
+            0 | 1 => {
+                let address = (address / 2) as u16;
+                if insert {
+                    self.session.add_breakpoint(address);
+                } else {
+                    self.session.remove_breakpoint(address);
+                }
+                return "OK".to_string();
+            },
+            2 => WatchKind::Write,
+            3 => WatchKind::Read,
+            4 => WatchKind::Access,
+            _ => return String::new(),
+        };
+
+        for address in words_in_range(address, length) {
+            let watchpoint = Watchpoint { kind: watch_kind, address };
+            if insert {
+                self.session.add_watchpoint(watchpoint);
+            } else {
+                self.session.remove_watchpoint(watchpoint);
+            }
+        }
+
+        "OK".to_string()
+    }
+
+    fn stop_reply(&self, reason: StopReason) -> String {
+        match reason {
+            StopReason::Step | StopReason::Halted => format!("S{:02x}", SIGTRAP),
+            StopReason::Breakpoint(_) if self.swbreak => format!("T{:02x}swbreak:;", SIGTRAP),
+            StopReason::Breakpoint(_) => format!("S{:02x}", SIGTRAP),
+            StopReason::Watchpoint(watchpoint) => {
+                let name = match watchpoint.kind {
+                    WatchKind::Write => "watch",
+                    WatchKind::Read => "rwatch",
+                    WatchKind::Access => "awatch",
+                };
+                format!("T{:02x}{}:{:x};", SIGTRAP, name, watchpoint.address as u32 * 2)
+            },
+            StopReason::Limit => format!("S{:02x}", SIGINT),
+            StopReason::HistoryStart => format!("T{:02x}replaylog:begin;", SIGTRAP),
+            StopReason::Error(_) => format!("S{:02x}", SIGILL),
+        }
+    }
+
+    fn read_register(&self, code: u16) -> u32 {
+        let register = Register::from_code(code).unwrap();
+        let value = self.session.cpu.registers.get(register) as u32;
+        if register == Register::PC {
+            value * 2
+        } else {
+            value
+        }
+    }
+
+    // Returns `false` if the value doesn't fit the register
+    fn write_register(&mut self, code: u16, value: u32) -> bool {
+        let register = Register::from_code(code).unwrap();
+        let value = if register == Register::PC { value / 2 } else { value };
+        match u16::try_from(value) {
+            Ok(value) => {
+                self.session.cpu.registers.set(register, value);
+                true
+            },
+            Err(_) => false,
+        }
+    }
+
+    fn read_byte_at(&self, byte_address: u32) -> u8 {
+        let word = self.session.cpu.bus.peek((byte_address / 2) as u16);
+        (word >> (8 * (byte_address % 2))) as u8
+    }
+
+    fn write_byte_at(&mut self, byte_address: u32, byte: u8) {
+        let address = (byte_address / 2) as u16;
+        let shift = 8 * (byte_address % 2);
+        let word = self.session.cpu.bus.peek(address) & !(0xff << shift) | (byte as u16) << shift;
+        self.session.poke(address, word);
+    }
+}
@@ -0,0 +1,92 @@
+
+use std::path::{Path, PathBuf};
+use std::{error, fmt, fs, io};
+use std::collections::{HashMap, HashSet};
+
+use assembler::{AssemblerError, Program};
+
+#[derive(Debug)]
+pub enum ImageError {
+    FileRead(PathBuf, io::Error),
+    Assembler(AssemblerError),
+    InvalidHexWord(PathBuf, usize, String),
+    OddLength(PathBuf),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            ImageError::FileRead(path, _) => write!(f, "failed to read {}", path.display()),
+            ImageError::Assembler(err) => write!(f, "{}", err),
+            ImageError::InvalidHexWord(path, line_number, word) => write!(f, "invalid word \"{}\" at line {} of {}", word, line_number, path.display()),
+            ImageError::OddLength(path) => write!(f, "{} contains an odd number of bytes", path.display()),
+        }
+    }
+}
+
+impl error::Error for ImageError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ImageError::FileRead(_, io_error) => Some(io_error),
+            ImageError::Assembler(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Loads a program image, the format is determined by the file extension:
+///
+/// - `.asm`: assembly source, assembled on the fly
+/// - `.hex`: whitespace separated hexadecimal words, `#` starts a comment
+/// - anything else: raw little endian words as written by the assembler
+///
+/// Only assembly sources provide labels.
+pub fn load_image(path: &Path) -> Result<Program, ImageError> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("asm") => assembler::assemble_file(path.to_path_buf())
+            .map_err(ImageError::Assembler),
+        Some("hex") => {
+            let text = fs::read_to_string(path)
+                .map_err(|err| ImageError::FileRead(path.to_path_buf(), err))?;
+            let words = parse_hex(&text)
+                .map_err(|(line_number, word)| ImageError::InvalidHexWord(path.to_path_buf(), line_number, word))?;
+            Ok(program_from_words(words))
+        },
+        _ => {
+            let bytes = fs::read(path)
+                .map_err(|err| ImageError::FileRead(path.to_path_buf(), err))?;
+            if bytes.len() % 2 != 0 {
+                return Err(ImageError::OddLength(path.to_path_buf()));
+            }
+            let words = bytes.chunks(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .collect();
+            Ok(program_from_words(words))
+        },
+    }
+}
+
+fn parse_hex(text: &str) -> Result<Vec<u16>, (usize, String)> {
+    let mut words = Vec::new();
+
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap();
+        for word in line.split_whitespace() {
+            let digits = word.strip_prefix("0x").unwrap_or(word);
+            let value = u16::from_str_radix(digits, 16)
+                .map_err(|_| (line_number + 1, word.to_string()))?;
+            words.push(value);
+        }
+    }
+
+    Ok(words)
+}
+
+fn program_from_words(words: Vec<u16>) -> Program {
+    Program {
+        words,
+        labels: HashMap::new(),
+        lines: Vec::new(),
+        data: HashSet::new(),
+    }
+}
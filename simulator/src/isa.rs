@@ -0,0 +1,194 @@
+
+use std::fmt;
+
+use assembler::{OpCode, Register, Condition};
+
+pub fn register_name(register: Register) -> &'static str {
+    match register {
+        Register::A => "A",
+        Register::B => "B",
+        Register::Addr => "Addr",
+        Register::SP => "SP",
+        Register::SR => "SR",
+        Register::PC => "PC",
+    }
+}
+
+fn condition_suffix(condition: Condition) -> &'static str {
+    match condition {
+        Condition::None => "",
+        Condition::Zero => ".z",
+        Condition::Equal => ".eq",
+        Condition::NotEqual => ".ne",
+        Condition::LessThan => ".lt",
+        Condition::LessThanOrEqual => ".le",
+        Condition::GreaterThan => ".gt",
+        Condition::GreaterThanOrEqual => ".ge",
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    Mov(Register, Register),
+    Ld,
+    Ldi(u16),
+    St,
+    And,
+    Andi(u16),
+    Or,
+    Ori(u16),
+    Xor,
+    Xori(u16),
+    Not,
+    Add,
+    Addi(u16),
+    Sub,
+    Sl,
+    Sli(u16),
+    Sr,
+    Sri(u16),
+    Cmp,
+    Cmpi(u16),
+    Jmp(Condition, Register),
+}
+
+impl Instruction {
+    /// Decodes an instruction word the way the assembler encodes it.
+    ///
+    /// Returns `None` for opcodes the assembler never emits and for invalid register fields.
+    pub fn decode(word: u16) -> Option<Instruction> {
+        let opcode = OpCode::from_code(word >> 11)?;
+        let immediate = word & 0x7ff;
+        let low = word & 0x7;
+        let high = (word >> 3) & 0x7;
+
+        let instruction = match opcode {
+            OpCode::MOV => Instruction::Mov(Register::from_code(low)?, Register::from_code(high)?),
+            OpCode::LD => Instruction::Ld,
+            OpCode::LDI => Instruction::Ldi(immediate),
+            OpCode::ST => Instruction::St,
+            OpCode::AND => Instruction::And,
+            OpCode::ANDI => Instruction::Andi(immediate),
+            OpCode::OR => Instruction::Or,
+            OpCode::ORI => Instruction::Ori(immediate),
+            OpCode::XOR => Instruction::Xor,
+            OpCode::XORI => Instruction::Xori(immediate),
+            OpCode::NOT => Instruction::Not,
+            OpCode::ADD => Instruction::Add,
+            OpCode::ADDI => Instruction::Addi(immediate),
+            OpCode::SUB => Instruction::Sub,
+            OpCode::SL => Instruction::Sl,
+            OpCode::SLI => Instruction::Sli(immediate),
+            OpCode::SR => Instruction::Sr,
+            OpCode::SRI => Instruction::Sri(immediate),
+            OpCode::CMP => Instruction::Cmp,
+            OpCode::CMPI => Instruction::Cmpi(immediate),
+            OpCode::JMP => Instruction::Jmp(Condition::from_code(low)?, Register::from_code(high)?),
+        };
+
+        Some(instruction)
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            Instruction::Mov(target, source) => write!(f, "mov {}, {}", register_name(*target), register_name(*source)),
+            Instruction::Ld => write!(f, "ld"),
+            Instruction::Ldi(value) => write!(f, "ldi 0x{:04x}", value),
+            Instruction::St => write!(f, "st"),
+            Instruction::And => write!(f, "and"),
+            Instruction::Andi(value) => write!(f, "andi 0x{:04x}", value),
+            Instruction::Or => write!(f, "or"),
+            Instruction::Ori(value) => write!(f, "ori 0x{:04x}", value),
+            Instruction::Xor => write!(f, "xor"),
+            Instruction::Xori(value) => write!(f, "xori 0x{:04x}", value),
+            Instruction::Not => write!(f, "not"),
+            Instruction::Add => write!(f, "add"),
+            Instruction::Addi(value) => write!(f, "addi 0x{:04x}", value),
+            Instruction::Sub => write!(f, "sub"),
+            Instruction::Sl => write!(f, "sl"),
+            Instruction::Sli(value) => write!(f, "sli {}", value),
+            Instruction::Sr => write!(f, "sr"),
+            Instruction::Sri(value) => write!(f, "sri {}", value),
+            Instruction::Cmp => write!(f, "cmp"),
+            Instruction::Cmpi(value) => write!(f, "cmpi 0x{:04x}", value),
+            Instruction::Jmp(condition, source) => write!(f, "jmp{} {}", condition_suffix(*condition), register_name(*source)),
+        }
+    }
+}
+
+/// What InstructionDecoder.hdl makes of an instruction word.
+///
+/// The decoder determines the instruction from the low 4 bits of the opcode only, bit 4 just selects the immediate
+/// operand of the instructions that have an immediate variant. Register codes 6 and 7 select no register at all: as a
+/// source nothing drives the data bus, which then reads 0, and as a target nothing is loaded.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Operation {
+    /// Behaves exactly like the documented instruction
+    Instruction(Instruction),
+    /// `mov` with at least one register code that doesn't select a register
+    Mov(Option<Register>, Option<Register>),
+    /// `jmp` from a register code that doesn't select a register, jumps to 0 if the condition is met
+    Jmp(Condition, Option<Register>),
+    /// Opcodes 0x0d to 0x0f and 0x1d to 0x1f enable nothing, only the PC increments
+    Nop,
+}
+
+impl Operation {
+    /// Decodes any instruction word the way the hardware does
+    pub fn decode(word: u16) -> Operation {
+        let opcode = word >> 11;
+        let immediate = if opcode & 0x10 != 0 { Some(word & 0x7ff) } else { None };
+        let low = word & 0x7;
+        let high = (word >> 3) & 0x7;
+        // Always succeeds, the condition field has only 3 bits
+        let condition = Condition::from_code(low).unwrap();
+
+        let instruction = match opcode & 0xf {
+            0x0 => match (Register::from_code(low), Register::from_code(high)) {
+                (Some(target), Some(source)) => Instruction::Mov(target, source),
+                (target, source) => return Operation::Mov(target, source),
+            },
+            0x1 => immediate.map_or(Instruction::Ld, Instruction::Ldi),
+            0x2 => Instruction::St,
+            0x3 => immediate.map_or(Instruction::And, Instruction::Andi),
+            0x4 => immediate.map_or(Instruction::Or, Instruction::Ori),
+            0x5 => immediate.map_or(Instruction::Xor, Instruction::Xori),
+            0x6 => Instruction::Not,
+            0x7 => immediate.map_or(Instruction::Add, Instruction::Addi),
+            0x8 => Instruction::Sub,
+            0x9 => immediate.map_or(Instruction::Sl, Instruction::Sli),
+            0xa => immediate.map_or(Instruction::Sr, Instruction::Sri),
+            0xb => immediate.map_or(Instruction::Cmp, Instruction::Cmpi),
+            0xc => match Register::from_code(high) {
+                Some(source) => Instruction::Jmp(condition, source),
+                None => return Operation::Jmp(condition, None),
+            },
+            _ => return Operation::Nop,
+        };
+
+        Operation::Instruction(instruction)
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        let name = |register: Option<Register>| register.map_or("none", register_name);
+
+        match self {
+            Operation::Instruction(instruction) => write!(f, "{}", instruction),
+            Operation::Mov(target, source) => write!(f, "mov {}, {}", name(*target), name(*source)),
+            Operation::Jmp(condition, source) => write!(f, "jmp{} {}", condition_suffix(*condition), name(*source)),
+            Operation::Nop => write!(f, "nop"),
+        }
+    }
+}
+
+/// Disassembles a single word, words that aren't valid instructions are shown as data
+pub fn disassemble(word: u16) -> String {
+    match Instruction::decode(word) {
+        Some(instruction) => instruction.to_string(),
+        None => format!(".word 0x{:04x}", word),
+    }
+}
@@ -0,0 +1,26 @@
+
+mod alu;
+mod bus;
+mod cpu;
+pub mod cosim;
+pub mod dap;
+mod debugger;
+pub mod faults;
+pub mod gdb;
+mod image;
+mod isa;
+mod signals;
+mod snapshot;
+pub mod stimulus;
+pub mod trace;
+pub mod vcd;
+pub mod peripherals;
+
+pub use crate::alu::{AluFunction, AluOutput, evaluate as evaluate_alu};
+pub use crate::bus::{Bus, BusAccess, AccessKind, BusError, Mapping, Peripheral, ROM_BASE, ROM_SIZE, SRAM_BASE, SRAM_SIZE, GPIO_OUT_ADDRESS, BUTTONS_ADDRESS, UART_BASE, TIMER_BASE, SEVEN_SEGMENT_BASE};
+pub use crate::cpu::{Cpu, CpuState, Registers, Phase, TimingMode, DecodeMode, SimulatorError, DEFAULT_CLOCK_PERIOD, CYCLES_PER_INSTRUCTION, FLAG_CARRY, FLAG_ZERO, FLAG_EQUAL, FLAG_LESS_THAN};
+pub use crate::debugger::{Session, StopReason, Watchpoint, WatchKind, DEFAULT_RUN_LIMIT, DEFAULT_HISTORY_LIMIT, parse_number};
+pub use crate::image::{load_image, ImageError};
+pub use crate::snapshot::{Snapshot, SnapshotError};
+pub use crate::signals::{ControlSignals, BusConflict};
+pub use crate::isa::{Instruction, Operation, disassemble, register_name};
@@ -0,0 +1,128 @@
+
+use crate::bus::Peripheral;
+
+/// The output register of the GPIO circuit in testbench.json, drives the bargraph.
+///
+/// The register is write-only, its outputs are not connected to the data bus.
+/// Every write is recorded together with the clock cycle it happened in.
+#[derive(Default)]
+pub struct GpioOut {
+    value: u16,
+    cycle: u64,
+    history: Vec<(u64, u16)>,
+}
+
+impl GpioOut {
+    pub fn new() -> GpioOut {
+        GpioOut {
+            value: 0,
+            cycle: 0,
+            history: Vec::new(),
+        }
+    }
+
+    pub fn value(&self) -> u16 {
+        self.value
+    }
+
+    pub fn history(&self) -> &[(u64, u16)] {
+        &self.history
+    }
+}
+
+impl Peripheral for GpioOut {
+    fn size(&self) -> usize {
+        1
+    }
+
+    fn read(&mut self, _offset: u16) -> u16 {
+        0
+    }
+
+    fn peek(&self, _offset: u16) -> u16 {
+        0
+    }
+
+    fn write(&mut self, _offset: u16, value: u16) {
+        self.value = value;
+        self.history.push((self.cycle, value));
+    }
+
+    fn tick(&mut self) {
+        self.cycle += 1;
+    }
+
+    fn save_state(&self) -> Vec<u16> {
+        vec![self.value, self.cycle as u16, (self.cycle >> 16) as u16, (self.cycle >> 32) as u16, (self.cycle >> 48) as u16]
+    }
+
+    // Writes that happened after the restored cycle are dropped from the history
+    fn restore_state(&mut self, state: &[u16]) {
+        if let [value, cycle @ ..] = state {
+            self.value = *value;
+            self.cycle = cycle.iter().rev().fold(0, |cycle, &part| cycle << 16 | part as u64);
+            let cycle = self.cycle;
+            self.history.retain(|&(write_cycle, _)| write_cycle < cycle);
+        }
+    }
+}
+
+pub const BUTTON_COUNT: usize = 8;
+
+/// The 8 button inputs of the GPIO circuit in testbench.json
+#[derive(Default)]
+pub struct Buttons {
+    state: u8,
+}
+
+impl Buttons {
+    pub fn new() -> Buttons {
+        Buttons {
+            state: 0,
+        }
+    }
+
+    pub fn state(&self) -> u8 {
+        self.state
+    }
+
+    pub fn set_state(&mut self, state: u8) {
+        self.state = state;
+    }
+
+    pub fn set(&mut self, button: usize, pressed: bool) {
+        assert!(button < BUTTON_COUNT);
+        if pressed {
+            self.state |= 1 << button;
+        } else {
+            self.state &= !(1 << button);
+        }
+    }
+}
+
+impl Peripheral for Buttons {
+    fn size(&self) -> usize {
+        1
+    }
+
+    fn read(&mut self, offset: u16) -> u16 {
+        self.peek(offset)
+    }
+
+    fn peek(&self, _offset: u16) -> u16 {
+        self.state as u16
+    }
+
+    // The buttons are read-only
+    fn write(&mut self, _offset: u16, _value: u16) {}
+
+    fn save_state(&self) -> Vec<u16> {
+        vec![self.state as u16]
+    }
+
+    fn restore_state(&mut self, state: &[u16]) {
+        if let [state] = state {
+            self.state = *state as u8;
+        }
+    }
+}
@@ -0,0 +1,104 @@
+
+use crate::bus::Peripheral;
+
+/// Read-only memory, writes are ignored like in the testbench where the ROM has no write enable
+pub struct Rom {
+    words: Vec<u16>,
+}
+
+impl Rom {
+    pub fn new(size: usize) -> Rom {
+        Rom {
+            words: vec![0; size],
+        }
+    }
+
+    /// Loads `contents` starting at offset 0, returns `false` without loading anything if they don't fit
+    pub fn load(&mut self, contents: &[u16]) -> bool {
+        if contents.len() > self.words.len() {
+            return false;
+        }
+        self.words[..contents.len()].copy_from_slice(contents);
+        true
+    }
+
+    pub fn words(&self) -> &[u16] {
+        &self.words
+    }
+
+    pub fn words_mut(&mut self) -> &mut [u16] {
+        &mut self.words
+    }
+}
+
+impl Peripheral for Rom {
+    fn size(&self) -> usize {
+        self.words.len()
+    }
+
+    fn read(&mut self, offset: u16) -> u16 {
+        self.peek(offset)
+    }
+
+    fn peek(&self, offset: u16) -> u16 {
+        self.words[offset as usize]
+    }
+
+    fn write(&mut self, _offset: u16, _value: u16) {}
+
+    fn save_state(&self) -> Vec<u16> {
+        self.words.clone()
+    }
+
+    fn restore_state(&mut self, state: &[u16]) {
+        let len = state.len().min(self.words.len());
+        self.words[..len].copy_from_slice(&state[..len]);
+    }
+}
+
+pub struct Ram {
+    words: Vec<u16>,
+}
+
+impl Ram {
+    pub fn new(size: usize) -> Ram {
+        Ram {
+            words: vec![0; size],
+        }
+    }
+
+    pub fn words(&self) -> &[u16] {
+        &self.words
+    }
+
+    pub fn words_mut(&mut self) -> &mut [u16] {
+        &mut self.words
+    }
+}
+
+impl Peripheral for Ram {
+    fn size(&self) -> usize {
+        self.words.len()
+    }
+
+    fn read(&mut self, offset: u16) -> u16 {
+        self.peek(offset)
+    }
+
+    fn peek(&self, offset: u16) -> u16 {
+        self.words[offset as usize]
+    }
+
+    fn write(&mut self, offset: u16, value: u16) {
+        self.words[offset as usize] = value;
+    }
+
+    fn save_state(&self) -> Vec<u16> {
+        self.words.clone()
+    }
+
+    fn restore_state(&mut self, state: &[u16]) {
+        let len = state.len().min(self.words.len());
+        self.words[..len].copy_from_slice(&state[..len]);
+    }
+}
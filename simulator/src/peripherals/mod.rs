@@ -0,0 +1,12 @@
+
+mod memory;
+mod gpio;
+mod uart;
+mod timer;
+mod seven_segment;
+
+pub use self::memory::{Rom, Ram};
+pub use self::gpio::{GpioOut, Buttons, BUTTON_COUNT};
+pub use self::uart::{Uart, UART_DATA, UART_STATUS, UART_STATUS_RX_READY, UART_STATUS_TX_READY};
+pub use self::timer::{Timer, TIMER_COUNT_LOW, TIMER_COUNT_HIGH};
+pub use self::seven_segment::{SevenSegment, DEFAULT_DIGIT_COUNT};
@@ -0,0 +1,112 @@
+
+use crate::bus::Peripheral;
+
+pub const DEFAULT_DIGIT_COUNT: usize = 4;
+
+// Segment patterns for the hexadecimal digits, bit 0 is segment a and bit 6 is segment g
+const HEX_PATTERNS: [u8; 16] = [
+    0x3f, 0x06, 0x5b, 0x4f, 0x66, 0x6d, 0x7d, 0x07,
+    0x7f, 0x6f, 0x77, 0x7c, 0x39, 0x5e, 0x79, 0x71,
+];
+
+/// A multi-digit 7-segment display.
+///
+/// Register layout for a display with N digits:
+///
+/// | Offset     | Name        | Description                                                              |
+/// |------------|-------------|--------------------------------------------------------------------------|
+/// | 0 to N - 1 | DIGIT0..    | Segments of a digit, bit 0-6: segments a-g, bit 7: decimal point         |
+/// | N          | HEX         | Write only: shows the value in hexadecimal, one nibble per digit         |
+///
+/// Digit 0 is the rightmost digit.
+pub struct SevenSegment {
+    digits: Vec<u8>,
+}
+
+impl SevenSegment {
+    pub fn new(digit_count: usize) -> SevenSegment {
+        SevenSegment {
+            digits: vec![0; digit_count],
+        }
+    }
+
+    pub fn digits(&self) -> &[u8] {
+        &self.digits
+    }
+
+    /// Returns the displayed text, leftmost digit first. Patterns that don't show a hex digit are shown as `?`.
+    pub fn text(&self) -> String {
+        self.digits.iter().rev()
+            .map(|&segments| {
+                let c = match segments & 0x7f {
+                    0 => ' ',
+                    pattern => HEX_PATTERNS.iter()
+                        .position(|&p| p == pattern)
+                        .and_then(|digit| std::char::from_digit(digit as u32, 16))
+                        .unwrap_or('?'),
+                };
+                if segments & 0x80 != 0 {
+                    format!("{}.", c)
+                } else {
+                    c.to_string()
+                }
+            })
+            .collect()
+    }
+
+    /// Draws the display as three lines of ASCII art
+    pub fn render(&self) -> String {
+        let mut lines = [String::new(), String::new(), String::new()];
+
+        for &segments in self.digits.iter().rev() {
+            let on = |segment: u8, c: char| if segments & (1 << segment) != 0 { c } else { ' ' };
+            lines[0].push_str(&format!(" {}  ", on(0, '_')));
+            lines[1].push_str(&format!("{}{}{} ", on(5, '|'), on(6, '_'), on(1, '|')));
+            lines[2].push_str(&format!("{}{}{}{}", on(4, '|'), on(3, '_'), on(2, '|'), on(7, '.')));
+        }
+
+        lines.join("\n")
+    }
+}
+
+impl Default for SevenSegment {
+    fn default() -> SevenSegment {
+        SevenSegment::new(DEFAULT_DIGIT_COUNT)
+    }
+}
+
+impl Peripheral for SevenSegment {
+    fn size(&self) -> usize {
+        self.digits.len() + 1
+    }
+
+    fn read(&mut self, offset: u16) -> u16 {
+        self.peek(offset)
+    }
+
+    fn peek(&self, offset: u16) -> u16 {
+        self.digits.get(offset as usize).copied().unwrap_or(0) as u16
+    }
+
+    fn write(&mut self, offset: u16, value: u16) {
+        let offset = offset as usize;
+        if offset < self.digits.len() {
+            self.digits[offset] = value as u8;
+        } else {
+            for (i, digit) in self.digits.iter_mut().enumerate() {
+                let nibble = value.checked_shr(4 * i as u32).unwrap_or(0) & 0xf;
+                *digit = HEX_PATTERNS[nibble as usize];
+            }
+        }
+    }
+
+    fn save_state(&self) -> Vec<u16> {
+        self.digits.iter().map(|&segments| segments as u16).collect()
+    }
+
+    fn restore_state(&mut self, state: &[u16]) {
+        for (digit, &segments) in self.digits.iter_mut().zip(state) {
+            *digit = segments as u8;
+        }
+    }
+}
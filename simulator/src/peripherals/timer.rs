@@ -0,0 +1,73 @@
+
+use crate::bus::Peripheral;
+
+pub const TIMER_COUNT_LOW: u16 = 0;
+pub const TIMER_COUNT_HIGH: u16 = 1;
+
+/// A free-running counter of CPU clock cycles.
+///
+/// Register layout:
+///
+/// | Offset | Name       | Description                                                             |
+/// |--------|------------|-------------------------------------------------------------------------|
+/// | 0      | COUNT_LOW  | Read: bits 0-15 of the counter, latches COUNT_HIGH. Write: reset to 0   |
+/// | 1      | COUNT_HIGH | Bits 16-31 of the counter at the time COUNT_LOW was last read           |
+#[derive(Default)]
+pub struct Timer {
+    count: u32,
+    latched_high: u16,
+}
+
+impl Timer {
+    pub fn new() -> Timer {
+        Timer {
+            count: 0,
+            latched_high: 0,
+        }
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+}
+
+impl Peripheral for Timer {
+    fn size(&self) -> usize {
+        2
+    }
+
+    fn read(&mut self, offset: u16) -> u16 {
+        if offset == TIMER_COUNT_LOW {
+            self.latched_high = (self.count >> 16) as u16;
+        }
+        self.peek(offset)
+    }
+
+    fn peek(&self, offset: u16) -> u16 {
+        match offset {
+            TIMER_COUNT_LOW => self.count as u16,
+            _ => self.latched_high,
+        }
+    }
+
+    fn write(&mut self, offset: u16, _value: u16) {
+        if offset == TIMER_COUNT_LOW {
+            self.count = 0;
+        }
+    }
+
+    fn tick(&mut self) {
+        self.count = self.count.wrapping_add(1);
+    }
+
+    fn save_state(&self) -> Vec<u16> {
+        vec![self.count as u16, (self.count >> 16) as u16, self.latched_high]
+    }
+
+    fn restore_state(&mut self, state: &[u16]) {
+        if let [low, high, latched_high] = state {
+            self.count = (*high as u32) << 16 | *low as u32;
+            self.latched_high = *latched_high;
+        }
+    }
+}
@@ -0,0 +1,129 @@
+
+use std::collections::VecDeque;
+use std::io::{BufReader, Read, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use crate::bus::Peripheral;
+
+pub const UART_DATA: u16 = 0;
+pub const UART_STATUS: u16 = 1;
+
+pub const UART_STATUS_RX_READY: u16 = 1 << 0;
+pub const UART_STATUS_TX_READY: u16 = 1 << 1;
+
+/// A memory mapped UART console.
+///
+/// Register layout:
+///
+/// | Offset | Name   | Description                                                                    |
+/// |--------|--------|--------------------------------------------------------------------------------|
+/// | 0      | DATA   | Write: transmit the low byte. Read: receive a byte, 0 if none is available     |
+/// | 1      | STATUS | Bit 0: RX ready, a byte can be read from DATA. Bit 1: TX ready, always set     |
+///
+/// Transmitted bytes are recorded and written to the output, received bytes come from
+/// `push_input` or from a stream connected with `connect_input`.
+/// The UART has no state that could be saved in snapshots, the streams can't be rewound.
+pub struct Uart {
+    output: Option<Box<dyn Write>>,
+    transmitted: Vec<u8>,
+    input: VecDeque<u8>,
+    input_stream: Option<Receiver<u8>>,
+}
+
+impl Uart {
+    pub fn new() -> Uart {
+        Uart {
+            output: None,
+            transmitted: Vec::new(),
+            input: VecDeque::new(),
+            input_stream: None,
+        }
+    }
+
+    /// Creates a UART that transmits to stdout and receives from stdin
+    pub fn stdio() -> Uart {
+        let mut uart = Uart::new();
+        uart.set_output(Box::new(std::io::stdout()));
+        uart.connect_input(std::io::stdin());
+        uart
+    }
+
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = Some(output);
+    }
+
+    pub fn push_input(&mut self, bytes: &[u8]) {
+        self.input.extend(bytes);
+    }
+
+    /// Receives bytes from `reader` in the background so that reading never blocks the simulation
+    pub fn connect_input<R: Read + Send + 'static>(&mut self, reader: R) {
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            for byte in BufReader::new(reader).bytes() {
+                match byte {
+                    Ok(byte) => if sender.send(byte).is_err() {
+                        break;
+                    },
+                    Err(_) => break,
+                }
+            }
+        });
+
+        self.input_stream = Some(receiver);
+    }
+
+    pub fn transmitted(&self) -> &[u8] {
+        &self.transmitted
+    }
+
+    fn poll_input(&mut self) {
+        if let Some(stream) = &self.input_stream {
+            self.input.extend(stream.try_iter());
+        }
+    }
+}
+
+impl Default for Uart {
+    fn default() -> Uart {
+        Uart::new()
+    }
+}
+
+impl Peripheral for Uart {
+    fn size(&self) -> usize {
+        2
+    }
+
+    fn read(&mut self, offset: u16) -> u16 {
+        self.poll_input();
+
+        match offset {
+            UART_DATA => self.input.pop_front().unwrap_or(0) as u16,
+            _ => self.peek(offset),
+        }
+    }
+
+    fn peek(&self, offset: u16) -> u16 {
+        match offset {
+            UART_DATA => self.input.front().copied().unwrap_or(0) as u16,
+            _ => {
+                let rx_ready = if self.input.is_empty() { 0 } else { UART_STATUS_RX_READY };
+                rx_ready | UART_STATUS_TX_READY
+            },
+        }
+    }
+
+    fn write(&mut self, offset: u16, value: u16) {
+        if offset == UART_DATA {
+            let byte = value as u8;
+            self.transmitted.push(byte);
+            if let Some(output) = &mut self.output {
+                // The simulation keeps running if the console goes away
+                let _ = output.write_all(&[byte]).and_then(|_| output.flush());
+            }
+        }
+    }
+}
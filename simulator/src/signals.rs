@@ -0,0 +1,267 @@
+
+use std::fmt;
+
+use assembler::{Condition, Register};
+
+use crate::alu::AluFunction;
+use crate::isa::Instruction;
+
+// Outputs of RegisterDecoder in InstructionDecoder.hdl, in the order A, B, Addr, SP, SR, PC
+fn decode_register(code: u16) -> [bool; 6] {
+    let mut selected = [false; 6];
+    if let Some(flag) = selected.get_mut(code as usize) {
+        *flag = true;
+    }
+    selected
+}
+
+/// The outputs of InstructionDecoder.hdl for an instruction word.
+///
+/// The decoder assigns most outputs once per instruction, the compiler ORs these assignments together. This model
+/// evaluates the same equations, so an undocumented encoding enables exactly the signals it enables in hardware.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ControlSignals {
+    pub immediate_value: u16,
+    pub operand2_is_immediate: bool,
+    pub immediate_out: bool,
+    pub a_in: bool,
+    pub a_out: bool,
+    pub b_in: bool,
+    pub b_out: bool,
+    pub addr_in: bool,
+    pub addr_out: bool,
+    pub sp_in: bool,
+    pub sp_out: bool,
+    pub sr_in: bool,
+    pub sr_carry_in: bool,
+    pub sr_zero_in: bool,
+    pub sr_equal_in: bool,
+    pub sr_less_than_in: bool,
+    pub sr_out: bool,
+    pub pc_in: bool,
+    pub pc_out: bool,
+    pub pc_in_cond: u16,
+    pub alu_out: bool,
+    pub alu_fn: u16,
+    pub mem_read: bool,
+    pub mem_write: bool,
+}
+
+impl ControlSignals {
+    pub fn decode(instruction: u16) -> ControlSignals {
+        let opcode = instruction >> 11;
+        let kind = |code: u16| opcode & 0xf == code;
+
+        let is_mov = kind(0x0);
+        let is_ld = kind(0x1);
+        let is_st = kind(0x2);
+        let is_and = kind(0x3);
+        let is_or = kind(0x4);
+        let is_xor = kind(0x5);
+        let is_not = kind(0x6);
+        let is_add = kind(0x7);
+        let is_sub = kind(0x8);
+        let is_sl = kind(0x9);
+        let is_sr = kind(0xa);
+        let is_cmp = kind(0xb);
+        let is_jmp = kind(0xc);
+        let is_immediate_variant = opcode & 0x10 != 0;
+
+        let mov_target = decode_register(instruction & 0x7);
+        // mov and jmp take their source from the same bits
+        let source = decode_register((instruction >> 3) & 0x7);
+        let jmp_cond = instruction & 0x7;
+
+        let is_alu = is_and || is_or || is_xor || is_not || is_add || is_sub || is_sl || is_sr;
+        let has_immediate_operand = is_and || is_or || is_xor || is_add || is_sl || is_sr || is_cmp;
+
+        let mut alu_fn = 0;
+        for (enabled, function) in [(is_or, 1), (is_xor, 2), (is_not, 3), (is_sl, 4), (is_sr, 5), (is_add, 6), (is_sub, 7), (is_cmp, 7)] {
+            if enabled {
+                alu_fn |= function;
+            }
+        }
+
+        let mov_in = |register: usize| mov_target[register] && is_mov;
+        let out = |register: usize| source[register] && (is_mov || is_jmp);
+
+        ControlSignals {
+            immediate_value: instruction & 0x7ff,
+            operand2_is_immediate: has_immediate_operand && is_immediate_variant,
+            immediate_out: is_ld && is_immediate_variant,
+            a_in: mov_in(0) || is_ld || is_alu,
+            a_out: out(0) || is_st,
+            b_in: mov_in(1),
+            b_out: out(1),
+            addr_in: mov_in(2),
+            addr_out: out(2),
+            sp_in: mov_in(3),
+            sp_out: out(3),
+            sr_in: mov_in(4),
+            sr_carry_in: is_add || is_sub,
+            sr_zero_in: is_alu,
+            sr_equal_in: is_cmp,
+            sr_less_than_in: is_cmp,
+            sr_out: out(4),
+            pc_in: mov_in(5) || is_jmp,
+            pc_out: out(5),
+            pc_in_cond: if is_jmp { jmp_cond } else { 0 },
+            alu_out: is_alu,
+            alu_fn,
+            mem_read: is_ld && !is_immediate_variant,
+            mem_write: is_st,
+        }
+    }
+
+    /// The signals an instruction needs according to the ISA the assembler implements, independently of the equations
+    /// in InstructionDecoder.hdl. `word` is the encoding of the instruction, its low 11 bits are the immediate value.
+    pub fn reference(instruction: Instruction, word: u16) -> ControlSignals {
+        let mut signals = ControlSignals {
+            immediate_value: word & 0x7ff,
+            ..ControlSignals::default()
+        };
+
+        // ALU instructions write their result to A and update the zero flag, add and sub also update the carry flag
+        let alu = |signals: &mut ControlSignals, function: AluFunction, immediate: bool| {
+            signals.alu_fn = function as u16;
+            signals.alu_out = true;
+            signals.a_in = true;
+            signals.sr_zero_in = true;
+            signals.sr_carry_in = matches!(function, AluFunction::Add | AluFunction::Subtract);
+            signals.operand2_is_immediate = immediate;
+        };
+
+        match instruction {
+            Instruction::Mov(target, source) => {
+                signals.set_register_in(target);
+                signals.set_register_out(source);
+                // A move to the PC is an unconditional jump
+                signals.pc_in_cond = Condition::None as u16;
+            },
+            Instruction::Ld => {
+                signals.mem_read = true;
+                signals.a_in = true;
+            },
+            Instruction::Ldi(_) => {
+                signals.immediate_out = true;
+                signals.a_in = true;
+            },
+            Instruction::St => {
+                signals.a_out = true;
+                signals.mem_write = true;
+            },
+            Instruction::And => alu(&mut signals, AluFunction::And, false),
+            Instruction::Andi(_) => alu(&mut signals, AluFunction::And, true),
+            Instruction::Or => alu(&mut signals, AluFunction::Or, false),
+            Instruction::Ori(_) => alu(&mut signals, AluFunction::Or, true),
+            Instruction::Xor => alu(&mut signals, AluFunction::Xor, false),
+            Instruction::Xori(_) => alu(&mut signals, AluFunction::Xor, true),
+            Instruction::Not => alu(&mut signals, AluFunction::Not, false),
+            Instruction::Add => alu(&mut signals, AluFunction::Add, false),
+            Instruction::Addi(_) => alu(&mut signals, AluFunction::Add, true),
+            Instruction::Sub => alu(&mut signals, AluFunction::Subtract, false),
+            Instruction::Sl => alu(&mut signals, AluFunction::ShiftLeft, false),
+            Instruction::Sli(_) => alu(&mut signals, AluFunction::ShiftLeft, true),
+            Instruction::Sr => alu(&mut signals, AluFunction::ShiftRight, false),
+            Instruction::Sri(_) => alu(&mut signals, AluFunction::ShiftRight, true),
+            // cmp subtracts without writing the result and only updates the comparison flags
+            Instruction::Cmp | Instruction::Cmpi(_) => {
+                signals.alu_fn = AluFunction::Subtract as u16;
+                signals.sr_equal_in = true;
+                signals.sr_less_than_in = true;
+                signals.operand2_is_immediate = matches!(instruction, Instruction::Cmpi(_));
+            },
+            Instruction::Jmp(condition, source) => {
+                signals.set_register_out(source);
+                signals.pc_in = true;
+                signals.pc_in_cond = condition as u16;
+            },
+        }
+
+        signals
+    }
+
+    fn set_register_in(&mut self, register: Register) {
+        match register {
+            Register::A => self.a_in = true,
+            Register::B => self.b_in = true,
+            Register::Addr => self.addr_in = true,
+            Register::SP => self.sp_in = true,
+            Register::SR => self.sr_in = true,
+            Register::PC => self.pc_in = true,
+        }
+    }
+
+    fn set_register_out(&mut self, register: Register) {
+        match register {
+            Register::A => self.a_out = true,
+            Register::B => self.b_out = true,
+            Register::Addr => self.addr_out = true,
+            Register::SP => self.sp_out = true,
+            Register::SR => self.sr_out = true,
+            Register::PC => self.pc_out = true,
+        }
+    }
+
+    /// All outputs with the names of the ports of InstructionDecoder.hdl
+    pub fn outputs(&self) -> [(&'static str, u16); 24] {
+        [
+            ("immediate_value", self.immediate_value),
+            ("operand2_is_immediate", self.operand2_is_immediate as u16),
+            ("immediate_out", self.immediate_out as u16),
+            ("A_in", self.a_in as u16),
+            ("A_out", self.a_out as u16),
+            ("B_in", self.b_in as u16),
+            ("B_out", self.b_out as u16),
+            ("Addr_in", self.addr_in as u16),
+            ("Addr_out", self.addr_out as u16),
+            ("SP_in", self.sp_in as u16),
+            ("SP_out", self.sp_out as u16),
+            ("SR_in", self.sr_in as u16),
+            ("SR_carry_in", self.sr_carry_in as u16),
+            ("SR_zero_in", self.sr_zero_in as u16),
+            ("SR_equal_in", self.sr_equal_in as u16),
+            ("SR_less_than_in", self.sr_less_than_in as u16),
+            ("SR_out", self.sr_out as u16),
+            ("PC_in", self.pc_in as u16),
+            ("PC_out", self.pc_out as u16),
+            ("PC_in_cond", self.pc_in_cond),
+            ("alu_out", self.alu_out as u16),
+            ("alu_fn", self.alu_fn),
+            ("mem_read", self.mem_read as u16),
+            ("mem_write", self.mem_write as u16),
+        ]
+    }
+
+    /// Names of the sources that drive the data bus in the execute cycle
+    pub fn data_bus_drivers(&self) -> Vec<&'static str> {
+        [
+            (self.immediate_out, "immediate"),
+            (self.a_out, "A"),
+            (self.b_out, "B"),
+            (self.addr_out, "Addr"),
+            (self.sp_out, "SP"),
+            (self.sr_out, "SR"),
+            (self.pc_out, "PC"),
+            (self.alu_out, "ALU"),
+            (self.mem_read, "memory"),
+        ].iter()
+            .filter(|(enabled, _)| *enabled)
+            .map(|&(_, name)| name)
+            .collect()
+    }
+}
+
+/// An instruction that enabled more than one driver of the data bus, the bus carries the OR of their values
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BusConflict {
+    pub pc: u16,
+    pub word: u16,
+    pub drivers: Vec<&'static str>,
+}
+
+impl fmt::Display for BusConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "0x{:04x} at 0x{:04x} drives the data bus from {}", self.word, self.pc, self.drivers.join(", "))
+    }
+}
@@ -0,0 +1,187 @@
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::{error, fmt};
+
+use serde_json::{json, Map, Value};
+
+use assembler::Register;
+
+use crate::cpu::{Cpu, CpuState, Phase, Registers};
+use crate::isa::register_name;
+
+const FORMAT: &str = "hdl-cpu-snapshot";
+const VERSION: u64 = 1;
+
+const REGISTERS: [Register; 6] = [Register::A, Register::B, Register::Addr, Register::SP, Register::SR, Register::PC];
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    FileRead(PathBuf, io::Error),
+    FileWrite(PathBuf, io::Error),
+    Json(serde_json::Error),
+    Invalid(String),
+    UnknownDevice(String),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            SnapshotError::FileRead(path, _) => write!(f, "failed to read snapshot {}", path.display()),
+            SnapshotError::FileWrite(path, _) => write!(f, "failed to write snapshot {}", path.display()),
+            SnapshotError::Json(_) => write!(f, "snapshot is not valid JSON"),
+            SnapshotError::Invalid(field) => write!(f, "invalid or missing \"{}\" in snapshot", field),
+            SnapshotError::UnknownDevice(name) => write!(f, "snapshot contains device \"{}\" that isn't mapped", name),
+        }
+    }
+}
+
+impl error::Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            SnapshotError::FileRead(_, err) => Some(err),
+            SnapshotError::FileWrite(_, err) => Some(err),
+            SnapshotError::Json(err) => Some(err),
+            SnapshotError::Invalid(_) => None,
+            SnapshotError::UnknownDevice(_) => None,
+        }
+    }
+}
+
+/// The complete state of a machine: the CPU and the saved state of every mapped device.
+///
+/// Snapshots are stored as JSON. Devices are identified by the name they are mapped with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    pub cpu: CpuState,
+    pub devices: Vec<(String, Vec<u16>)>,
+}
+
+fn field<'a>(value: &'a Value, name: &str) -> Result<&'a Value, SnapshotError> {
+    value.get(name).ok_or_else(|| SnapshotError::Invalid(name.to_string()))
+}
+
+fn number(value: &Value, name: &str) -> Result<u64, SnapshotError> {
+    field(value, name)?.as_u64().ok_or_else(|| SnapshotError::Invalid(name.to_string()))
+}
+
+fn word(value: &Value, name: &str) -> Result<u16, SnapshotError> {
+    let number = number(value, name)?;
+    if number > 0xffff {
+        return Err(SnapshotError::Invalid(name.to_string()));
+    }
+    Ok(number as u16)
+}
+
+impl Snapshot {
+    pub fn capture(cpu: &Cpu) -> Snapshot {
+        Snapshot {
+            cpu: cpu.state(),
+            devices: cpu.bus.mappings().iter()
+                .map(|mapping| (mapping.name.clone(), mapping.device().save_state()))
+                .collect(),
+        }
+    }
+
+    /// Restores the snapshot into `cpu`, whose bus must have all devices of the snapshot mapped
+    pub fn restore(&self, cpu: &mut Cpu) -> Result<(), SnapshotError> {
+        let mut indices = Vec::new();
+        for (name, _) in &self.devices {
+            let index = cpu.bus.mappings().iter()
+                .position(|mapping| &mapping.name == name)
+                .ok_or_else(|| SnapshotError::UnknownDevice(name.clone()))?;
+            indices.push(index);
+        }
+
+        cpu.restore_state(&self.cpu);
+        for (index, (_, state)) in indices.into_iter().zip(&self.devices) {
+            cpu.bus.restore_state(index, state);
+        }
+
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Value {
+        let registers = REGISTERS.iter()
+            .map(|&register| (register_name(register).to_string(), json!(self.cpu.registers.get(register))))
+            .collect::<Map<_, _>>();
+        let devices = self.devices.iter()
+            .map(|(name, state)| (name.clone(), json!(state)))
+            .collect::<Map<_, _>>();
+
+        json!({
+            "format": FORMAT,
+            "version": VERSION,
+            "cycles": self.cpu.cycles,
+            "instructions": self.cpu.instructions,
+            "phase": match self.cpu.phase {
+                Phase::Fetch => "fetch",
+                Phase::Execute => "execute",
+            },
+            "ir": self.cpu.ir,
+            "registers": registers,
+            "devices": devices,
+        })
+    }
+
+    pub fn from_json(value: &Value) -> Result<Snapshot, SnapshotError> {
+        if field(value, "format")? != FORMAT {
+            return Err(SnapshotError::Invalid("format".to_string()));
+        }
+        if number(value, "version")? != VERSION {
+            return Err(SnapshotError::Invalid("version".to_string()));
+        }
+
+        let phase = match field(value, "phase")?.as_str() {
+            Some("fetch") => Phase::Fetch,
+            Some("execute") => Phase::Execute,
+            _ => return Err(SnapshotError::Invalid("phase".to_string())),
+        };
+
+        let registers_value = field(value, "registers")?;
+        let mut registers = Registers::default();
+        for &register in &REGISTERS {
+            registers.set(register, word(registers_value, register_name(register))?);
+        }
+
+        let devices = field(value, "devices")?.as_object()
+            .ok_or_else(|| SnapshotError::Invalid("devices".to_string()))?
+            .iter()
+            .map(|(name, state)| {
+                let state = state.as_array()
+                    .and_then(|words| words.iter()
+                        .map(|word| word.as_u64().filter(|&word| word <= 0xffff).map(|word| word as u16))
+                        .collect::<Option<Vec<_>>>())
+                    .ok_or_else(|| SnapshotError::Invalid(format!("devices.{}", name)))?;
+                Ok((name.clone(), state))
+            })
+            .collect::<Result<Vec<_>, SnapshotError>>()?;
+
+        Ok(Snapshot {
+            cpu: CpuState {
+                registers,
+                ir: word(value, "ir")?,
+                phase,
+                instructions: number(value, "instructions")?,
+                cycles: number(value, "cycles")?,
+            },
+            devices,
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SnapshotError> {
+        let path = path.as_ref();
+        fs::write(path, self.to_json().to_string())
+            .map_err(|err| SnapshotError::FileWrite(path.to_path_buf(), err))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Snapshot, SnapshotError> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .map_err(|err| SnapshotError::FileRead(path.to_path_buf(), err))?;
+        let value = serde_json::from_str(&contents)
+            .map_err(SnapshotError::Json)?;
+        Snapshot::from_json(&value)
+    }
+}
@@ -0,0 +1,295 @@
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::{error, fmt};
+
+use assembler::Program;
+
+use crate::cpu::{Cpu, Phase};
+use crate::debugger::parse_number;
+use crate::peripherals::{Buttons, Uart, BUTTON_COUNT};
+
+#[derive(Debug)]
+pub enum StimulusError {
+    FileRead(PathBuf, io::Error),
+    Syntax(usize, String),
+    UnknownLabel(usize, String),
+    MissingDevice(usize, &'static str),
+}
+
+impl fmt::Display for StimulusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            StimulusError::FileRead(path, _) => write!(f, "failed to read stimulus {}", path.display()),
+            StimulusError::Syntax(line, message) => write!(f, "line {}: {}", line, message),
+            StimulusError::UnknownLabel(line, label) => write!(f, "line {}: unknown label \"{}\"", line, label),
+            StimulusError::MissingDevice(line, device) => write!(f, "line {}: no {} is mapped", line, device),
+        }
+    }
+}
+
+impl error::Error for StimulusError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            StimulusError::FileRead(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Trigger {
+    /// Once the given number of cycles has been executed
+    Cycle(u64),
+    /// The given number of cycles after the previous event
+    After(u64),
+    /// The n-th time the CPU fetches from the address after the previous event
+    Label(u16, u32),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    Buttons(u8),
+    Press(usize),
+    Release(usize),
+    Uart(Vec<u8>),
+    Stop,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub line: usize,
+    pub trigger: Trigger,
+    pub action: Action,
+}
+
+/// A script of inputs that is played back during a simulation.
+///
+/// Every line of a stimulus file contains a trigger and an action, events fire one after another in the order of the
+/// file:
+///
+/// ```text
+/// # Press the first button once the program waits for it
+/// wait_button      press 0
+/// +20              release 0
+/// # The second time the loop starts
+/// loop#2           buttons 0x03
+/// 5000             uart "hello\n"
+/// 10000            stop
+/// ```
+///
+/// Triggers are an absolute cycle count, `+N` for N cycles after the previous event or `LABEL` / `LABEL#N` for the
+/// (n-th) fetch from a label after the previous event. Actions are `buttons VALUE`, `press N`, `release N`, `uart`
+/// with a string or a byte value, and `stop` to end the simulation.
+#[derive(Clone, Debug)]
+pub struct Stimulus {
+    events: Vec<Event>,
+    next: usize,
+    // Cycle in which the previous event fired
+    armed_at: Option<u64>,
+    hits: u32,
+    fired: Vec<(u64, usize)>,
+}
+
+// Comments start with a `#` at the beginning of the line or after whitespace, outside of strings. Other `#`s separate
+// the count of a label trigger.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut previous = ' ';
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string && previous.is_whitespace() => return &line[..i],
+            _ => {},
+        }
+        previous = c;
+    }
+    line
+}
+
+fn parse_string(text: &str) -> Option<Vec<u8>> {
+    let text = text.strip_prefix('"')?.strip_suffix('"')?;
+
+    let mut bytes = Vec::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buffer = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+        match chars.next()? {
+            'n' => bytes.push(b'\n'),
+            'r' => bytes.push(b'\r'),
+            't' => bytes.push(b'\t'),
+            '0' => bytes.push(0),
+            '\\' => bytes.push(b'\\'),
+            '"' => bytes.push(b'"'),
+            'x' => {
+                let digits = chars.by_ref().take(2).collect::<String>();
+                if digits.len() != 2 {
+                    return None;
+                }
+                bytes.push(u8::from_str_radix(&digits, 16).ok()?);
+            },
+            _ => return None,
+        }
+    }
+    Some(bytes)
+}
+
+fn parse_trigger(text: &str, program: &Program, line: usize) -> Result<Trigger, StimulusError> {
+    let syntax = || StimulusError::Syntax(line, format!("invalid trigger \"{}\"", text));
+
+    if let Some(cycles) = text.strip_prefix('+') {
+        return parse_number(cycles).map(Trigger::After).ok_or_else(syntax);
+    }
+    if text.starts_with(|c: char| c.is_ascii_digit()) {
+        return parse_number(text).map(Trigger::Cycle).ok_or_else(syntax);
+    }
+
+    let (label, count) = match text.split_once('#') {
+        Some((label, count)) => (label, count.parse().ok().filter(|&count| count > 0).ok_or_else(syntax)?),
+        None => (text, 1),
+    };
+    let address = program.labels.get(label)
+        .ok_or_else(|| StimulusError::UnknownLabel(line, label.to_string()))?;
+    Ok(Trigger::Label(*address, count))
+}
+
+fn parse_action(text: &str, line: usize) -> Result<Action, StimulusError> {
+    let syntax = |message: &str| StimulusError::Syntax(line, message.to_string());
+
+    let (name, argument) = match text.split_once(char::is_whitespace) {
+        Some((name, argument)) => (name, argument.trim()),
+        None => (text, ""),
+    };
+    let button = || parse_number(argument)
+        .filter(|&button| button < BUTTON_COUNT as u64)
+        .map(|button| button as usize)
+        .ok_or_else(|| syntax("invalid button number"));
+
+    match name {
+        "buttons" => parse_number(argument)
+            .filter(|&value| value <= 0xff)
+            .map(|value| Action::Buttons(value as u8))
+            .ok_or_else(|| syntax("invalid button state")),
+        "press" => Ok(Action::Press(button()?)),
+        "release" => Ok(Action::Release(button()?)),
+        "uart" => if argument.starts_with('"') {
+            parse_string(argument).map(Action::Uart).ok_or_else(|| syntax("invalid string"))
+        } else {
+            parse_number(argument)
+                .filter(|&value| value <= 0xff)
+                .map(|value| Action::Uart(vec![value as u8]))
+                .ok_or_else(|| syntax("invalid byte"))
+        },
+        "stop" if argument.is_empty() => Ok(Action::Stop),
+        _ => Err(syntax(&format!("unknown action \"{}\"", text))),
+    }
+}
+
+impl Stimulus {
+    /// Parses a stimulus, labels are looked up in `program`
+    pub fn parse(source: &str, program: &Program) -> Result<Stimulus, StimulusError> {
+        let mut events = Vec::new();
+
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index + 1;
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (trigger, action) = line.split_once(char::is_whitespace)
+                .ok_or_else(|| StimulusError::Syntax(line_number, "expected a trigger and an action".to_string()))?;
+
+            events.push(Event {
+                line: line_number,
+                trigger: parse_trigger(trigger, program, line_number)?,
+                action: parse_action(action.trim(), line_number)?,
+            });
+        }
+
+        Ok(Stimulus {
+            events,
+            next: 0,
+            armed_at: None,
+            hits: 0,
+            fired: Vec::new(),
+        })
+    }
+
+    pub fn load(path: &Path, program: &Program) -> Result<Stimulus, StimulusError> {
+        let source = fs::read_to_string(path)
+            .map_err(|err| StimulusError::FileRead(path.to_path_buf(), err))?;
+        Stimulus::parse(&source, program)
+    }
+
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Cycle and source line of every event that fired so far
+    pub fn fired(&self) -> &[(u64, usize)] {
+        &self.fired
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next == self.events.len()
+    }
+
+    /// Fires the events that are due before the next clock cycle, call this before every cycle.
+    ///
+    /// Returns `true` if a `stop` action fired.
+    pub fn apply(&mut self, cpu: &mut Cpu) -> Result<bool, StimulusError> {
+        let cycle = cpu.cycles();
+
+        while let Some(event) = self.events.get(self.next) {
+            let due = match event.trigger {
+                Trigger::Cycle(at) => cycle >= at,
+                Trigger::After(delay) => cycle >= self.armed_at.unwrap_or(0) + delay,
+                Trigger::Label(address, count) => {
+                    // Only count fetches after the previous event fired
+                    let fetch = cpu.phase() == Phase::Fetch && cpu.registers.pc == address
+                        && self.armed_at.is_none_or(|armed_at| cycle > armed_at);
+                    if fetch {
+                        self.hits += 1;
+                    }
+                    fetch && self.hits >= count
+                },
+            };
+            if !due {
+                return Ok(false);
+            }
+
+            let line = event.line;
+            let action = event.action.clone();
+            self.next += 1;
+            self.armed_at = Some(cycle);
+            self.hits = 0;
+            self.fired.push((cycle, line));
+
+            match action {
+                Action::Buttons(state) => Stimulus::buttons(cpu, line)?.set_state(state),
+                Action::Press(button) => Stimulus::buttons(cpu, line)?.set(button, true),
+                Action::Release(button) => Stimulus::buttons(cpu, line)?.set(button, false),
+                Action::Uart(bytes) => cpu.bus.device_mut::<Uart>()
+                    .ok_or(StimulusError::MissingDevice(line, "UART"))?
+                    .push_input(&bytes),
+                Action::Stop => return Ok(true),
+            }
+        }
+
+        Ok(false)
+    }
+
+    fn buttons(cpu: &mut Cpu, line: usize) -> Result<&mut Buttons, StimulusError> {
+        cpu.bus.device_mut::<Buttons>()
+            .ok_or(StimulusError::MissingDevice(line, "buttons"))
+    }
+}
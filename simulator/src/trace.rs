@@ -0,0 +1,192 @@
+
+use std::io::{self, BufRead, Write};
+use std::{error, fmt};
+
+use assembler::Register;
+
+use crate::bus::{BusAccess, AccessKind};
+use crate::cpu::{Cpu, Phase, Registers};
+use crate::isa::{disassemble, register_name};
+
+// Registers in the order they appear in a trace line, the PC only appears after jumps
+const TRACED_REGISTERS: [Register; 6] = [Register::A, Register::B, Register::Addr, Register::SP, Register::SR, Register::PC];
+
+/// One executed instruction.
+///
+/// In text form an entry is a single line like `8 0004 0800 ld | A=f0f0 [0400]->f0f0`: the cycle in which the
+/// instruction was fetched, its address and word, the disassembly, and after the `|` the registers it changed and its
+/// memory access. `[ADDRESS]->DATA` is a read, `[ADDRESS]<-DATA` a write. The disassembly is only informational.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub cycle: u64,
+    pub pc: u16,
+    pub word: u16,
+    pub changes: Vec<(Register, u16)>,
+    pub access: Option<BusAccess>,
+}
+
+impl TraceEntry {
+    pub fn new(cycle: u64, word: u16, before: &Registers, after: &Registers, access: Option<BusAccess>) -> TraceEntry {
+        let changes = TRACED_REGISTERS.iter()
+            .filter(|&&register| match register {
+                Register::PC => after.pc != before.pc.wrapping_add(1),
+                _ => after.get(register) != before.get(register),
+            })
+            .map(|&register| (register, after.get(register)))
+            .collect();
+
+        TraceEntry {
+            cycle,
+            pc: before.pc,
+            word,
+            changes,
+            access,
+        }
+    }
+
+    /// Compares everything but the cycle, which is only compared if `compare_cycles` is set
+    pub fn matches(&self, other: &TraceEntry, compare_cycles: bool) -> bool {
+        (!compare_cycles || self.cycle == other.cycle)
+            && self.pc == other.pc
+            && self.word == other.word
+            && self.changes == other.changes
+            && self.access == other.access
+    }
+
+    pub fn parse(line: &str) -> Option<TraceEntry> {
+        let (instruction, effects) = line.split_once('|')?;
+
+        let mut fields = instruction.split_whitespace();
+        let cycle = fields.next()?.parse().ok()?;
+        let pc = u16::from_str_radix(fields.next()?, 16).ok()?;
+        let word = u16::from_str_radix(fields.next()?, 16).ok()?;
+
+        let mut changes = Vec::new();
+        let mut access = None;
+        for effect in effects.split_whitespace() {
+            if let Some(effect) = effect.strip_prefix('[') {
+                let (address, data, kind) = if let Some((address, data)) = effect.split_once("]->") {
+                    (address, data, AccessKind::Read)
+                } else {
+                    let (address, data) = effect.split_once("]<-")?;
+                    (address, data, AccessKind::Write)
+                };
+                access = Some(BusAccess {
+                    kind,
+                    address: u16::from_str_radix(address, 16).ok()?,
+                    data: u16::from_str_radix(data, 16).ok()?,
+                });
+            } else {
+                let (register, value) = effect.split_once('=')?;
+                changes.push((Register::from_str(register)?, u16::from_str_radix(value, 16).ok()?));
+            }
+        }
+
+        Some(TraceEntry {
+            cycle,
+            pc,
+            word,
+            changes,
+            access,
+        })
+    }
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{} {:04x} {:04x} {} |", self.cycle, self.pc, self.word, disassemble(self.word))?;
+        for &(register, value) in &self.changes {
+            write!(f, " {}={:04x}", register_name(register), value)?;
+        }
+        if let Some(access) = self.access {
+            let arrow = match access.kind {
+                AccessKind::Read => "->",
+                AccessKind::Write => "<-",
+            };
+            write!(f, " [{:04x}]{}{:04x}", access.address, arrow, access.data)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum TraceError {
+    Io(io::Error),
+    Syntax(usize),
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            TraceError::Io(_) => write!(f, "failed to read trace"),
+            TraceError::Syntax(line) => write!(f, "invalid trace entry in line {}", line),
+        }
+    }
+}
+
+impl error::Error for TraceError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            TraceError::Io(err) => Some(err),
+            TraceError::Syntax(_) => None,
+        }
+    }
+}
+
+/// Reads a trace, skipping empty lines and comments starting with `#`
+pub fn read_trace<R: BufRead>(reader: R) -> Result<Vec<TraceEntry>, TraceError> {
+    let mut entries = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.map_err(TraceError::Io)?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        entries.push(TraceEntry::parse(line).ok_or(TraceError::Syntax(index + 1))?);
+    }
+    Ok(entries)
+}
+
+/// Index of the first entry in which the traces differ, or `None` if they are the same
+pub fn first_divergence(expected: &[TraceEntry], actual: &[TraceEntry], compare_cycles: bool) -> Option<usize> {
+    expected.iter().zip(actual)
+        .position(|(expected, actual)| !expected.matches(actual, compare_cycles))
+        .or_else(|| if expected.len() != actual.len() {
+            Some(expected.len().min(actual.len()))
+        } else {
+            None
+        })
+}
+
+/// Writes a trace entry for every instruction the CPU executes, call `sample` after every clock cycle
+pub struct Tracer<W: Write> {
+    writer: W,
+    // Cycle and registers at the start of the instruction that is being executed
+    start: Option<(u64, Registers)>,
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(writer: W) -> Tracer<W> {
+        Tracer {
+            writer,
+            start: None,
+        }
+    }
+
+    pub fn sample(&mut self, cpu: &Cpu) -> io::Result<()> {
+        match cpu.phase() {
+            // The instruction was just fetched, which doesn't change any registers besides IR
+            Phase::Execute => self.start = Some((cpu.cycles() - 1, cpu.registers)),
+            Phase::Fetch => if let Some((cycle, before)) = self.start.take() {
+                let entry = TraceEntry::new(cycle, cpu.ir(), &before, &cpu.registers, cpu.bus_access());
+                writeln!(self.writer, "{}", entry)?;
+            },
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
@@ -0,0 +1,175 @@
+
+use std::io::{self, Write};
+
+use crate::bus::AccessKind;
+use crate::cpu::{Cpu, Phase, FLAG_CARRY, FLAG_ZERO, FLAG_EQUAL, FLAG_LESS_THAN};
+use crate::peripherals::{GpioOut, Buttons, BUTTON_COUNT};
+
+// Name and width of the dumped signals, in the order of `values`
+const SIGNALS: [(&str, u32); 20] = [
+    ("clk", 1),
+    ("fetch", 1),
+    ("pc", 16),
+    ("ir", 16),
+    ("a", 16),
+    ("b", 16),
+    ("addr", 16),
+    ("sp", 16),
+    ("sr", 16),
+    ("flag_c", 1),
+    ("flag_z", 1),
+    ("flag_eq", 1),
+    ("flag_lt", 1),
+    ("bus_addr", 16),
+    ("bus_data", 16),
+    ("bus_rd", 1),
+    ("bus_wr", 1),
+    ("gpio_out", 16),
+    ("buttons", BUTTON_COUNT as u32),
+    ("instructions", 32),
+];
+
+const CLK: usize = 0;
+const BUS_SIGNALS: std::ops::Range<usize> = 13..17;
+
+/// Writes the state of the CPU after every clock cycle as Value Change Dump, e.g. for GTKWave.
+///
+/// Cycle `n` spans the time from `n * period` to `(n + 1) * period` like in testbench.json. The clock is high during
+/// the first half of each cycle and the memory bus shows the access of the cycle. The registers change at the rising
+/// edge that ends the cycle.
+pub struct VcdWriter<W: Write> {
+    writer: W,
+    period: u64,
+    values: Vec<u64>,
+    // Registers after the last sampled cycle, they are dumped with the next rising edge
+    pending: Vec<u64>,
+    time: u64,
+}
+
+fn identifier(index: usize) -> String {
+    // Identifiers use the printable ASCII characters from '!' to '~'
+    let mut index = index;
+    let mut identifier = String::new();
+    loop {
+        identifier.push((b'!' + (index % 94) as u8) as char);
+        index /= 94;
+        if index == 0 {
+            return identifier;
+        }
+        index -= 1;
+    }
+}
+
+fn values(cpu: &Cpu) -> Vec<u64> {
+    let regs = &cpu.registers;
+    let access = cpu.bus_access();
+    let gpio = cpu.bus.device::<GpioOut>().map_or(0, GpioOut::value);
+    let buttons = cpu.bus.device::<Buttons>().map_or(0, Buttons::state);
+
+    vec![
+        0,
+        (cpu.phase() == Phase::Fetch) as u64,
+        regs.pc as u64,
+        cpu.ir() as u64,
+        regs.a as u64,
+        regs.b as u64,
+        regs.addr as u64,
+        regs.sp as u64,
+        regs.sr as u64,
+        regs.flag(FLAG_CARRY) as u64,
+        regs.flag(FLAG_ZERO) as u64,
+        regs.flag(FLAG_EQUAL) as u64,
+        regs.flag(FLAG_LESS_THAN) as u64,
+        access.map_or(0, |access| access.address as u64),
+        access.map_or(0, |access| access.data as u64),
+        access.is_some_and(|access| access.kind == AccessKind::Read) as u64,
+        access.is_some_and(|access| access.kind == AccessKind::Write) as u64,
+        gpio as u64,
+        buttons as u64,
+        cpu.instructions(),
+    ]
+}
+
+impl<W: Write> VcdWriter<W> {
+    /// Writes the header and the current state of `cpu` as initial values
+    pub fn new(mut writer: W, cpu: &Cpu) -> io::Result<VcdWriter<W>> {
+        writeln!(writer, "$version hdl-cpu simulator $end")?;
+        writeln!(writer, "$timescale 1ns $end")?;
+        writeln!(writer, "$scope module cpu $end")?;
+        for (index, (name, width)) in SIGNALS.iter().enumerate() {
+            writeln!(writer, "$var wire {} {} {} $end", width, identifier(index), name)?;
+        }
+        writeln!(writer, "$upscope $end")?;
+        writeln!(writer, "$enddefinitions $end")?;
+
+        let initial = values(cpu);
+        let time = cpu.time();
+        writeln!(writer, "#{}", time)?;
+        writeln!(writer, "$dumpvars")?;
+        for (index, &value) in initial.iter().enumerate() {
+            write_value(&mut writer, index, value)?;
+        }
+        writeln!(writer, "$end")?;
+
+        Ok(VcdWriter {
+            writer,
+            period: cpu.clock_period(),
+            values: initial.clone(),
+            pending: initial,
+            time,
+        })
+    }
+
+    /// Dumps the cycle the CPU just executed
+    pub fn sample(&mut self, cpu: &Cpu) -> io::Result<()> {
+        let start = cpu.cycles_to_time(cpu.cycles().saturating_sub(1));
+        let current = values(cpu);
+
+        let mut rising = std::mem::replace(&mut self.pending, current.clone());
+        rising[CLK] = 1;
+        rising[BUS_SIGNALS].copy_from_slice(&current[BUS_SIGNALS]);
+        self.dump(start, &rising)?;
+
+        let mut falling = rising;
+        falling[CLK] = 0;
+        self.dump(start + self.period / 2, &falling)
+    }
+
+    /// Dumps the registers after the last cycle and returns the writer
+    pub fn finish(mut self, cpu: &Cpu) -> io::Result<W> {
+        let mut last = self.pending.clone();
+        for index in BUS_SIGNALS {
+            last[index] = 0;
+        }
+        self.dump(cpu.time(), &last)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn dump(&mut self, time: u64, values: &[u64]) -> io::Result<()> {
+        let changed = (0..values.len())
+            .filter(|&index| values[index] != self.values[index])
+            .collect::<Vec<_>>();
+        if changed.is_empty() {
+            return Ok(());
+        }
+
+        if time > self.time {
+            writeln!(self.writer, "#{}", time)?;
+            self.time = time;
+        }
+        for index in changed {
+            write_value(&mut self.writer, index, values[index])?;
+            self.values[index] = values[index];
+        }
+        Ok(())
+    }
+}
+
+fn write_value<W: Write>(writer: &mut W, index: usize, value: u64) -> io::Result<()> {
+    if SIGNALS[index].1 == 1 {
+        writeln!(writer, "{}{}", value & 1, identifier(index))
+    } else {
+        writeln!(writer, "b{:b} {}", value, identifier(index))
+    }
+}
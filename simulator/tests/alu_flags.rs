@@ -0,0 +1,174 @@
+
+// The flag semantics of ALU.hdl, as modelled by `evaluate_alu` and stored by the CPU
+
+use simulator::{AluFunction, Cpu, evaluate_alu, FLAG_CARRY, FLAG_ZERO, FLAG_EQUAL, FLAG_LESS_THAN};
+
+fn scaled_operands() -> impl Iterator<Item = (u16, u16)> {
+    (0..=0xffu16).flat_map(|a| (0..=0xffu16).map(move |b| (a << 8 | a, b << 8 | b)))
+}
+
+#[test]
+fn add_carry_is_the_unsigned_overflow() {
+    let output = evaluate_alu(AluFunction::Add, 0xffff, 0x0001);
+    assert_eq!(output.result, 0x0000);
+    assert!(output.carry);
+    assert!(output.zero);
+
+    // There is no signed overflow flag
+    let output = evaluate_alu(AluFunction::Add, 0x7fff, 0x0001);
+    assert_eq!(output.result, 0x8000);
+    assert!(!output.carry);
+
+    for (a, b) in scaled_operands() {
+        assert_eq!(evaluate_alu(AluFunction::Add, a, b).carry, a as u32 + b as u32 > 0xffff);
+    }
+}
+
+#[test]
+fn subtract_carry_means_no_borrow() {
+    assert!(evaluate_alu(AluFunction::Subtract, 5, 3).carry);
+    assert!(evaluate_alu(AluFunction::Subtract, 5, 5).carry);
+    assert!(!evaluate_alu(AluFunction::Subtract, 3, 5).carry);
+    assert!(evaluate_alu(AluFunction::Subtract, 0, 0).carry);
+
+    for (a, b) in scaled_operands() {
+        let output = evaluate_alu(AluFunction::Subtract, a, b);
+        assert_eq!(output.result, a.wrapping_sub(b));
+        assert_eq!(output.carry, a >= b);
+    }
+}
+
+#[test]
+fn subtract_compares_unsigned() {
+    let output = evaluate_alu(AluFunction::Subtract, 3, 5);
+    assert!(output.less_than);
+    assert!(!output.equal);
+
+    // -1 < 1 as signed numbers, but not as unsigned ones
+    let output = evaluate_alu(AluFunction::Subtract, 0xffff, 0x0001);
+    assert!(!output.less_than);
+    assert!(!output.equal);
+
+    let output = evaluate_alu(AluFunction::Subtract, 5, 5);
+    assert!(!output.less_than);
+    assert!(output.equal);
+
+    for (a, b) in scaled_operands() {
+        let output = evaluate_alu(AluFunction::Subtract, a, b);
+        assert_eq!(output.equal, a == b);
+        assert_eq!(output.less_than, a < b);
+    }
+}
+
+#[test]
+fn equal_is_the_zero_flag() {
+    for function in 0..8 {
+        let function = AluFunction::from_code(function);
+        for (a, b) in scaled_operands() {
+            let output = evaluate_alu(function, a, b);
+            assert_eq!(output.zero, output.result == 0);
+            assert_eq!(output.equal, output.zero);
+        }
+    }
+}
+
+#[test]
+fn carry_comes_from_the_adder_for_every_function() {
+    // The adder always adds the operands, only subtraction changes what it computes
+    let output = evaluate_alu(AluFunction::And, 0xffff, 0x0001);
+    assert_eq!(output.result, 0x0001);
+    assert!(output.carry);
+
+    let output = evaluate_alu(AluFunction::ShiftLeft, 0x8000, 0x0001);
+    assert_eq!(output.result, 0x0000);
+    assert!(!output.carry);
+}
+
+#[test]
+fn less_than_is_neither_equal_nor_carry() {
+    // Only meaningful after subtraction, other functions combine their own zero flag with the carry of an addition
+    let output = evaluate_alu(AluFunction::And, 0x0001, 0x0001);
+    assert!(output.less_than);
+
+    let output = evaluate_alu(AluFunction::And, 0x0001, 0x0002);
+    assert!(!output.less_than);
+
+    for function in 0..8 {
+        let function = AluFunction::from_code(function);
+        for (a, b) in scaled_operands() {
+            let output = evaluate_alu(function, a, b);
+            assert_eq!(output.less_than, !(output.equal || output.carry));
+        }
+    }
+}
+
+#[test]
+fn shifts_only_use_the_low_four_bits_of_operand2() {
+    assert_eq!(evaluate_alu(AluFunction::ShiftLeft, 0x0001, 0x000f).result, 0x8000);
+    assert_eq!(evaluate_alu(AluFunction::ShiftLeft, 0x0001, 0x0010).result, 0x0001);
+    assert_eq!(evaluate_alu(AluFunction::ShiftRight, 0x8000, 0x001f).result, 0x0001);
+    assert_eq!(evaluate_alu(AluFunction::ShiftRight, 0x8000, 0xfff0).result, 0x8000);
+}
+
+fn run(source: &str) -> Cpu {
+    let program = assembler::assemble(source).unwrap();
+    let mut cpu = Cpu::with_program(&program.words).unwrap();
+    for _ in 0..program.words.len() {
+        cpu.step_instruction().unwrap();
+    }
+    cpu
+}
+
+#[test]
+fn cpu_stores_carry_only_for_add_and_subtract() {
+    let cpu = run("
+        ldi 0
+        not
+        mov B, A
+        ldi 1
+        add
+    ");
+    assert_eq!(cpu.registers.a, 0x0000);
+    assert!(cpu.registers.flag(FLAG_CARRY));
+    assert!(cpu.registers.flag(FLAG_ZERO));
+
+    // AND keeps the carry of the addition although the ALU computes a new one
+    let cpu = run("
+        ldi 0
+        not
+        mov B, A
+        ldi 1
+        add
+        ldi 2
+        and
+    ");
+    assert_eq!(cpu.registers.a, 0x0002);
+    assert!(cpu.registers.flag(FLAG_CARRY));
+    assert!(!cpu.registers.flag(FLAG_ZERO));
+}
+
+#[test]
+fn cpu_stores_equal_and_less_than_only_for_compare() {
+    let cpu = run("
+        ldi 5
+        mov B, A
+        ldi 3
+        cmp
+    ");
+    // The accumulator and the carry and zero flags are left alone
+    assert_eq!(cpu.registers.a, 3);
+    assert!(!cpu.registers.flag(FLAG_CARRY));
+    assert!(!cpu.registers.flag(FLAG_ZERO));
+    assert!(!cpu.registers.flag(FLAG_EQUAL));
+    assert!(cpu.registers.flag(FLAG_LESS_THAN));
+
+    let cpu = run("
+        ldi 0
+        cmpi 0
+        ldi 0
+        xori 0
+    ");
+    assert!(cpu.registers.flag(FLAG_EQUAL));
+    assert!(!cpu.registers.flag(FLAG_LESS_THAN));
+    assert!(cpu.registers.flag(FLAG_ZERO));
+}
@@ -0,0 +1,150 @@
+
+// Co-simulation against a stand-in for the gate-level CPU: its blocks only load constants, so every comparison can be
+// made to pass or fail on purpose
+
+use circuit::{CircuitFile, ComponentKind};
+use circuit::testing::{Builder, pin};
+use simulator::{Bus, Cpu, GPIO_OUT_ADDRESS, SRAM_BASE};
+use simulator::cosim::{CoSimulation, GateCpu};
+
+// Values the blocks of the stand-in load at every rising edge. `sram` is written at address 0 while the clock is high,
+// from the first rising edge on.
+#[derive(Default)]
+struct Constants {
+    a: u16,
+    addr: u16,
+    ir: u16,
+    pc: u16,
+    gpio: u16,
+    sram: Option<u16>,
+}
+
+// A block with a clock input `C` and inputs `D0` to `D15` that a 16-bit register loads at every rising edge
+fn block(builder: &mut Builder, name: &str) {
+    builder.edit(name);
+    let register = builder.add(ComponentKind::Register { width: 16 });
+    let one = builder.add(ComponentKind::Const { value: true });
+    builder.wire(&pin(one, "out"), &pin(register, "EN"));
+    let clock = builder.add(ComponentKind::Input { label: "C".to_string() });
+    builder.wire(&pin(clock, "out"), &pin(register, "C"));
+    for bit in 0..16 {
+        let input = builder.add(ComponentKind::Input { label: format!("D{}", bit) });
+        builder.wire(&pin(input, "out"), &pin(register, &format!("D{}", bit)));
+    }
+}
+
+fn stand_in(constants: &Constants) -> CircuitFile {
+    let mut builder = Builder::new();
+    for name in &["CPU", "Register", "StatusRegister", "ProgramCounter", "GPIO"] {
+        builder.add_circuit(name);
+    }
+    for name in &["Register", "StatusRegister", "ProgramCounter", "GPIO"] {
+        block(&mut builder, name);
+    }
+
+    // The CPU declares A, B, Addr, SP and IR in this order
+    builder.edit("CPU");
+    let clock = builder.add(ComponentKind::Input { label: "clk".to_string() });
+    let values = [constants.a, 0, constants.addr, 0, constants.ir];
+    let blocks = values.iter().map(|&value| ("Register", value))
+        .chain(vec![("StatusRegister", 0), ("ProgramCounter", constants.pc)]);
+    for (name, value) in blocks {
+        let block = builder.add(ComponentKind::Custom { circuit_name: name.to_string() });
+        builder.wire(&pin(clock, "out"), &pin(block, "C"));
+        builder.constant(block, "D", 16, value as u64);
+    }
+
+    builder.edit("main");
+    let clock = builder.add(ComponentKind::Clock { period: 20 });
+    builder.add(ComponentKind::Rom { address_width: 4, word_size: 16, contents: String::new() });
+    let cpu = builder.add(ComponentKind::Custom { circuit_name: "CPU".to_string() });
+    builder.wire(&pin(clock, "out"), &pin(cpu, "clk"));
+    let gpio = builder.add(ComponentKind::Custom { circuit_name: "GPIO".to_string() });
+    builder.wire(&pin(clock, "out"), &pin(gpio, "C"));
+    builder.constant(gpio, "D", 16, constants.gpio as u64);
+    let sram = builder.add(ComponentKind::Sram { address_width: 4, data_width: 16 });
+    if let Some(value) = constants.sram {
+        // `GateCpu::new` lets the circuit run for a while before the first instruction
+        let started = builder.add(ComponentKind::Register { width: 1 });
+        builder.constant(started, "D", 1, 1);
+        let one = builder.add(ComponentKind::Const { value: true });
+        builder.wire(&pin(one, "out"), &pin(started, "EN"));
+        builder.wire(&pin(clock, "out"), &pin(started, "C"));
+        let write_enable = builder.and(&[pin(clock, "out"), pin(started, "Q0")]);
+        builder.wire(&write_enable, &pin(sram, "WE"));
+        builder.constant(sram, "D", 16, value as u64);
+    }
+
+    builder.file
+}
+
+fn co_simulation(source: &str, constants: &Constants) -> CoSimulation {
+    let words = assembler::assemble(source).unwrap().words;
+    let gate = GateCpu::new(stand_in(constants), &words, &[]).unwrap();
+    CoSimulation::new(Cpu::new(Bus::testbench(&words).unwrap()), gate)
+}
+
+fn word(source: &str) -> u16 {
+    assembler::assemble(source).unwrap().words[0]
+}
+
+#[test]
+fn blocks_are_found_by_their_order() {
+    let co_simulation = co_simulation("ldi 5", &Constants::default());
+    let gate = &co_simulation.gate;
+    let paths = ["A", "B", "Addr", "SP", "IR", "SR", "PC", "GPIO"].iter()
+        .map(|name| gate.block_path(name).unwrap_or("none"))
+        .collect::<Vec<_>>();
+    assert_eq!(paths, [
+        "main.CPU2.Register1", "main.CPU2.Register2", "main.CPU2.Register3", "main.CPU2.Register4", "main.CPU2.Register5",
+        "main.CPU2.StatusRegister6", "main.CPU2.ProgramCounter7", "main.GPIO3",
+    ]);
+    assert_eq!(gate.block_path("cycle"), None);
+}
+
+#[test]
+fn registers_are_compared_after_every_instruction() {
+    let constants = Constants { a: 5, ir: word("ldi 5"), pc: 1, ..Constants::default() };
+    let mut co_simulation = co_simulation("ldi 5\nldi 5", &constants);
+    assert!(co_simulation.step().unwrap().is_none());
+
+    // The stand-in keeps the PC at 1
+    let mismatch = co_simulation.step().unwrap().unwrap();
+    assert_eq!(mismatch.differences, ["PC is 0x0001 instead of 0x0002"]);
+    assert_eq!((mismatch.instruction, mismatch.pc, mismatch.word), (1, 1, word("ldi 5")));
+    assert_eq!((mismatch.isa.pc, mismatch.gate.pc), (2, 1));
+    assert_eq!(mismatch.trace.len(), 2);
+    assert_eq!(mismatch.to_string(), format!("instruction 1 (0x{:04x} at 0x0001): PC is 0x0001 instead of 0x0002", word("ldi 5")));
+}
+
+#[test]
+fn stores_are_compared_with_the_sram() {
+    let store = |stored: u16| {
+        let constants = Constants { a: 0x1234, addr: SRAM_BASE, ir: word("st"), pc: 1, sram: Some(stored), ..Constants::default() };
+        let mut co_simulation = co_simulation("st", &constants);
+        co_simulation.cpu.registers.a = 0x1234;
+        co_simulation.cpu.registers.addr = SRAM_BASE;
+        co_simulation.step().unwrap()
+    };
+
+    assert!(store(0x1234).is_none());
+    let mismatch = store(0x4321).unwrap();
+    assert_eq!(mismatch.differences, ["[0400] is 0x4321 instead of 0x1234"]);
+}
+
+#[test]
+fn stores_are_compared_with_the_gpio_register() {
+    let constants = Constants { a: 0xff, addr: GPIO_OUT_ADDRESS, ir: word("st"), pc: 1, gpio: 0xff, ..Constants::default() };
+    let mut co_simulation = co_simulation("st", &constants);
+    co_simulation.cpu.registers.a = 0xff;
+    co_simulation.cpu.registers.addr = GPIO_OUT_ADDRESS;
+    assert!(co_simulation.step().unwrap().is_none());
+}
+
+#[test]
+fn writes_without_a_store_are_reported() {
+    let constants = Constants { ir: word("ldi 0"), pc: 1, gpio: 0xff, sram: Some(7), ..Constants::default() };
+    let mut co_simulation = co_simulation("ldi 0", &constants);
+    let mismatch = co_simulation.step().unwrap().unwrap();
+    assert_eq!(mismatch.differences, ["unexpected write [0400]<-0007", "unexpected GPIO write 0x00ff"]);
+}
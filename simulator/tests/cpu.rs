@@ -0,0 +1,136 @@
+
+// Semantics of the instructions, checked with small assembled programs
+
+use simulator::{BusError, Cpu, DecodeMode, Phase, SimulatorError, TimingMode, FLAG_ZERO, FLAG_EQUAL, FLAG_LESS_THAN, ROM_SIZE, SRAM_BASE};
+
+fn cpu(source: &str) -> Cpu {
+    Cpu::with_program(&assembler::assemble(source).unwrap().words).unwrap()
+}
+
+fn run(source: &str, instructions: usize) -> Cpu {
+    let mut cpu = cpu(source);
+    for _ in 0..instructions {
+        cpu.step().unwrap();
+    }
+    cpu
+}
+
+#[test]
+fn mov_from_pc_reads_the_address_of_the_instruction() {
+    let cpu = run("
+        ldi 0
+        ldi 0
+        mov B, PC
+    ", 3);
+    assert_eq!(cpu.registers.b, 2);
+    assert_eq!(cpu.registers.pc, 3);
+}
+
+#[test]
+fn mov_to_pc_jumps() {
+    let cpu = run("
+        ldi 7
+        mov PC, A
+    ", 2);
+    assert_eq!(cpu.registers.pc, 7);
+}
+
+#[test]
+fn jump_conditions_check_the_status_register() {
+    // Whether each jump is taken with no flag, Z, EQ or LT set
+    let jumps = [
+        ("jmp", [true, true, true, true]),
+        ("jmp.z", [false, true, false, false]),
+        ("jmp.eq", [false, false, true, false]),
+        ("jmp.ne", [true, true, false, true]),
+        ("jmp.lt", [false, false, false, true]),
+        ("jmp.le", [false, false, true, true]),
+        ("jmp.gt", [true, true, false, false]),
+        ("jmp.ge", [true, true, true, false]),
+    ];
+    let flags = [0, FLAG_ZERO, FLAG_EQUAL, FLAG_LESS_THAN];
+
+    for (jump, taken) in &jumps {
+        for (flag, taken) in flags.iter().zip(taken) {
+            let mut cpu = cpu(&format!("{} B", jump));
+            cpu.registers.b = 0x10;
+            cpu.registers.sr = *flag;
+            cpu.step().unwrap();
+            assert_eq!(cpu.registers.pc, if *taken { 0x10 } else { 1 }, "{} with SR 0x{:x}", jump, flag);
+            assert_eq!(cpu.registers.sr, *flag);
+        }
+    }
+}
+
+#[test]
+fn load_and_store_address_through_addr() {
+    let cpu = run(&format!("
+        ldi 0x{:x}
+        mov Addr, A
+        ldi 42
+        st
+        ldi 0
+        ld
+    ", SRAM_BASE + 5), 6);
+    assert_eq!(cpu.registers.addr, SRAM_BASE + 5);
+    assert_eq!(cpu.registers.a, 42);
+    assert_eq!(cpu.bus.peek(SRAM_BASE + 5), 42);
+    assert_eq!(cpu.bus.peek(SRAM_BASE + 4), 0);
+}
+
+#[test]
+fn strict_mode_stops_at_unused_opcodes() {
+    // Opcode 0x0d
+    let mut cpu = Cpu::with_program(&[0x6800]).unwrap();
+    match cpu.step() {
+        Err(SimulatorError::IllegalInstruction(0, 0x6800)) => {},
+        result => panic!("unexpected result {:?}", result),
+    }
+    assert_eq!(cpu.registers.pc, 0);
+    assert_eq!(cpu.instructions(), 0);
+}
+
+#[test]
+fn hardware_mode_only_increments_the_pc_for_unused_opcodes() {
+    // Opcodes 0x0d to 0x0f
+    let mut cpu = Cpu::with_program(&[0x6800, 0x7000, 0x7800]).unwrap();
+    cpu.set_decode_mode(DecodeMode::Hardware);
+    cpu.registers.a = 3;
+    for _ in 0..3 {
+        cpu.step().unwrap();
+    }
+    assert_eq!(cpu.registers.pc, 3);
+    assert_eq!(cpu.registers.a, 3);
+    assert_eq!(cpu.instructions(), 3);
+}
+
+#[test]
+fn cycle_timing_steps_fetch_and_execute() {
+    let mut cpu = cpu("ldi 7");
+    cpu.set_timing_mode(TimingMode::Cycle);
+    let word = cpu.bus.peek(0);
+
+    cpu.step().unwrap();
+    assert_eq!(cpu.phase(), Phase::Execute);
+    assert_eq!(cpu.ir(), word);
+    assert_eq!((cpu.registers.a, cpu.registers.pc), (0, 0));
+    assert_eq!((cpu.cycles(), cpu.instructions()), (1, 0));
+
+    cpu.step().unwrap();
+    assert_eq!(cpu.phase(), Phase::Fetch);
+    assert_eq!((cpu.registers.a, cpu.registers.pc), (7, 1));
+    assert_eq!((cpu.cycles(), cpu.instructions()), (2, 1));
+}
+
+#[test]
+fn programs_have_to_fit_into_the_rom() {
+    let cpu = Cpu::with_program(&vec![0x1234; ROM_SIZE]).unwrap();
+    assert_eq!(cpu.bus.peek(ROM_SIZE as u16 - 1), 0x1234);
+
+    match Cpu::with_program(&vec![0; ROM_SIZE + 1]) {
+        Err(error @ BusError::ProgramTooLarge(_)) => {
+            assert_eq!(error.to_string(), "program of 1025 words doesn't fit into the ROM of 1024 words");
+        },
+        result => panic!("unexpected result {:?}", result.map(|cpu| cpu.registers)),
+    }
+}
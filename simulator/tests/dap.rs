@@ -0,0 +1,75 @@
+
+// Requests to the debug adapter, sent over an in-memory stream
+
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+fn encode(requests: &[Value]) -> Vec<u8> {
+    let mut input = Vec::new();
+    for (seq, request) in requests.iter().enumerate() {
+        let mut request = request.clone();
+        request["seq"] = json!(seq + 1);
+        request["type"] = json!("request");
+        let content = request.to_string();
+        input.extend(format!("Content-Length: {}\r\n\r\n{}", content.len(), content).bytes());
+    }
+    input
+}
+
+fn decode(mut output: &str) -> Vec<Value> {
+    let mut messages = Vec::new();
+    while let Some(header_end) = output.find("\r\n\r\n") {
+        let length = output[..header_end].trim_start_matches("Content-Length: ").parse::<usize>().unwrap();
+        let content = &output[header_end + 4..header_end + 4 + length];
+        messages.push(serde_json::from_str(content).unwrap());
+        output = &output[header_end + 4 + length..];
+    }
+    messages
+}
+
+fn run(requests: &[Value]) -> Vec<Value> {
+    let mut output = Vec::new();
+    simulator::dap::serve(Cursor::new(encode(requests)), &mut output).unwrap();
+    decode(&String::from_utf8(output).unwrap())
+}
+
+fn response<'a>(messages: &'a [Value], command: &str) -> Vec<&'a Value> {
+    messages.iter().filter(|message| message["type"] == "response" && message["command"] == command).collect()
+}
+
+#[test]
+fn breakpoints_belong_to_their_source_and_step_out_is_refused() {
+    let program = fs::canonicalize(Path::new(env!("CARGO_MANIFEST_DIR")).join("../assembler/examples/subroutines.asm")).unwrap();
+    let source = fs::read_to_string(&program).unwrap();
+    // The first instruction of the subroutine
+    let line = source.lines().position(|line| line.trim() == "subroutine:").unwrap() + 3;
+
+    let messages = run(&[
+        json!({ "command": "initialize", "arguments": {} }),
+        json!({ "command": "launch", "arguments": { "program": program } }),
+        json!({ "command": "setBreakpoints", "arguments": { "source": { "path": program }, "breakpoints": [{ "line": line }] } }),
+        json!({ "command": "setBreakpoints", "arguments": { "source": { "path": "other.asm" }, "breakpoints": [{ "line": 3 }] } }),
+        json!({ "command": "configurationDone", "arguments": {} }),
+        json!({ "command": "stepOut", "arguments": { "threadId": 1 } }),
+        json!({ "command": "stackTrace", "arguments": { "threadId": 1 } }),
+        json!({ "command": "disconnect", "arguments": {} }),
+    ]);
+
+    let set_breakpoints = response(&messages, "setBreakpoints");
+    assert_eq!(set_breakpoints[0]["body"]["breakpoints"][0]["verified"], true);
+    assert_eq!(set_breakpoints[0]["body"]["breakpoints"][0]["line"], line);
+    assert_eq!(set_breakpoints[1]["body"]["breakpoints"][0]["verified"], false);
+
+    // The breakpoint in the program is still there
+    let stopped = messages.iter().find(|message| message["event"] == "stopped").unwrap();
+    assert_eq!(stopped["body"]["reason"], "breakpoint");
+
+    let step_out = response(&messages, "stepOut");
+    assert_eq!(step_out[0]["success"], false);
+    assert_eq!(messages.iter().filter(|message| message["event"] == "stopped").count(), 1);
+    let frame = &response(&messages, "stackTrace")[0]["body"]["stackFrames"][0];
+    assert_eq!((&frame["name"], &frame["line"]), (&json!("subroutine"), &json!(line)));
+}
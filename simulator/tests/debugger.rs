@@ -0,0 +1,73 @@
+
+// Stepping over jumps, and watchpoints and reverse execution with the decode modes that execute undocumented encodings
+
+use simulator::{Bus, Cpu, DecodeMode, Session, StopReason, Watchpoint, WatchKind};
+use simulator::peripherals::GpioOut;
+
+// Stores 0x55 at 0x400 with `st` encoded with the immediate bit set (opcode 0x12), which the hardware executes like `st`
+fn aliased_store(decode_mode: DecodeMode) -> Session {
+    let program = assembler::assemble("ldi 0x0400\nmov Addr, A\nldi 0x55\nst\nloop:\nldi loop\njmp A\n").unwrap();
+    let mut words = program.words.clone();
+    assert_eq!(words[3], 0x1000);
+    words[3] = 0x9000;
+
+    let mut cpu = Cpu::new(Bus::testbench(&words).unwrap());
+    cpu.set_decode_mode(decode_mode);
+    Session::new(cpu, program, None)
+}
+
+#[test]
+fn aliased_store_hits_watchpoints_and_is_undone() {
+    for &decode_mode in &[DecodeMode::Hardware, DecodeMode::Signals] {
+        let mut session = aliased_store(decode_mode);
+        let watchpoint = Watchpoint { kind: WatchKind::Write, address: 0x400 };
+        session.add_watchpoint(watchpoint);
+
+        let reason = session.continue_execution();
+        assert!(matches!(reason, StopReason::Watchpoint(hit) if hit == watchpoint), "{:?}: {:?}", decode_mode, reason);
+        assert_eq!(session.cpu.bus.peek(0x400), 0x55);
+        session.step_back();
+        assert_eq!(session.cpu.bus.peek(0x400), 0, "{:?}", decode_mode);
+    }
+}
+
+fn session(source: &str) -> Session {
+    let program = assembler::assemble(source).unwrap();
+    Session::new(Cpu::with_program(&program.words).unwrap(), program, Some(source))
+}
+
+#[test]
+fn step_over_runs_subroutines_to_the_return_address() {
+    let mut session = session(include_str!("../../assembler/examples/subroutines.asm"));
+    let call = session.program.labels["return"] - 1;
+    while session.cpu.registers.pc != call {
+        assert!(matches!(session.step(), StopReason::Step));
+    }
+
+    assert!(matches!(session.step_over(), StopReason::Step));
+    assert_eq!(session.cpu.registers.pc, call + 1);
+    // The subroutine has output both values
+    let outputs = session.cpu.bus.device::<GpioOut>().unwrap().history().iter().map(|&(_, value)| value).collect::<Vec<_>>();
+    assert_eq!(outputs, [0x0f0f, 0xf0f0]);
+}
+
+#[test]
+fn step_over_stops_at_jumps_that_dont_come_back() {
+    let mut session = session("
+        ldi loop
+        mov B, A
+        loop:
+        ldi 0
+        jmp B
+        ldi 0
+    ");
+    for _ in 0..3 {
+        session.step();
+    }
+
+    // The loop never gets to the instruction after the jump
+    assert_eq!(session.cpu.registers.pc, 3);
+    assert!(matches!(session.step_over(), StopReason::Step));
+    assert_eq!(session.cpu.registers.pc, 2);
+    assert_eq!(session.cpu.instructions(), 4);
+}
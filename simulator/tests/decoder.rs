@@ -0,0 +1,49 @@
+
+// The model of InstructionDecoder.hdl against the signals the ISA asks for, for every instruction word
+
+use assembler::Register;
+use simulator::{ControlSignals, Instruction, Operation};
+
+// Names of the outputs that differ
+fn differences(expected: &ControlSignals, actual: &ControlSignals) -> Vec<&'static str> {
+    expected.outputs().iter()
+        .zip(actual.outputs().iter())
+        .filter(|(expected, actual)| expected.1 != actual.1)
+        .map(|(expected, _)| expected.0)
+        .collect()
+}
+
+#[test]
+fn model_matches_reference_for_every_encoding_of_the_isa() {
+    let mut checked = 0;
+    let mut disagreements = Vec::new();
+    for word in 0..=0xffffu16 {
+        if let Some(instruction) = Instruction::decode(word) {
+            checked += 1;
+            let differences = differences(&ControlSignals::reference(instruction, word), &ControlSignals::decode(word));
+            if !differences.is_empty() {
+                disagreements.push(format!("0x{:04x} {}: {}", word, instruction, differences.join(", ")));
+            }
+        }
+    }
+
+    assert!(checked > 30000);
+    assert!(disagreements.is_empty(), "{} disagreeing words:\n{}", disagreements.len(), disagreements.join("\n"));
+}
+
+#[test]
+fn model_matches_operations_for_every_word() {
+    // The signals of undocumented encodings have no reference, but they have to access memory and load the PC like
+    // `Operation` says
+    for word in 0..=0xffffu16 {
+        let signals = ControlSignals::decode(word);
+        let operation = Operation::decode(word);
+        let loads_pc = matches!(operation,
+            Operation::Instruction(Instruction::Jmp(..)) | Operation::Instruction(Instruction::Mov(Register::PC, _))
+            | Operation::Jmp(..) | Operation::Mov(Some(Register::PC), _));
+
+        assert_eq!(signals.mem_read, operation == Operation::Instruction(Instruction::Ld), "mem_read of 0x{:04x} ({})", word, operation);
+        assert_eq!(signals.mem_write, operation == Operation::Instruction(Instruction::St), "mem_write of 0x{:04x} ({})", word, operation);
+        assert_eq!(signals.pc_in, loads_pc, "pc_in of 0x{:04x} ({})", word, operation);
+    }
+}
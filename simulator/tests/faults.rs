@@ -0,0 +1,109 @@
+
+// Every kind of fault, each provoked by a program of its own
+
+use simulator::{AccessKind, Cpu, DecodeMode};
+use simulator::faults::{Fault, FaultDetector, FaultKind};
+
+// Runs `instructions` instructions of `source` with the decoder of the hardware, so that any word can be executed
+fn run(source: &str, instructions: usize, stack: Option<std::ops::Range<u16>>) -> Vec<Fault> {
+    let program = assembler::assemble(source).unwrap();
+    let mut cpu = Cpu::with_program(&program.words).unwrap();
+    cpu.set_decode_mode(DecodeMode::Hardware);
+    let mut detector = FaultDetector::new(&cpu, &program);
+    if let Some(stack) = stack {
+        detector.set_stack_region(stack);
+    }
+    for _ in 0..instructions * 2 {
+        cpu.step_cycle().unwrap();
+        detector.sample(&cpu);
+    }
+    detector.faults().to_vec()
+}
+
+fn kinds(faults: &[Fault]) -> Vec<(FaultKind, u16)> {
+    faults.iter().map(|fault| (fault.kind, fault.pc)).collect()
+}
+
+#[test]
+fn store_to_rom() {
+    let faults = run("
+        ldi 5
+        mov Addr, A
+        st
+    ", 3, None);
+    assert_eq!(kinds(&faults), [(FaultKind::RomWrite(5), 2)]);
+    assert_eq!(faults[0].line, Some(4));
+    // The cycle the instruction was fetched in
+    assert_eq!(faults[0].cycle, 4);
+    // Up to and including the store
+    assert_eq!(faults[0].trace.len(), 3);
+    assert_eq!(faults[0].to_string(), "cycle 4, PC 0x0002 (line 4): store to ROM address 0x0005");
+}
+
+#[test]
+fn access_to_unmapped_addresses() {
+    let faults = run("
+        ldi 0x700
+        mov Addr, A
+        ld
+        st
+    ", 4, None);
+    assert_eq!(kinds(&faults), [
+        (FaultKind::Unmapped(AccessKind::Read, 0x700), 2),
+        (FaultKind::Unmapped(AccessKind::Write, 0x700), 3),
+    ]);
+}
+
+#[test]
+fn read_of_uninitialized_sram() {
+    let faults = run("
+        ldi 0x405
+        mov Addr, A
+        ld
+        st
+        ld
+    ", 5, None);
+    // Once written, the word can be read
+    assert_eq!(kinds(&faults), [(FaultKind::UninitializedRead(0x405), 2)]);
+}
+
+#[test]
+fn jump_into_data() {
+    let faults = run("
+        ldi data
+        jmp A
+        data:
+        .word 0
+    ", 3, None);
+    assert_eq!(kinds(&faults), [(FaultKind::JumpIntoData(2), 1)]);
+}
+
+#[test]
+fn opcodes_the_assembler_never_emits() {
+    // Opcode 0x16 is decoded as not, 0x1c as jmp A
+    let faults = run("
+        .word 0xb000
+        .word 0xe000
+    ", 2, None);
+    assert_eq!(kinds(&faults), [(FaultKind::AliasedOpcode(0xb000), 0), (FaultKind::AliasedOpcode(0xe000), 1)]);
+
+    // Opcode 0x0d only increments the PC
+    let faults = run("
+        .word 0x6800
+    ", 1, None);
+    assert_eq!(kinds(&faults), [(FaultKind::Undecodable(0x6800), 0)]);
+}
+
+#[test]
+fn stack_pointer_leaving_its_region() {
+    // SP starts outside of the region, that isn't a fault
+    let faults = run("
+        ldi 0x500
+        mov SP, A
+        ldi 0x5ff
+        mov SP, A
+        ldi 0x300
+        mov SP, A
+    ", 6, Some(0x480..0x600));
+    assert_eq!(kinds(&faults), [(FaultKind::StackOutOfRange(0x300), 5)]);
+}
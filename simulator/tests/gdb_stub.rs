@@ -0,0 +1,128 @@
+
+// A scripted GDB remote protocol client against the stub of the simulator, running subroutines.asm
+
+use std::{fs, thread};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+
+use assembler::Program;
+use simulator::{Cpu, Bus, Session};
+
+struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    fn request(&mut self, packet: &str) -> String {
+        let checksum = packet.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(self.stream, "${}#{:02x}", packet, checksum).unwrap();
+
+        // Skip the acknowledgement and read the reply up to its checksum
+        let mut reply = Vec::new();
+        let mut byte = [0];
+        loop {
+            self.stream.read_exact(&mut byte).unwrap();
+            match byte[0] {
+                b'+' if reply.is_empty() => {},
+                b'#' => break,
+                byte => reply.push(byte),
+            }
+        }
+        let mut checksum = [0; 2];
+        self.stream.read_exact(&mut checksum).unwrap();
+        self.stream.write_all(b"+").unwrap();
+
+        String::from_utf8(reply[1..].to_vec()).unwrap()
+    }
+
+    fn expect(&mut self, packet: &str, expected: &str) {
+        let reply = self.request(packet);
+        assert_eq!(reply, expected, "unexpected reply to {}", packet);
+    }
+}
+
+// Starts the stub in a background thread and connects to it
+fn connect() -> (Client, Program) {
+    let source_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../assembler/examples/subroutines.asm");
+    let source = fs::read_to_string(&source_path).unwrap();
+    let program = assembler::assemble(&source).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let served = program.clone();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let cpu = Cpu::new(Bus::testbench(&served.words).unwrap());
+        let mut session = Session::new(cpu, served, Some(&source));
+        simulator::gdb::serve(&mut session, stream).unwrap();
+    });
+
+    let client = Client {
+        stream: TcpStream::connect(address).unwrap(),
+    };
+    (client, program)
+}
+
+#[test]
+fn scripted_session() {
+    let (mut client, program) = connect();
+    let subroutine = program.labels["subroutine"];
+
+    let supported = client.request("qSupported:swbreak+");
+    assert!(supported.contains("qXfer:features:read+"));
+
+    let target_xml = client.request("qXfer:features:read:target.xml:0,1000");
+    assert!(target_xml.starts_with('l') && target_xml.contains("name=\"pc\""));
+
+    client.expect("?", "S05");
+    client.expect("g", "0000000000000000000000000000");
+
+    // Run to the subroutine, the stack then holds 0xf0f0 and 0x0f0f
+    client.expect(&format!("Z0,{:x},2", subroutine as u32 * 2), "OK");
+    client.expect("c", "T05swbreak:;");
+    client.expect("p5", &format!("{:08x}", (subroutine as u32 * 2).swap_bytes()));
+    client.expect("p3", "0104");
+    client.expect("m800,4", "f0f00f0f");
+    client.expect(&format!("z0,{:x},2", subroutine as u32 * 2), "OK");
+
+    // Single-step over `mov Addr, SP`
+    client.expect("s", "S05");
+    client.expect("p2", "0104");
+
+    // Stop at the first output on the GPIO port
+    client.expect("Z2,c00,2", "OK");
+    client.expect("c", "T05watch:c00;");
+    client.expect("p0", "0f0f");
+    client.expect("z2,c00,2", "OK");
+
+    // Modify a register and the SRAM
+    client.expect("P1=3412", "OK");
+    client.expect("p1", "3412");
+    client.expect("M810,2:cdab", "OK");
+    client.expect("m810,2", "cdab");
+
+    client.expect("D", "OK");
+}
+
+#[test]
+fn out_of_range_requests() {
+    let (mut client, _) = connect();
+
+    // Ranges whose end overflows or lies beyond the last word
+    client.expect("mfffffffe,4", "E01");
+    client.expect("m1fffe,4", "E01");
+    client.expect("Mfffffffe,4:00000000", "E01");
+    client.expect("M1ffff,2:0000", "E01");
+    client.expect("Z2,fffffffe,4", "E01");
+    // The last word is in range, nothing is mapped there
+    client.expect("m1fffe,2", "0000");
+
+    // The PC is a byte address of up to 17 bits
+    client.expect("P5=00000100", "OK");
+    client.expect("p5", "00000100");
+    client.expect("P5=00000200", "E01");
+    client.expect("p5", "00000100");
+
+    client.expect("D", "OK");
+}
@@ -0,0 +1,112 @@
+
+// Parsing stimulus scripts and playing them back against a running program
+
+use simulator::{Bus, Cpu, UART_BASE};
+use simulator::peripherals::{Buttons, UART_DATA, UART_STATUS, UART_STATUS_RX_READY};
+use simulator::stimulus::{Action, Event, Stimulus, StimulusError, Trigger};
+
+// Each iteration takes 6 cycles, `loop` is fetched in cycles 0, 6, 12 and so on
+const LOOP: &str = "
+loop:
+    ld
+    ldi loop
+    jmp A
+";
+
+fn parse(source: &str) -> Result<Stimulus, StimulusError> {
+    Stimulus::parse(source, &assembler::assemble(LOOP).unwrap())
+}
+
+fn actions(source: &str) -> Vec<Action> {
+    parse(source).unwrap().events().iter().map(|event| event.action.clone()).collect()
+}
+
+#[test]
+fn triggers_and_comments() {
+    let stimulus = parse("
+        # Comment lines and empty lines are skipped
+
+        1000      buttons 0x03
+        +20       press 7   # a trailing comment
+        loop      release 0
+        loop#3    stop
+    ").unwrap();
+    assert_eq!(stimulus.events(), [
+        Event { line: 4, trigger: Trigger::Cycle(1000), action: Action::Buttons(3) },
+        Event { line: 5, trigger: Trigger::After(20), action: Action::Press(7) },
+        Event { line: 6, trigger: Trigger::Label(0, 1), action: Action::Release(0) },
+        Event { line: 7, trigger: Trigger::Label(0, 3), action: Action::Stop },
+    ]);
+}
+
+#[test]
+fn uart_strings_are_unescaped() {
+    assert_eq!(actions(r#"0 uart "a\n\r\t\0\\\"\x41""#), [Action::Uart(b"a\n\r\t\0\\\"A".to_vec())]);
+    // A `#` inside a string doesn't start a comment
+    assert_eq!(actions(r#"0 uart "x #1" # sent"#), [Action::Uart(b"x #1".to_vec())]);
+    assert_eq!(actions("0 uart 0x41"), [Action::Uart(vec![0x41])]);
+
+    for invalid in &[r#"0 uart "\q""#, r#"0 uart "open"#, r#"0 uart "\x4""#, "0 uart 0x100"] {
+        match parse(invalid) {
+            Err(StimulusError::Syntax(1, _)) => {},
+            result => panic!("unexpected result {:?} for {}", result, invalid),
+        }
+    }
+}
+
+#[test]
+fn errors_name_the_line() {
+    let error = parse("0 stop\nnowhere press 0").unwrap_err();
+    assert!(matches!(&error, StimulusError::UnknownLabel(2, label) if label == "nowhere"));
+    assert_eq!(error.to_string(), "line 2: unknown label \"nowhere\"");
+
+    let error = parse("\n0 jump 3").unwrap_err();
+    assert_eq!(error.to_string(), "line 2: unknown action \"jump 3\"");
+
+    for invalid in &["loop#0 stop", "+x stop", "10", "0 press 8", "0 buttons 0x100", "0 stop now"] {
+        match parse(invalid) {
+            Err(StimulusError::Syntax(1, _)) => {},
+            result => panic!("unexpected result {:?} for {}", result, invalid),
+        }
+    }
+}
+
+#[test]
+fn events_fire_in_order() {
+    let source = "
+        loop#3    press 0
+        +4        release 0
+        # Already due, fires right after the previous event
+        10        buttons 0x80
+        30        uart \"hi\"
+        loop      stop
+    ";
+    let mut stimulus = parse(source).unwrap();
+    let mut cpu = Cpu::new(Bus::extended(&assembler::assemble(LOOP).unwrap().words).unwrap());
+
+    let mut states = Vec::new();
+    while !stimulus.apply(&mut cpu).unwrap() {
+        states.push(cpu.bus.device_mut::<Buttons>().unwrap().state());
+        cpu.step_cycle().unwrap();
+        assert!(cpu.cycles() < 100);
+    }
+
+    // `loop` is fetched for the first time after cycle 30 in cycle 36
+    assert_eq!(cpu.cycles(), 36);
+    assert!(stimulus.is_finished());
+    assert_eq!(stimulus.fired(), [(12, 2), (16, 3), (16, 5), (30, 6), (36, 7)]);
+    assert_eq!(states[11..17], [0x00, 0x01, 0x01, 0x01, 0x01, 0x80]);
+
+    assert_eq!(cpu.bus.peek(UART_BASE + UART_STATUS) & UART_STATUS_RX_READY, UART_STATUS_RX_READY);
+    assert_eq!(cpu.bus.read(UART_BASE + UART_DATA), b'h' as u16);
+    assert_eq!(cpu.bus.read(UART_BASE + UART_DATA), b'i' as u16);
+    assert_eq!(cpu.bus.peek(UART_BASE + UART_STATUS) & UART_STATUS_RX_READY, 0);
+}
+
+#[test]
+fn actions_need_their_device() {
+    let mut stimulus = parse("0 uart 0x41").unwrap();
+    let mut cpu = Cpu::with_program(&assembler::assemble(LOOP).unwrap().words).unwrap();
+    let error = stimulus.apply(&mut cpu).unwrap_err();
+    assert_eq!(error.to_string(), "line 1: no UART is mapped");
+}